@@ -18,7 +18,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut cbd: HashCabide<Data> = HashCabide::new("alunos_head.db", Box::new(hash_fn))?;
 
     // Edit function passed to filter to change select condition
-    let results = cbd.remove_with(|student| student.data_inicio == "2018-06-16");
+    let results = cbd.remove_with(|student| student.data_inicio == "2018-06-16")?;
 
     for result in results.iter() {
         println!("Found DRE:{} NAME:{}", result.dre, result.nome);
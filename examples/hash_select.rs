@@ -18,7 +18,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut cbd: HashCabide<Data> = HashCabide::new("alunos_head.db", Box::new(hash_fn))?;
 
     // Change read() arg to desired id
-    let result = &cbd.read(('A' as u8, 0));
+    let result = &cbd.read((b'A', 0));
 
     match result {
         Ok(_v) => println!("Found {} from {}", _v.nome, _v.data_inicio),
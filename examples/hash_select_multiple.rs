@@ -18,7 +18,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut cbd: HashCabide<Data> = HashCabide::new("alunos_head.db", Box::new(hash_fn))?;
 
     // Change block id and initial letter
-    let ids: [(u8, u64); 2] = [('A' as u8, 0), ('B' as u8, 0)];
+    let ids: [(u8, u64); 2] = [(b'A', 0), (b'B', 0)];
 
     for id in ids.iter() {
         let result = &cbd.read(*id);
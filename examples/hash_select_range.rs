@@ -17,7 +17,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut cbd: HashCabide<Data> = HashCabide::new("alunos_head.db", Box::new(hash_fn))?;
 
-    let bucket = 'A' as u8;
+    let bucket = b'A';
 
     // Change block id and initial letter
     let id_range: [u64; 2] = [0,2];
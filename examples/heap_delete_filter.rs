@@ -15,7 +15,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut cbd: Cabide<Data> = Cabide::new("gh_head.db", None)?;
 
     // Edit function passed to filter to change delete condition
-    let results = cbd.remove_with(|student| student.estagio == "2017-01-01");
+    let results = cbd.remove_with(|student| student.estagio == "2017-01-01")?;
 
     for result in results.iter() {
         println!("Found {} from {}", result.uhe, result.estagio);
@@ -26,7 +26,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             estagio: String::from("2017-08-01"),
             geracao: rand::random::<f64>(),
         };
-        &cbd.write(&_entry);
+        cbd.write(&_entry)?;
     }
 
     println!();
@@ -1,7 +1,5 @@
 use cabide::Cabide;
-use cabide::READ_BLOCKS_COUNT;
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::Ordering;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 struct Data {
@@ -15,16 +13,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut cbd: Cabide<Data> = Cabide::new("gh_head.db", None)?;
 
     // Change read() arg to desired id
-    let result = &cbd.read(0);
+    let result = &cbd.read_counted(0);
 
-    match result {
-        Ok(_v) => println!("Found {} from {}", _v.uhe, _v.estagio),
-        Err(_e) => println!("Found nothing"),
-    }
+    let read_blocks = match result {
+        Ok((_v, blocks)) => {
+            println!("Found {} from {}", _v.uhe, _v.estagio);
+            *blocks
+        }
+        Err(_e) => {
+            println!("Found nothing");
+            0
+        }
+    };
 
     println!();
     println!("Used blocks: {}", cbd.blocks()?);
-    println!("Read blocks: {}", READ_BLOCKS_COUNT.load(Ordering::Relaxed));
+    println!("Read blocks: {}", read_blocks);
 
     Ok(())
 }
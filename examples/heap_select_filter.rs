@@ -1,7 +1,5 @@
 use cabide::Cabide;
-use cabide::READ_BLOCKS_COUNT;
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::Ordering;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 struct Data {
@@ -15,7 +13,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut cbd: Cabide<Data> = Cabide::new("gh_head.db", None)?;
 
     // Edit function passed to filter to change select condition
-    let results = cbd.filter(|student| student.estagio == "2017-01-01");
+    let (results, read_blocks) = cbd.filter_counted(|student| student.estagio == "2017-01-01");
 
     for result in results.iter() {
         println!("Found {} from {}", result.uhe, result.estagio);
@@ -23,7 +21,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!();
     println!("Used blocks: {}", cbd.blocks()?);
-    println!("Read blocks: {}", READ_BLOCKS_COUNT.load(Ordering::Relaxed));
+    println!("Read blocks: {}", read_blocks);
 
     Ok(())
 }
@@ -28,7 +28,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     )?;
 
 
-    let result = &cbd.remove(|entry| entry.dre == "143670124");
+    let _result = &cbd.remove(|entry| entry.dre == "143670124")?;
 
     println!();
     println!("Used blocks: {}", cbd.blocks()?);
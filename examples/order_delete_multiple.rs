@@ -28,7 +28,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     )?;
 
 
-    let result = &cbd.remove(|entry| entry.nome.starts_with('X'));
+    let result = &cbd.remove(|entry| entry.nome.starts_with('X'))?;
 
     println!("Found {} entries", result.len());
     println!();
@@ -51,7 +51,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             cr: (rand::random::<f32>() * 100.).round() / 10.,
         };
 
-        &cbd.write(&_entry);
+        cbd.write(&_entry)?;
     }
 
 
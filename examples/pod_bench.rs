@@ -0,0 +1,58 @@
+use bytemuck::{Pod, Zeroable};
+use cabide::{Cabide, PodCabide};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+#[repr(C)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+struct Reading {
+    sensor: u64,
+    timestamp: u64,
+    value: f64,
+}
+
+const RECORDS: u64 = 100_000;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let (pod_file, bincode_file) = ("readings_pod.db", "readings_bincode.db");
+    std::fs::File::create(pod_file)?;
+    std::fs::File::create(bincode_file)?;
+
+    let mut pod: PodCabide<Reading> = PodCabide::new(pod_file, None)?;
+    let mut cbd: Cabide<Reading> = Cabide::new(bincode_file, None)?;
+
+    let (mut pod_slots, mut blocks) = (vec![], vec![]);
+    for i in 0..RECORDS {
+        let reading = Reading {
+            sensor: i % 16,
+            timestamp: i,
+            value: i as f64 / 7.,
+        };
+        pod_slots.push(pod.write(&reading)?);
+        blocks.push(cbd.write(&reading)?);
+    }
+
+    let start = Instant::now();
+    for slot in &pod_slots {
+        pod.read(*slot)?;
+    }
+    println!(
+        "PodCabide: {:?} to read {} records",
+        start.elapsed(),
+        RECORDS
+    );
+
+    let start = Instant::now();
+    for block in &blocks {
+        cbd.read(*block)?;
+    }
+    println!(
+        "Cabide:    {:?} to read {} records",
+        start.elapsed(),
+        RECORDS
+    );
+
+    std::fs::remove_file(pod_file)?;
+    std::fs::remove_file(bincode_file)?;
+    Ok(())
+}
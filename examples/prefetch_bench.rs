@@ -0,0 +1,49 @@
+use cabide::Cabide;
+use std::time::Instant;
+
+const OBJECTS: u64 = 200_000;
+
+/// Evicts the file from the page cache, so the next scan reads it from disk
+#[cfg(target_os = "linux")]
+fn evict(filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::File::open(filename)?;
+    file.sync_all()?;
+    // SAFETY: the file descriptor is valid while `file` is alive
+    let ret = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    if ret != 0 {
+        return Err(std::io::Error::from_raw_os_error(ret).into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn evict(_filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let filename = "prefetch_bench.db";
+    std::fs::File::create(filename)?;
+
+    let mut cbd: Cabide<(u64, String)> = Cabide::new(filename, None)?;
+    for i in 0..OBJECTS {
+        cbd.write(&(i, "object".repeat((i % 10) as usize)))?;
+    }
+    let blocks = cbd.blocks()?;
+
+    evict(filename)?;
+    let start = Instant::now();
+    let found = cbd.filter(|(i, _)| i % 1000 == 0).len();
+    println!("Cold scan:       {:?} ({} objects)", start.elapsed(), found);
+
+    evict(filename)?;
+    let start = Instant::now();
+    cbd.prefetch(0..blocks)?;
+    let found = cbd.filter(|(i, _)| i % 1000 == 0).len();
+    println!("Prefetched scan: {:?} ({} objects)", start.elapsed(), found);
+
+    std::fs::remove_file(filename)?;
+    Ok(())
+}
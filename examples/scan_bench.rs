@@ -0,0 +1,40 @@
+use cabide::Cabide;
+use std::time::Instant;
+
+const BLOCKS: u64 = 100_000;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let filename = "scan_bench.db";
+    std::fs::File::create(filename)?;
+
+    // Every object uses a block, removing some of them leaves empty blocks for the scan to find
+    let mut cbd: Cabide<u64> = Cabide::new(filename, None)?;
+    for i in 0..BLOCKS {
+        cbd.write(&i)?;
+    }
+    for block in (0..BLOCKS).step_by(7) {
+        cbd.remove(block)?;
+    }
+    drop(cbd);
+
+    // A block per read, like scanning block by block
+    let start = Instant::now();
+    let cbd: Cabide<u64> = Cabide::builder().scan_chunk_size(1).open(filename)?;
+    println!(
+        "Block by block: {:?} to open {} blocks",
+        start.elapsed(),
+        cbd.blocks()?
+    );
+    drop(cbd);
+
+    let start = Instant::now();
+    let cbd: Cabide<u64> = Cabide::builder().open(filename)?;
+    println!(
+        "Chunked:        {:?} to open {} blocks",
+        start.elapsed(),
+        cbd.blocks()?
+    );
+
+    std::fs::remove_file(filename)?;
+    Ok(())
+}
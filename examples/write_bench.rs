@@ -0,0 +1,64 @@
+use cabide::Cabide;
+use serde::{Deserialize, Serialize};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Counts every allocation made by the process
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Reading {
+    sensor: u64,
+    timestamp: u64,
+    label: String,
+}
+
+const RECORDS: u64 = 100_000;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let filename = "readings_write.db";
+    std::fs::File::create(filename)?;
+    let mut cbd: Cabide<Reading> = Cabide::new(filename, None)?;
+
+    let readings: Vec<Reading> = (0..RECORDS)
+        .map(|i| Reading {
+            sensor: i % 16,
+            timestamp: i,
+            label: format!("sensor-{}", i % 16),
+        })
+        .collect();
+
+    let (allocations, start) = (ALLOCATIONS.load(Ordering::SeqCst), Instant::now());
+    for reading in &readings {
+        cbd.write(reading)?;
+    }
+    let (elapsed, allocations) = (
+        start.elapsed(),
+        ALLOCATIONS.load(Ordering::SeqCst) - allocations,
+    );
+    println!("Cabide: {:?} to write {} records", elapsed, RECORDS);
+    println!(
+        "Cabide: {:.2} allocations per write",
+        allocations as f64 / RECORDS as f64
+    );
+
+    std::fs::remove_file(filename)?;
+    Ok(())
+}
@@ -0,0 +1,462 @@
+use crate::{Cabide, Error};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+/// Maximum number of keys of a node, fuller nodes are split in two
+const MAX_KEYS: usize = 32;
+
+/// Node of the B-tree, stored as an object of the index file
+#[derive(Serialize, Deserialize, Debug)]
+enum Node<K> {
+    /// Keys with the block of their object in the heap file, sorted by key
+    Leaf(Vec<(K, u64)>),
+    /// `keys[i]` is the smallest key of `children[i + 1]`, there is one more child than keys
+    Internal { keys: Vec<K>, children: Vec<u64> },
+}
+
+/// Node that had to be split in two while inserting, the new one goes right after the old one
+struct Split<K> {
+    /// Smallest key of the new node
+    key: K,
+    /// Block of the new node
+    block: u64,
+}
+
+/// Outcome of inserting a key into a subtree
+struct Inserted<K> {
+    /// New block of the subtree's root
+    block: u64,
+    /// Node split from the subtree's root, if it got too full
+    split: Option<Split<K>>,
+    /// Block of the object that was replaced, if the key was already there
+    replaced: Option<u64>,
+}
+
+/// Ordered map of keys to objects, sorted as they are written, instead of being periodically re-sorted like
+/// [`OrderCabide`](crate::OrderCabide)
+///
+/// Objects are stored in a heap file, the B-tree in the index file maps each key to the object's block, only
+/// the nodes in the path to the key are rewritten by inserts and removals. The block of the root is kept in
+/// the header of the index file (see [`Cabide::user_metadata`])
+///
+/// Each node is rewritten with its own write, a crash in the middle of an insert may lose the object
+///
+/// ```rust
+/// use cabide::BTreeCabide;
+///
+/// # fn main() -> Result<(), cabide::Error> {
+/// # let _ = std::fs::remove_file("test52.heap");
+/// # let _ = std::fs::remove_file("test52.index");
+/// let mut cbd: BTreeCabide<String, u64> = BTreeCabide::new("test52.heap", "test52.index")?;
+/// for id in [30, 10, 20] {
+///     cbd.insert(id, &format!("user {}", id))?;
+/// }
+///
+/// assert_eq!(cbd.get(&20)?, Some("user 20".to_owned()));
+/// assert_eq!(cbd.range(15..)?, vec![(20, "user 20".to_owned()), (30, "user 30".to_owned())]);
+/// assert_eq!(cbd.iter()?.len(), 3);
+/// # std::fs::remove_file("test52.heap")?;
+/// # std::fs::remove_file("test52.index")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct BTreeCabide<T, K> {
+    heap: Cabide<T>,
+    index: Cabide<Node<K>>,
+    /// Block of the root node, `None` if the tree is empty
+    root: Option<u64>,
+}
+
+impl<T, K> BTreeCabide<T, K>
+where
+    for<'de> T: Serialize + Deserialize<'de>,
+    for<'de> K: Serialize + Deserialize<'de> + Ord + Clone,
+{
+    /// Binds the objects to the heap file and the B-tree to the index file, creating them if non existent
+    pub fn new(heap: impl AsRef<Path>, index: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut index: Cabide<Node<K>> = Cabide::new(index, None)?;
+        let root = match index.user_metadata().as_slice() {
+            [] => None,
+            bytes => Some(u64::from_le_bytes(
+                <[u8; 8]>::try_from(bytes).map_err(|_| Error::CorruptedBlock)?,
+            )),
+        };
+        if root.is_none() && index.blocks()? == 0 {
+            // Header only fits in empty files, so it's written before the first node
+            index.set_user_metadata(&[])?;
+        }
+
+        Ok(Self {
+            heap: Cabide::new(heap, None)?,
+            index,
+            root,
+        })
+    }
+
+    /// Persists the block of the root node, if it changed
+    fn set_root(&mut self, root: Option<u64>) -> Result<(), Error> {
+        if root != self.root {
+            let bytes = root.map(u64::to_le_bytes);
+            self.index
+                .set_user_metadata(bytes.as_ref().map_or(&[], |bytes| bytes))?;
+            self.root = root;
+        }
+        Ok(())
+    }
+
+    /// Inserts object with specified key, returns the object it replaced, if the key was already there
+    pub fn insert(&mut self, key: K, obj: &T) -> Result<Option<T>, Error> {
+        let value = self.heap.write(obj)?;
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                let root = self.index.write(&Node::Leaf(vec![(key, value)]))?;
+                self.set_root(Some(root))?;
+                return Ok(None);
+            }
+        };
+
+        let Inserted {
+            block: root,
+            split,
+            replaced,
+        } = self.insert_into(root, key, value)?;
+        let root = match split {
+            Some(split) => self.index.write(&Node::Internal {
+                keys: vec![split.key],
+                children: vec![root, split.block],
+            })?,
+            None => root,
+        };
+        self.set_root(Some(root))?;
+        match replaced {
+            Some(replaced) => Ok(Some(self.heap.remove(replaced)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Inserts key into the subtree at `block`
+    fn insert_into(&mut self, block: u64, key: K, value: u64) -> Result<Inserted<K>, Error> {
+        match self.index.read(block)? {
+            Node::Leaf(mut entries) => {
+                let replaced = match entries.binary_search_by(|(other, _)| other.cmp(&key)) {
+                    Ok(i) => Some(std::mem::replace(&mut entries[i].1, value)),
+                    Err(i) => {
+                        entries.insert(i, (key, value));
+                        None
+                    }
+                };
+
+                let split = if entries.len() > MAX_KEYS {
+                    let right = entries.split_off(entries.len() / 2);
+                    let key = right[0].0.clone();
+                    let block = self.index.write(&Node::Leaf(right))?;
+                    Some(Split { key, block })
+                } else {
+                    None
+                };
+                let block = self.index.update(block, &Node::Leaf(entries))?;
+                Ok(Inserted {
+                    block,
+                    split,
+                    replaced,
+                })
+            }
+            Node::Internal {
+                mut keys,
+                mut children,
+            } => {
+                let i = keys.partition_point(|other| *other <= key);
+                let child = self.insert_into(children[i], key, value)?;
+                let replaced = child.replaced;
+                if child.block == children[i] && child.split.is_none() {
+                    return Ok(Inserted {
+                        block,
+                        split: None,
+                        replaced,
+                    });
+                }
+
+                children[i] = child.block;
+                if let Some(child_split) = child.split {
+                    keys.insert(i, child_split.key);
+                    children.insert(i + 1, child_split.block);
+                }
+
+                let split = if keys.len() > MAX_KEYS {
+                    let mid = keys.len() / 2;
+                    let right = Node::Internal {
+                        keys: keys.split_off(mid + 1),
+                        children: children.split_off(mid + 1),
+                    };
+                    let key = keys.pop().ok_or(Error::CorruptedBlock)?;
+                    let block = self.index.write(&right)?;
+                    Some(Split { key, block })
+                } else {
+                    None
+                };
+                let block = self
+                    .index
+                    .update(block, &Node::Internal { keys, children })?;
+                Ok(Inserted {
+                    block,
+                    split,
+                    replaced,
+                })
+            }
+        }
+    }
+
+    /// Returns block of the object with specified key in the heap file
+    fn find(&mut self, key: &K) -> Result<Option<u64>, Error> {
+        let mut block = match self.root {
+            Some(root) => root,
+            None => return Ok(None),
+        };
+        loop {
+            match self.index.read(block)? {
+                Node::Leaf(entries) => {
+                    let found = entries.binary_search_by(|(other, _)| other.cmp(key));
+                    return Ok(found.ok().map(|i| entries[i].1));
+                }
+                Node::Internal { keys, children } => {
+                    block = children[keys.partition_point(|other| other <= key)];
+                }
+            }
+        }
+    }
+
+    /// Returns object with specified key, only the nodes in the path to it are read
+    pub fn get(&mut self, key: &K) -> Result<Option<T>, Error> {
+        match self.find(key)? {
+            Some(block) => Ok(Some(self.heap.read(block)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes object with specified key, returns it if the key was there
+    ///
+    /// Nodes that become empty are removed, but nodes aren't merged with their siblings
+    pub fn remove(&mut self, key: &K) -> Result<Option<T>, Error> {
+        let root = match self.root {
+            Some(root) => root,
+            None => return Ok(None),
+        };
+        let (root, removed) = self.remove_from(root, key)?;
+        self.set_root(root)?;
+        match removed {
+            Some(removed) => Ok(Some(self.heap.remove(removed)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes key from the subtree at `block`, returns the subtree's new block (`None` if it became empty)
+    /// and the block of the removed object
+    fn remove_from(&mut self, block: u64, key: &K) -> Result<(Option<u64>, Option<u64>), Error> {
+        match self.index.read(block)? {
+            Node::Leaf(mut entries) => {
+                let (_, removed) = match entries.binary_search_by(|(other, _)| other.cmp(key)) {
+                    Ok(i) => entries.remove(i),
+                    Err(_) => return Ok((Some(block), None)),
+                };
+                if entries.is_empty() {
+                    self.index.remove(block)?;
+                    return Ok((None, Some(removed)));
+                }
+                let block = self.index.update(block, &Node::Leaf(entries))?;
+                Ok((Some(block), Some(removed)))
+            }
+            Node::Internal {
+                mut keys,
+                mut children,
+            } => {
+                let i = keys.partition_point(|other| other <= key);
+                let (child, removed) = self.remove_from(children[i], key)?;
+                match child {
+                    Some(child) if child == children[i] => return Ok((Some(block), removed)),
+                    Some(child) => children[i] = child,
+                    None => {
+                        // The separator of the child is removed with it, its range goes to its neighbour
+                        children.remove(i);
+                        keys.remove(i.saturating_sub(1));
+                    }
+                }
+
+                if children.len() == 1 {
+                    // Internal nodes with a single child are replaced by it
+                    self.index.remove(block)?;
+                    return Ok((Some(children[0]), removed));
+                }
+                let block = self
+                    .index
+                    .update(block, &Node::Internal { keys, children })?;
+                Ok((Some(block), removed))
+            }
+        }
+    }
+
+    /// Returns every object with a key in the range, sorted by key
+    ///
+    /// Subtrees that can't have keys in the range aren't read
+    pub fn range(&mut self, range: impl RangeBounds<K>) -> Result<Vec<(K, T)>, Error> {
+        let mut found = vec![];
+        if let Some(root) = self.root {
+            self.collect(root, &range, &mut found)?;
+        }
+
+        let mut vec = Vec::with_capacity(found.len());
+        for (key, block) in found {
+            vec.push((key, self.heap.read(block)?));
+        }
+        Ok(vec)
+    }
+
+    /// Returns every object, sorted by key
+    #[inline]
+    pub fn iter(&mut self) -> Result<Vec<(K, T)>, Error> {
+        self.range(..)
+    }
+
+    /// Pushes the keys in the range of the subtree at `block` to `found`, in order, with their objects' blocks
+    fn collect(
+        &mut self,
+        block: u64,
+        range: &impl RangeBounds<K>,
+        found: &mut Vec<(K, u64)>,
+    ) -> Result<(), Error> {
+        match self.index.read(block)? {
+            Node::Leaf(entries) => {
+                found.extend(entries.into_iter().filter(|(key, _)| range.contains(key)));
+            }
+            Node::Internal { keys, children } => {
+                for (i, child) in children.into_iter().enumerate() {
+                    // Keys of the child are in `[keys[i - 1], keys[i])`
+                    let below = keys.get(i).is_some_and(|upper| match range.start_bound() {
+                        Bound::Included(start) | Bound::Excluded(start) => upper <= start,
+                        Bound::Unbounded => false,
+                    });
+                    let above = i.checked_sub(1).is_some_and(|i| match range.end_bound() {
+                        Bound::Included(end) => &keys[i] > end,
+                        Bound::Excluded(end) => &keys[i] >= end,
+                        Bound::Unbounded => false,
+                    });
+                    if !below && !above {
+                        self.collect(child, range, found)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Generator;
+
+    fn files(name: &str) -> (String, String) {
+        let files = (
+            format!("btree_{}.heap.test", name),
+            format!("btree_{}.index.test", name),
+        );
+        let _ = std::fs::remove_file(&files.0);
+        let _ = std::fs::remove_file(&files.1);
+        files
+    }
+
+    fn remove_files((heap, index): (String, String)) {
+        std::fs::remove_file(heap).unwrap();
+        std::fs::remove_file(index).unwrap();
+    }
+
+    /// Every key from 0 to `n` (exclusive) in a random order
+    fn shuffled(n: u64, seed: u64) -> Vec<u64> {
+        let (mut keys, mut rng) = ((0..n).collect::<Vec<_>>(), Generator::new(seed));
+        for i in (1..keys.len()).rev() {
+            keys.swap(i, rng.range(0..i as u64 + 1) as usize);
+        }
+        keys
+    }
+
+    #[test]
+    fn ordered_iteration() {
+        let (heap, index) = files("ordered");
+        let mut cbd: BTreeCabide<String, u64> = BTreeCabide::new(&heap, &index).unwrap();
+        for key in shuffled(2000, 1) {
+            assert_eq!(cbd.insert(key, &key.to_string()).unwrap(), None);
+        }
+        assert_eq!(
+            cbd.insert(7, &"seven".to_owned()).unwrap(),
+            Some("7".to_owned())
+        );
+
+        let expected: Vec<_> = (0..2000)
+            .map(|key| match key {
+                7 => (key, "seven".to_owned()),
+                _ => (key, key.to_string()),
+            })
+            .collect();
+        assert_eq!(cbd.iter().unwrap(), expected);
+
+        // this drops the last BTreeCabide, reading the root from the index file
+        cbd = BTreeCabide::new(&heap, &index).unwrap();
+        assert_eq!(cbd.iter().unwrap(), expected);
+        assert_eq!(cbd.get(&1999).unwrap(), Some("1999".to_owned()));
+        assert_eq!(cbd.get(&2000).unwrap(), None);
+        remove_files((heap, index));
+    }
+
+    #[test]
+    fn range_queries() {
+        let (heap, index) = files("range");
+        let mut cbd: BTreeCabide<u64, u64> = BTreeCabide::new(&heap, &index).unwrap();
+        // Only even keys, so the bounds fall both on keys and between them
+        for key in shuffled(1000, 2) {
+            cbd.insert(key * 2, &(key * 2)).unwrap();
+        }
+
+        let values = |range: Vec<(u64, u64)>| {
+            range
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect::<Vec<_>>()
+        };
+        let evens = |range: std::ops::Range<u64>| range.filter(|i| i % 2 == 0).collect::<Vec<_>>();
+        assert_eq!(values(cbd.range(100..200).unwrap()), evens(100..200));
+        assert_eq!(values(cbd.range(101..=201).unwrap()), evens(101..202));
+        assert_eq!(values(cbd.range(..10).unwrap()), evens(0..10));
+        assert_eq!(values(cbd.range(1990..).unwrap()), evens(1990..2000));
+        assert_eq!(
+            values(
+                cbd.range((Bound::Excluded(500), Bound::Excluded(600)))
+                    .unwrap()
+            ),
+            evens(501..600)
+        );
+        assert!(cbd.range(5000..).unwrap().is_empty());
+
+        for key in (0..2000).step_by(4) {
+            assert_eq!(cbd.remove(&key).unwrap(), Some(key));
+        }
+        assert_eq!(cbd.remove(&0).unwrap(), None);
+        let expected: Vec<u64> = (100..200).filter(|i| i % 4 == 2).collect();
+        assert_eq!(values(cbd.range(100..200).unwrap()), expected);
+
+        for key in (2..2000).step_by(4) {
+            cbd.remove(&key).unwrap();
+        }
+        assert!(cbd.iter().unwrap().is_empty());
+        assert_eq!(cbd.heap.filter(|_| true), vec![]);
+        assert_eq!(cbd.index.filter(|_| true).len(), 0);
+
+        // this drops the last BTreeCabide, the empty tree is persisted too
+        cbd = BTreeCabide::new(&heap, &index).unwrap();
+        assert!(cbd.root.is_none());
+        cbd.insert(1, &1).unwrap();
+        assert_eq!(cbd.iter().unwrap(), vec![(1, 1)]);
+        remove_files((heap, index));
+    }
+}
@@ -0,0 +1,850 @@
+use crate::cursor::Storage;
+use crate::header::layout_fingerprint;
+use crate::{
+    Allocation, Allocator, Cabide, Error, SyncMethod, SyncMode, DEFAULT_MAX_OBJECT_SIZE,
+    DEFAULT_SCAN_CHUNK_SIZE,
+};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, fs::OpenOptions, io, io::Cursor, marker::PhantomData};
+
+/// Transforms the serialized bytes of every object before they are written and after they are read
+///
+/// Allows plugging compression or encryption in without changing the block layout
+pub trait Codec: fmt::Debug + Send + Sync {
+    /// Transforms serialized object into the bytes that will be stored
+    fn encode(&self, raw: Vec<u8>) -> Vec<u8>;
+
+    /// Reverts `encode`, returning the serialized object
+    fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>, Error>;
+}
+
+/// Configures and opens a [`Cabide`]
+///
+/// ```rust
+/// use cabide::Cabide;
+///
+/// # fn main() -> Result<(), cabide::Error> {
+/// # std::fs::File::create("test16.file")?;
+/// let mut cbd: Cabide<u8, 64> = Cabide::builder()
+///     .prefill(10)
+///     .append_only(true)
+///     .open("test16.file")?;
+/// assert_eq!(cbd.blocks()?, 10);
+///
+/// cbd.write(&1)?;
+/// cbd.remove(0)?;
+/// assert_eq!(cbd.write(&2)?, 1);
+/// # std::fs::remove_file("test16.file")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CabideBuilder<T, const BLOCK_SIZE: usize = { crate::protocol::BLOCK_SIZE as usize }> {
+    /// Number of blocks the file should have, it's never shrinked
+    pub(crate) prefill: Option<u64>,
+    /// Byte range of the file the database is bound to, if it's bounded
+    pub(crate) range: Option<(u64, u64)>,
+    /// If writes should always append, instead of re-using free blocks
+    pub(crate) append_only: bool,
+    /// If objects may be split across free blocks that aren't continuous
+    pub(crate) fragmentation: bool,
+    /// How free blocks are chosen to be re-used
+    pub(crate) allocation: Allocation,
+    /// Chooses the free blocks to be re-used instead of `allocation`, if set
+    pub(crate) allocator: Option<Box<dyn Allocator>>,
+    /// Maximum length in bytes the file can grow to, if it's limited
+    pub(crate) max_bytes: Option<u64>,
+    /// Maximum size in bytes of an object
+    pub(crate) max_object_size: u64,
+    /// Maximum number of blocks of an object, derived from the maximum size if not set
+    pub(crate) max_object_blocks: Option<u64>,
+    /// Transforms serialized objects, if any
+    pub(crate) codec: Option<Box<dyn Codec>>,
+    /// Minimum size of the serialized objects transformed by the codec, if smaller ones aren't
+    pub(crate) codec_threshold: Option<u64>,
+    /// If objects are prefixed by a sequence number
+    pub(crate) sequence_numbers: bool,
+    /// If the block of each sequence number is kept in memory
+    pub(crate) sequence_index: bool,
+    /// If objects are prefixed by an id that is kept when they are moved
+    pub(crate) stable_ids: bool,
+    /// If objects are prefixed by a CRC32 of their content, verified when they are read
+    pub(crate) checksums: bool,
+    /// If objects are prefixed by their enum variant, only set by `TaggedCabide`
+    pub(crate) tagged: bool,
+    /// How the file is synced to disk when the database is dropped, if it is
+    pub(crate) sync_on_drop: Option<SyncMethod>,
+    /// If `write`, `remove` and `update` wait for the file to reach the disk before returning
+    pub(crate) sync_on_write: bool,
+    /// Bytes read at once when scanning the file for empty blocks
+    pub(crate) scan_chunk_size: u64,
+    /// Fingerprint of the objects' layout, checked against the one in the header if set
+    pub(crate) layout: Option<u64>,
+    /// If corrupted objects found by scans are emptied
+    pub(crate) quarantine_on_corruption: bool,
+    /// If the free blocks are stored in a sidecar file, so opening doesn't scan the file for them
+    pub(crate) persist_free_blocks: bool,
+    /// If the file is opened without write access, so every change fails
+    pub(crate) read_only: bool,
+    /// If reads are served by a read-only map of the file, instead of syscalls
+    pub(crate) mmap: bool,
+    /// Maximum number of objects kept by the read cache, if there is one
+    pub(crate) read_cache: Option<usize>,
+    /// Options the file is opened with, read and write access are always added
+    pub(crate) open_options: Option<OpenOptions>,
+    /// Marks that the built database must contain a single type
+    pub(crate) _marker: PhantomData<T>,
+}
+
+impl<T, const B: usize> Default for CabideBuilder<T, B> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            prefill: None,
+            range: None,
+            append_only: false,
+            fragmentation: false,
+            allocation: Allocation::BestFit,
+            allocator: None,
+            max_bytes: None,
+            max_object_size: DEFAULT_MAX_OBJECT_SIZE,
+            max_object_blocks: None,
+            codec: None,
+            codec_threshold: None,
+            sequence_numbers: false,
+            sequence_index: false,
+            stable_ids: false,
+            checksums: false,
+            tagged: false,
+            sync_on_drop: None,
+            sync_on_write: false,
+            scan_chunk_size: DEFAULT_SCAN_CHUNK_SIZE,
+            layout: None,
+            quarantine_on_corruption: false,
+            persist_free_blocks: false,
+            read_only: false,
+            mmap: false,
+            read_cache: None,
+            open_options: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, const B: usize> CabideBuilder<T, B> {
+    /// Starts with the same defaults as [`Cabide::new`]
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pre-fills the file to have specified number of blocks, ignored if it already has more
+    #[inline]
+    pub fn prefill(mut self, blocks: u64) -> Self {
+        self.prefill = Some(blocks);
+        self
+    }
+
+    /// Binds database to the `[offset, offset + len)` byte range of the file, see [`Cabide::new_at`]
+    #[inline]
+    pub fn range(mut self, offset: u64, len: u64) -> Self {
+        self.range = Some((offset, len));
+        self
+    }
+
+    /// Always appends writes to the end of the file, see [`Cabide::with_free_block_reuse`]
+    #[inline]
+    pub fn append_only(mut self, append_only: bool) -> Self {
+        self.append_only = append_only;
+        self
+    }
+
+    /// Allows objects to be split across free blocks that aren't continuous, if no run of free blocks fits
+    /// them, instead of appending them to the end of the file
+    ///
+    /// Trades read locality for space, the last block of each part ends with the block where the next part
+    /// starts, so reads follow the chain. Every database reads fragmented objects, this only affects writes,
+    /// compaction makes them continuous again
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test45.file")?;
+    /// let mut cbd: Cabide<Vec<u8>> = Cabide::builder().fragmentation(true).open("test45.file")?;
+    /// for i in 0..10 {
+    ///     cbd.write(&vec![i; 10])?;
+    /// }
+    /// for block in (0..10).step_by(2) {
+    ///     cbd.remove(block)?;
+    /// }
+    ///
+    /// // No free block is next to another, but together they fit the object
+    /// let obj = vec![10; 50];
+    /// let block = cbd.write(&obj)?;
+    /// assert_eq!(cbd.blocks()?, 10);
+    /// assert_eq!(cbd.read(block)?, obj);
+    /// # std::fs::remove_file("test45.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn fragmentation(mut self, fragmentation: bool) -> Self {
+        self.fragmentation = fragmentation;
+        self
+    }
+
+    /// Sets how free blocks in the middle of the file are chosen, defaults to `Allocation::BestFit`
+    #[inline]
+    pub fn allocation(mut self, allocation: Allocation) -> Self {
+        self.allocation = allocation;
+        self
+    }
+
+    /// Chooses the free blocks re-used by writes with specified allocator, instead of the built-in
+    /// [`Allocation`] strategies
+    #[inline]
+    pub fn allocator(mut self, allocator: impl Allocator + 'static) -> Self {
+        self.allocator = Some(Box::new(allocator));
+        self
+    }
+
+    /// Limits the length in bytes the file can grow to, writes and pre-filling that would grow it past the
+    /// limit fail with `Error::SizeLimitExceeded` without changing it
+    ///
+    /// Free blocks are still re-used, files already past the limit are never shrinked
+    ///
+    /// ```rust
+    /// use cabide::{Cabide, Error};
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test49.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::builder().max_bytes(10 * 30).open("test49.file")?;
+    /// for i in 0..10 {
+    ///     cbd.write(&i)?;
+    /// }
+    /// assert!(matches!(cbd.write(&10), Err(Error::SizeLimitExceeded { .. })));
+    ///
+    /// cbd.remove(3)?;
+    /// assert_eq!(cbd.write(&10)?, 3);
+    /// # std::fs::remove_file("test49.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn max_bytes(mut self, max: u64) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+
+    /// Sets maximum size in bytes of an object, see [`Cabide::set_max_object_size`]
+    #[inline]
+    pub fn max_object_size(mut self, max: u64) -> Self {
+        self.max_object_size = max;
+        self
+    }
+
+    /// Sets maximum number of blocks of an object, see [`Cabide::set_max_object_blocks`]
+    #[inline]
+    pub fn max_object_blocks(mut self, max: u64) -> Self {
+        self.max_object_blocks = Some(max);
+        self
+    }
+
+    /// Transforms every serialized object with specified codec, files must always be opened with the same one
+    #[inline]
+    pub fn codec(mut self, codec: impl Codec + 'static) -> Self {
+        self.codec = Some(Box::new(codec));
+        self
+    }
+
+    /// Only transforms objects with the codec if they are serialized into at least `min_size` bytes, smaller
+    /// ones are stored as they are, since they compress poorly and aren't worth the CPU
+    ///
+    /// Each object is prefixed by a flag telling if it was transformed, so files must always be opened with
+    /// the same setting (the threshold itself may change)
+    ///
+    /// ```rust
+    /// use cabide::{Cabide, Codec, Error};
+    ///
+    /// #[derive(Debug)]
+    /// struct Reverse;
+    ///
+    /// impl Codec for Reverse {
+    ///     fn encode(&self, mut raw: Vec<u8>) -> Vec<u8> {
+    ///         raw.reverse();
+    ///         raw
+    ///     }
+    ///
+    ///     fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>, Error> {
+    ///         Ok(self.encode(stored))
+    ///     }
+    /// }
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test39.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::builder()
+    ///     .codec(Reverse)
+    ///     .codec_threshold(64)
+    ///     .open("test39.file")?;
+    ///
+    /// let small = cbd.write(&"small".to_owned())?;
+    /// let big = cbd.write(&"big".repeat(30))?;
+    /// assert_eq!(cbd.read(small)?, "small");
+    /// assert_eq!(cbd.read(big)?, "big".repeat(30));
+    /// # std::fs::remove_file("test39.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn codec_threshold(mut self, min_size: u64) -> Self {
+        self.codec_threshold = Some(min_size);
+        self
+    }
+
+    /// Prefixes every object with an increasing sequence number, used by [`Cabide::iter_ordered`] to
+    /// iterate in the order objects were written, files must always be opened with the same setting
+    #[inline]
+    pub fn sequence_numbers(mut self, enabled: bool) -> Self {
+        self.sequence_numbers = enabled;
+        self
+    }
+
+    /// Keeps the block of each sequence number in memory, built when the file is opened, so
+    /// [`Cabide::read_seq`] doesn't scan the file, ignored if sequence numbers are disabled
+    ///
+    /// Changes made by other handles are only seen after [`Cabide::reload`]
+    #[inline]
+    pub fn sequence_index(mut self, enabled: bool) -> Self {
+        self.sequence_index = enabled;
+        self
+    }
+
+    /// Prefixes every object with an id given on its first write, kept when it's replaced (like by
+    /// [`Cabide::patch`]) or moved by compaction, so [`Cabide::read_by_id`] finds it wherever it is
+    ///
+    /// The block of every id is kept in memory, built when the file is opened, ids of removed objects aren't
+    /// given again while the file is open. Files must always be opened with the same setting
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test47.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::builder().stable_ids(true).open("test47.file")?;
+    ///
+    /// let removed = cbd.write(&"removed".to_owned())?;
+    /// let block = cbd.write(&"moved".to_owned())?;
+    /// let id = cbd.id(block)?;
+    /// cbd.remove(removed)?;
+    ///
+    /// cbd.compact_resumable(10)?;
+    /// assert_eq!(cbd.read(0)?, "moved");
+    /// assert_eq!(cbd.read_by_id(id)?, "moved");
+    /// # std::fs::remove_file("test47.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn stable_ids(mut self, enabled: bool) -> Self {
+        self.stable_ids = enabled;
+        self
+    }
+
+    /// Prefixes every object with a CRC32 of its content, verified when it's read, so bits flipped on disk
+    /// fail with `Error::ChecksumMismatch` instead of returning a wrong object
+    ///
+    /// Files must always be opened with the same setting, so files written without checksums are still
+    /// read without them
+    ///
+    /// ```rust
+    /// use cabide::{Cabide, Error};
+    /// use std::io::{Seek, SeekFrom, Write};
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test64.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::builder().checksums(true).open("test64.file")?;
+    /// let block = cbd.write(&"checked".to_owned())?;
+    ///
+    /// // Replaces the 'c' at the start of the string with a 'C'
+    /// let mut file = std::fs::OpenOptions::new().write(true).open("test64.file")?;
+    /// file.seek(SeekFrom::Start(1 + 4 + 8))?;
+    /// file.write_all(b"C")?;
+    /// assert!(matches!(cbd.read(block), Err(Error::ChecksumMismatch { block: 0 })));
+    /// # std::fs::remove_file("test64.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn checksums(mut self, enabled: bool) -> Self {
+        self.checksums = enabled;
+        self
+    }
+
+    /// Syncs the file to disk with specified method when the database is dropped, errors are ignored
+    ///
+    /// `SyncMethod::Data` is enough unless something relies on the file's metadata, like its modification time
+    #[inline]
+    pub fn sync_on_drop(mut self, method: SyncMethod) -> Self {
+        self.sync_on_drop = Some(method);
+        self
+    }
+
+    /// Chooses when the file is synced to disk, defaults to `SyncMode::Never`, replaces `sync_on_drop`
+    ///
+    /// ```rust
+    /// use cabide::{Cabide, SyncMode};
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test66.file")?;
+    /// // Every write is durable once it returns, even if the process is killed right after
+    /// let mut cbd: Cabide<u8> = Cabide::builder()
+    ///     .sync_mode(SyncMode::OnEveryWrite)
+    ///     .open("test66.file")?;
+    /// let block = cbd.write(&1)?;
+    /// assert_eq!(cbd.remove(block)?, 1);
+    /// # std::fs::remove_file("test66.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn sync_mode(mut self, mode: SyncMode) -> Self {
+        self.sync_on_write = mode == SyncMode::OnEveryWrite;
+        self.sync_on_drop = match mode {
+            SyncMode::OnDrop => Some(SyncMethod::All),
+            SyncMode::Never | SyncMode::OnEveryWrite => None,
+        };
+        self
+    }
+
+    /// Maps the file read-only, so reads (like the ones of scans, such as `filter` or `first`) copy from memory
+    /// instead of issuing a seek and a read per block, defaults to false
+    ///
+    /// Writes still use file IO, the map sees them and is remapped when the file grows past it (blocks past
+    /// it are read with file IO until then). Nothing else may shrink the file while it's mapped, reading the
+    /// missing pages crashes the process, databases in memory ignore it
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test68.file")?;
+    /// let mut cbd: Cabide<u32> = Cabide::builder().mmap(true).open("test68.file")?;
+    /// for i in 0..1000 {
+    ///     cbd.write(&i)?;
+    /// }
+    /// assert_eq!(cbd.filter(|i| i % 100 == 0).len(), 10);
+    /// # std::fs::remove_file("test68.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn mmap(mut self, enabled: bool) -> Self {
+        self.mmap = enabled;
+        self
+    }
+
+    /// Keeps the `capacity` most recently read objects in memory, so reading them again with
+    /// [`Cabide::read_shared`] doesn't touch the file nor deserialize them
+    ///
+    /// Only `read_shared` uses it, [`Cabide::read`] returns an owned object, so it always deserializes it from
+    /// the file
+    ///
+    /// Objects are dropped from it when they are changed (like by `write`, `remove` or `update`), changes made
+    /// by other handles are only seen after [`Cabide::reload`]
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test69.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::builder().read_cache(100).open("test69.file")?;
+    /// let block = cbd.write(&"hot".to_owned())?;
+    /// assert_eq!(*cbd.read_shared(block)?, "hot");
+    ///
+    /// // Served from memory
+    /// assert_eq!(*cbd.read_shared(block)?, "hot");
+    ///
+    /// let block = cbd.update(block, &"changed".to_owned())?;
+    /// assert_eq!(*cbd.read_shared(block)?, "changed");
+    /// # std::fs::remove_file("test69.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn read_cache(mut self, capacity: usize) -> Self {
+        self.read_cache = Some(capacity);
+        self
+    }
+
+    /// Sets how many bytes are read at once when scanning the file for empty blocks on open (and on
+    /// [`Cabide::reload`]), defaults to [`DEFAULT_SCAN_CHUNK_SIZE`]
+    ///
+    /// At least a block is read at once, bigger chunks mean less syscalls but more memory while scanning
+    #[inline]
+    pub fn scan_chunk_size(mut self, bytes: u64) -> Self {
+        self.scan_chunk_size = bytes;
+        self
+    }
+
+    /// Empties the blocks of corrupted objects found by scans (like [`Cabide::filter`]) and records their
+    /// starting blocks, see [`Cabide::quarantined_blocks`], instead of skipping them every time
+    ///
+    /// The objects are lost, but their blocks can be re-used and the database keeps being usable
+    #[inline]
+    pub fn quarantine_on_corruption(mut self, enabled: bool) -> Self {
+        self.quarantine_on_corruption = enabled;
+        self
+    }
+
+    /// Stores the free blocks in a sidecar file (`<file>.idx`) when the database is dropped (or on
+    /// [`Cabide::flush_index`]), so opening it loads them instead of scanning the whole file
+    ///
+    /// Every handle removes the sidecar before first changing the file (even without this option), so only a
+    /// sidecar that describes the file is loaded, otherwise the file is scanned. Changes made without a
+    /// `Cabide` are only caught if they make the file newer than the sidecar. Sequence numbers and stable ids
+    /// still need to read every object. Only unbounded databases in a file, without a custom [`Allocator`],
+    /// have a sidecar
+    ///
+    /// The file must not be changed by other handles while it's open, like with [`Cabide::reload`]
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test55.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::builder().persist_free_blocks(true).open("test55.file")?;
+    /// for i in 0..10 {
+    ///     cbd.write(&i)?;
+    /// }
+    /// cbd.remove(3)?;
+    ///
+    /// drop(cbd);
+    ///
+    /// // Free blocks are loaded from "test55.file.idx"
+    /// let mut cbd: Cabide<u8> = Cabide::builder().persist_free_blocks(true).open("test55.file")?;
+    /// assert_eq!(cbd.write(&10)?, 3);
+    /// drop(cbd);
+    /// # std::fs::remove_file("test55.file")?;
+    /// # std::fs::remove_file("test55.file.idx")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn persist_free_blocks(mut self, enabled: bool) -> Self {
+        self.persist_free_blocks = enabled;
+        self
+    }
+
+    /// Stores a fingerprint of the objects' layout in the file's header, opening it with this check and a
+    /// type with another layout fails with `Error::LayoutChanged`, instead of returning garbage
+    ///
+    /// The fingerprint hashes `T::default()` serialized, so adding, removing or resizing fields is caught,
+    /// but changes that serialize the default the same way aren't (like renaming fields, which doesn't
+    /// affect how they are stored, or changing the type of the elements of a collection)
+    ///
+    /// It's a guardrail, not schema evolution, and it must be enabled when the file is created (opening a
+    /// file that has objects but no header with it fails)
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use cabide::{Cabide, Error};
+    ///
+    /// #[derive(Serialize, Deserialize, Default)]
+    /// struct Student {
+    ///     name: String,
+    /// }
+    ///
+    /// #[derive(Serialize, Deserialize, Default)]
+    /// struct StudentWithDre {
+    ///     name: String,
+    ///     dre: u64,
+    /// }
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # let _ = std::fs::remove_file("test29.file");
+    /// let mut cbd: Cabide<Student> = Cabide::builder().layout_check().open("test29.file")?;
+    /// cbd.write(&Student { name: "Alice".to_owned() })?;
+    /// drop(cbd);
+    ///
+    /// let res = Cabide::<StudentWithDre>::builder().layout_check().open("test29.file");
+    /// assert!(matches!(res, Err(Error::LayoutChanged { .. })));
+    /// # std::fs::remove_file("test29.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn layout_check(mut self) -> Self
+    where
+        T: Serialize + Default,
+    {
+        // Types that can't be serialized fail on every write anyway
+        self.layout = layout_fingerprint::<T>().ok();
+        self
+    }
+
+    /// Opens the file with specified options (like permissions on creation or platform specific flags),
+    /// read and write access are always added (write access unless `read_only`), appending, creation and
+    /// truncation are overriden
+    #[inline]
+    pub fn open_options(mut self, options: OpenOptions) -> Self {
+        self.open_options = Some(options);
+        self
+    }
+
+    /// Opens the file without write access, it must exist and every change fails with `Error::ReadOnly`, see
+    /// [`Cabide::open_read_only`]
+    #[inline]
+    pub fn read_only(mut self, enabled: bool) -> Self {
+        self.read_only = enabled;
+        self
+    }
+
+    /// Options the file is opened with, the ones given to `open_options` with read and write access (unless
+    /// read-only), but without appending
+    #[inline]
+    fn file_options(&self) -> OpenOptions {
+        let mut options = self.open_options.clone().unwrap_or_else(OpenOptions::new);
+        // Appending would ignore the seek to the block being written
+        options.read(true).write(!self.read_only).append(false);
+        options
+    }
+
+    /// Binds database to specified file, creating it if non existent
+    ///
+    /// Fails with `Error::NotSeekable` if the path exists but isn't a regular file (like a directory or a pipe)
+    pub fn open<P>(self, filename: P) -> Result<Cabide<T, B>, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = filename.as_ref();
+        match fs::metadata(path) {
+            // Opening a pipe may block and every block access needs to seek, so only regular files are accepted
+            Ok(metadata) if !metadata.file_type().is_file() => {
+                return Err(Error::NotSeekable {
+                    path: path.to_path_buf(),
+                })
+            }
+            Ok(_) => {}
+            // Fresh databases are only moved into place once ready, so interrupting it leaves no half-built file
+            Err(err) if err.kind() == io::ErrorKind::NotFound && self.read_only => {
+                return Err(Error::NotExistant)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let temp = creation_path(path);
+                let mut cabide = self.create(path, &temp)?;
+                cabide.sync_all()?;
+                fs::rename(&temp, path)?;
+                return Ok(cabide);
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let file = self
+            .file_options()
+            .create(!self.read_only)
+            .truncate(false)
+            .open(path)?;
+        Cabide::from_file(file, path, self)
+    }
+
+    /// Binds database to a buffer in memory instead of a file, see [`Cabide::from_bytes`]
+    #[inline]
+    pub fn open_bytes(self, bytes: Vec<u8>) -> Result<Cabide<T, B>, Error> {
+        Cabide::from_storage(Storage::Memory(Cursor::new(bytes)), None, self)
+    }
+
+    /// Builds a fresh database for `path` at `temp`, any leftover from an interrupted creation is discarded
+    fn create(self, path: &Path, temp: &Path) -> Result<Cabide<T, B>, Error> {
+        let file = self.file_options().create(true).truncate(true).open(temp)?;
+        Cabide::from_file(file, path, self)
+    }
+}
+
+/// Path where a fresh database is built before being moved into place
+#[inline]
+fn creation_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".tmp");
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Xor(u8);
+
+    impl Codec for Xor {
+        fn encode(&self, raw: Vec<u8>) -> Vec<u8> {
+            raw.into_iter().map(|byte| byte ^ self.0).collect()
+        }
+
+        fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>, Error> {
+            Ok(self.encode(stored))
+        }
+    }
+
+    #[test]
+    fn options() {
+        let filename = "builder_options.test";
+        std::fs::File::create(filename).unwrap();
+        let builder = || {
+            Cabide::<String, 64>::builder()
+                .prefill(10)
+                .append_only(true)
+                .codec(Xor(0b1010_1010))
+        };
+
+        let mut cbd = builder().open(filename).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 10);
+        // Block size isn't the default one, so it's stored in a header
+        let len = std::fs::metadata(filename).unwrap().len();
+        assert_eq!(len, crate::header::HEADER_SIZE + 640);
+
+        // 100 characters and the length need 2 blocks of 62 bytes of content
+        let value = "a".repeat(100);
+        assert_eq!(cbd.write(&value).unwrap(), 0);
+        assert_eq!(cbd.write(&value).unwrap(), 2);
+        assert!(!std::fs::read(filename)
+            .unwrap()
+            .windows(100)
+            .any(|w| w == value.as_bytes()));
+
+        cbd.remove(0).unwrap();
+        assert_eq!(cbd.write(&"b".to_owned()).unwrap(), 4);
+
+        // this drops the last cabide, therefore closes the file
+        cbd = builder().open(filename).unwrap();
+        assert_eq!(cbd.filter(|_| true), vec![value, "b".to_owned()]);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn sync() {
+        let filename = "builder_sync.test";
+        std::fs::File::create(filename).unwrap();
+
+        for method in &[SyncMethod::Data, SyncMethod::All] {
+            let mut cbd: Cabide<String> = Cabide::builder()
+                .sync_on_drop(*method)
+                .open(filename)
+                .unwrap();
+            let block = cbd.write(&format!("{:?}", method)).unwrap();
+            cbd.sync_data().unwrap();
+            cbd.sync_all().unwrap();
+            drop(cbd);
+
+            cbd = Cabide::new(filename, None).unwrap();
+            assert_eq!(cbd.read(block).unwrap(), format!("{:?}", method));
+        }
+
+        for mode in &[SyncMode::Never, SyncMode::OnEveryWrite, SyncMode::OnDrop] {
+            let mut cbd: Cabide<String> =
+                Cabide::builder().sync_mode(*mode).open(filename).unwrap();
+            let block = cbd.write(&format!("{:?}", mode)).unwrap();
+            let block = cbd.update(block, &format!("{:?}", mode).repeat(5)).unwrap();
+            cbd.flush().unwrap();
+            drop(cbd);
+
+            cbd = Cabide::new(filename, None).unwrap();
+            assert_eq!(cbd.remove(block).unwrap(), format!("{:?}", mode).repeat(5));
+        }
+
+        // Only `OnEveryWrite` syncs after each change, the others wait for `flush` or the drop
+        for mode in &[SyncMode::Never, SyncMode::OnEveryWrite, SyncMode::OnDrop] {
+            std::fs::File::create(filename).unwrap();
+            let mut cbd: Cabide<String> =
+                Cabide::builder().sync_mode(*mode).open(filename).unwrap();
+            let expected = u64::from(*mode == SyncMode::OnEveryWrite);
+            let synced = |cbd: &mut Cabide<String>, change: &dyn Fn(&mut Cabide<String>)| {
+                let syncs = cbd.file.syncs;
+                change(cbd);
+                assert_eq!(cbd.file.syncs - syncs, expected, "{:?}", mode);
+            };
+            for i in 0..10 {
+                synced(&mut cbd, &|cbd| {
+                    assert_eq!(cbd.write(&i.to_string()).unwrap(), i)
+                });
+            }
+            synced(&mut cbd, &|cbd| assert_eq!(cbd.remove(3).unwrap(), "3"));
+            synced(&mut cbd, &|cbd| {
+                assert_eq!(cbd.update(5, &"five".to_owned()).unwrap(), 5)
+            });
+        }
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn codec_threshold() {
+        let filename = "builder_codec_threshold.test";
+        std::fs::File::create(filename).unwrap();
+        let builder = || {
+            Cabide::<String>::builder()
+                .codec(Xor(0b1010_1010))
+                .codec_threshold(50)
+                .sequence_numbers(true)
+        };
+
+        let mut cbd = builder().open(filename).unwrap();
+        let objects: Vec<(u64, String)> = (0..20)
+            .map(|i| {
+                let obj = format!("{:02}", i).repeat(if i % 2 == 0 { 1 } else { 40 });
+                (cbd.write(&obj).unwrap(), obj)
+            })
+            .collect();
+
+        // Tiny objects are stored as they are, but large ones are transformed
+        let stored = std::fs::read(filename).unwrap();
+        for (i, (_, obj)) in objects.iter().enumerate() {
+            let raw = stored.windows(obj.len()).any(|w| w == obj.as_bytes());
+            assert_eq!(raw, i % 2 == 0);
+        }
+
+        // this drops the last cabide, therefore closes the file
+        cbd = builder().codec_threshold(10).open(filename).unwrap();
+        for (block, obj) in &objects {
+            assert_eq!(&cbd.read(*block).unwrap(), obj);
+        }
+        assert_eq!(cbd.read_seq(3).unwrap(), objects[3].1);
+        let (block, obj) = &objects[4];
+        assert_eq!(&cbd.remove(*block).unwrap(), obj);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn not_seekable() {
+        let dirname = "builder_not_seekable.test";
+        fs::create_dir_all(dirname).unwrap();
+
+        match Cabide::<u8>::new(dirname, None) {
+            Err(err @ Error::NotSeekable { .. }) => assert_eq!(
+                err.to_string(),
+                "builder_not_seekable.test isn't a regular file, it can't be seeked"
+            ),
+            res => panic!("expected not seekable error, got {:?}", res),
+        }
+        fs::remove_dir(dirname).unwrap();
+    }
+
+    #[test]
+    fn atomic_creation() {
+        let filename = Path::new("builder_atomic.test");
+        let _ = fs::remove_file(filename);
+
+        // Simulates a crash after pre-filling, before moving the database into place
+        let cbd = Cabide::<u8>::builder()
+            .prefill(100)
+            .create(filename, &creation_path(filename))
+            .unwrap();
+        drop(cbd);
+        assert!(!filename.exists());
+
+        let cbd: Cabide<u8> = Cabide::new(filename, Some(100)).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 100);
+        assert!(!creation_path(filename).exists());
+        fs::remove_file(filename).unwrap();
+    }
+}
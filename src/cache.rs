@@ -0,0 +1,102 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Least recently read objects, keyed by their starting block, see [`crate::CabideBuilder::read_cache`]
+#[derive(Debug)]
+pub(crate) struct ReadCache<T> {
+    /// Maximum number of objects kept
+    capacity: usize,
+    /// (starting block -> (object, last use)) of every cached object
+    entries: HashMap<u64, (Arc<T>, u64)>,
+    /// (last use -> starting block) of every cached object, the first one is evicted when it's full
+    uses: BTreeMap<u64, u64>,
+    /// Incremented on every use, so uses are ordered
+    clock: u64,
+}
+
+impl<T> ReadCache<T> {
+    /// Creates empty cache that keeps at most `capacity` objects, nothing is kept if it's zero
+    #[inline]
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            uses: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// Returns the object starting at specified block, if it's cached, marking it as the most recently used
+    pub(crate) fn get(&mut self, block: u64) -> Option<Arc<T>> {
+        let (obj, used) = self.entries.get_mut(&block)?;
+        self.uses.remove(used);
+        *used = self.clock;
+        self.uses.insert(self.clock, block);
+        self.clock += 1;
+        Some(Arc::clone(obj))
+    }
+
+    /// Caches the object starting at specified block, evicting the least recently used one if it's full
+    pub(crate) fn insert(&mut self, block: u64, obj: Arc<T>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.invalidate(block);
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.uses.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(block, (obj, self.clock));
+        self.uses.insert(self.clock, block);
+        self.clock += 1;
+    }
+
+    /// Drops the object starting at specified block, if it's cached
+    #[inline]
+    pub(crate) fn invalidate(&mut self, block: u64) {
+        if let Some((_, used)) = self.entries.remove(&block) {
+            self.uses.remove(&used);
+        }
+    }
+
+    /// Drops every cached object
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.uses.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = ReadCache::new(2);
+        cache.insert(1, Arc::new("a"));
+        cache.insert(2, Arc::new("b"));
+        assert_eq!(cache.get(1).as_deref(), Some(&"a"));
+
+        // 2 wasn't used since 1 was read
+        cache.insert(3, Arc::new("c"));
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(1).as_deref(), Some(&"a"));
+        assert_eq!(cache.get(3).as_deref(), Some(&"c"));
+
+        // Replacing an object doesn't evict another one
+        cache.insert(3, Arc::new("d"));
+        assert_eq!(cache.get(1).as_deref(), Some(&"a"));
+        assert_eq!(cache.get(3).as_deref(), Some(&"d"));
+
+        cache.invalidate(1);
+        assert_eq!(cache.get(1), None);
+        cache.clear();
+        assert_eq!(cache.get(3), None);
+
+        let mut cache = ReadCache::new(0);
+        cache.insert(1, Arc::new("a"));
+        assert_eq!(cache.get(1), None);
+    }
+}
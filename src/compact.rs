@@ -0,0 +1,513 @@
+use crate::protocol::Metadata;
+use crate::reader::BlockReader;
+use crate::{Cabide, Error};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::{fs, fs::OpenOptions, path::Path, path::PathBuf};
+
+/// Progress of a resumable compaction, persisted to a sidecar file so interrupted runs can resume
+#[derive(Default, Debug)]
+struct Progress {
+    /// Next block to be read, every object before it has been moved
+    read: u64,
+    /// Next block to be written
+    write: u64,
+    /// Segment being moved, must be (re-)applied before continuing
+    pending: Option<Pending>,
+}
+
+/// Segment read from the file, kept in the sidecar so moving it can be replayed after a crash
+#[derive(Debug)]
+struct Pending {
+    /// First block read for this segment
+    from: u64,
+    /// Block where the segment is written to
+    to: u64,
+    /// Raw blocks of every object in the segment
+    raw: Vec<u8>,
+}
+
+impl Progress {
+    /// Encodes progress with little endian integers, so sidecars are portable between platforms
+    ///
+    /// `[read][write][0 or 1]`, followed by `[from][to][raw length][raw]` if there is a pending segment
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend(&self.read.to_le_bytes());
+        bytes.extend(&self.write.to_le_bytes());
+        match &self.pending {
+            Some(pending) => {
+                bytes.push(1);
+                bytes.extend(&pending.from.to_le_bytes());
+                bytes.extend(&pending.to.to_le_bytes());
+                bytes.extend(&(pending.raw.len() as u64).to_le_bytes());
+                bytes.extend(&pending.raw);
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+
+    /// Decodes progress encoded by `to_bytes`
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let (read, write) = (read_u64(bytes, 0)?, read_u64(bytes, 8)?);
+        let pending = match bytes.get(16) {
+            Some(0) => None,
+            Some(1) => {
+                let len =
+                    usize::try_from(read_u64(bytes, 33)?).map_err(|_| Error::CorruptedBlock)?;
+                let raw = bytes.get(41..).filter(|raw| raw.len() == len);
+                Some(Pending {
+                    from: read_u64(bytes, 17)?,
+                    to: read_u64(bytes, 25)?,
+                    raw: raw.ok_or(Error::CorruptedBlock)?.to_vec(),
+                })
+            }
+            _ => return Err(Error::CorruptedBlock),
+        };
+        Ok(Self {
+            read,
+            write,
+            pending,
+        })
+    }
+}
+
+/// Reads the little endian integer that starts at specified byte
+#[inline]
+fn read_u64(bytes: &[u8], start: usize) -> Result<u64, Error> {
+    let int = bytes.get(start..start + 8).ok_or(Error::CorruptedBlock)?;
+    let int = <[u8; 8]>::try_from(int).map_err(|_| Error::CorruptedBlock)?;
+    Ok(u64::from_le_bytes(int))
+}
+
+/// Path of the sidecar that holds the compaction progress, databases bound to a range of the file (see
+/// `Cabide::new_at`) have their own, named after the range's offset
+#[inline]
+fn sidecar_path(path: &Path, range: Option<u64>) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    if let Some(offset) = range {
+        path.push(format!(".{}", offset));
+    }
+    path.push(".compact");
+    path.into()
+}
+
+/// Atomically replaces the sidecar with the current progress
+fn save_progress(sidecar: &Path, progress: &Progress) -> Result<(), Error> {
+    let mut temp = sidecar.as_os_str().to_owned();
+    temp.push(".tmp");
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temp)?;
+    file.write_all(&progress.to_bytes())?;
+    file.sync_data()?;
+    fs::rename(&temp, sidecar)?;
+    Ok(())
+}
+
+impl<T, const B: usize> Cabide<T, B> {
+    /// Moves every object to the start of the file (keeping their order) and truncates it, getting rid of
+    /// the empty blocks in the middle of the file
+    ///
+    /// Works in segments of `segment_blocks` blocks, only one segment is kept in memory (and in the sidecar
+    /// file, `<file>.compact`) at a time, so no big amount of extra space is needed
+    ///
+    /// Progress is persisted after each segment, if interrupted calling it again resumes the compaction,
+    /// the database must not be written to before the compaction finishes
+    ///
+    /// Block numbers change, so any external reference to them is invalidated
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test13.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test13.file", None)?;
+    ///
+    /// for i in 0..100 {
+    ///     cbd.write(&i)?;
+    /// }
+    ///
+    /// for i in 0..50 {
+    ///     cbd.remove(i * 2)?;
+    /// }
+    ///
+    /// cbd.compact_resumable(10)?;
+    /// assert_eq!(cbd.blocks()?, 50);
+    /// assert_eq!(cbd.read(0)?, 1);
+    /// assert_eq!(cbd.read(49)?, 99);
+    /// # std::fs::remove_file("test13.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compact_resumable(&mut self, segment_blocks: u64) -> Result<(), Error> {
+        self.compact_segments(segment_blocks, None)
+    }
+
+    /// Same as `compact_resumable`, but runs to the end at once and returns the `(old, new)` starting blocks of
+    /// every object, in their new order, so external references to them can be fixed
+    ///
+    /// Segments have as many blocks as scans read at once (see [`CabideBuilder::scan_chunk_size`]), it must not
+    /// be called while a resumable compaction is unfinished
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test62.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test62.file", Some(100))?;
+    /// for i in 0..5 {
+    ///     cbd.write(&i)?;
+    /// }
+    /// cbd.remove(1)?;
+    /// cbd.remove(3)?;
+    ///
+    /// assert_eq!(cbd.compact()?, vec![(0, 0), (2, 1), (4, 2)]);
+    /// assert_eq!(cbd.blocks()?, 3);
+    /// assert_eq!(cbd.read(2)?, 4);
+    /// # std::fs::remove_file("test62.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`CabideBuilder::scan_chunk_size`]: crate::CabideBuilder::scan_chunk_size
+    pub fn compact(&mut self) -> Result<Vec<(u64, u64)>, Error> {
+        // Fragmented objects are moved before the others, so they are traced back to where they were
+        let defragmented: BTreeMap<u64, u64> = self
+            .defragment()?
+            .into_iter()
+            .map(|(old, new)| (new, old))
+            .collect();
+        let before = self.object_blocks()?;
+        self.compact_resumable(self.scan_chunk_size / Self::BLOCK_SIZE)?;
+        let after = self.object_blocks()?;
+        if before.len() != after.len() {
+            return Err(Error::CorruptedBlock);
+        }
+
+        Ok(before
+            .into_iter()
+            .map(|old| defragmented.get(&old).copied().unwrap_or(old))
+            .zip(after)
+            .collect())
+    }
+
+    /// Rewrites every fragmented object to continuous blocks, since compaction moves blocks without following
+    /// the links between their extents, returns the `(old, new)` starting blocks of the rewritten objects
+    ///
+    /// Each copy is written before the fragmented object is removed, so a crash may duplicate it, but never
+    /// loses it
+    fn defragment(&mut self) -> Result<Vec<(u64, u64)>, Error> {
+        let mut fragmented = false;
+        for block in 0..self.written_blocks() {
+            fragmented |= self.read_metadata(block)? == Some(Metadata::Fragment as u8);
+        }
+        if !fragmented {
+            return Ok(vec![]);
+        }
+
+        let (fragmentation, mut framed) = (self.fragmentation, vec![]);
+        self.fragmentation = false;
+        let (mut moved, mut result) = (vec![], Ok(()));
+        for block in self.object_blocks()? {
+            match self.defragment_object(block, &mut framed) {
+                Ok(Some(new)) => moved.push((block, new)),
+                Ok(None) => {}
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        self.fragmentation = fragmentation;
+        result.map(|_| moved)
+    }
+
+    /// Copies the object at specified block to continuous blocks, if it's fragmented, returns its new block
+    fn defragment_object(
+        &mut self,
+        block: u64,
+        framed: &mut Vec<u8>,
+    ) -> Result<Option<u64>, Error> {
+        let mut raw = vec![];
+        let mut reader = BlockReader::new(self, block, false)?;
+        let result = reader.read_to_end(&mut raw).map_err(Error::from);
+        let fragmented = reader.fragmented;
+        reader.finish(result)?;
+        if !fragmented {
+            return Ok(None);
+        }
+
+        let new = self.write_raw(&raw, 1, framed)?;
+        let mut reader = BlockReader::new(self, block, true)?;
+        let result = io::copy(&mut reader, &mut io::sink()).map_err(Error::from);
+        reader.finish(result)?;
+        Ok(Some(new))
+    }
+
+    /// Compacts the file, stopping after `max_segments` segments if specified (simulating an interruption)
+    fn compact_segments(
+        &mut self,
+        segment_blocks: u64,
+        mut max_segments: Option<u64>,
+    ) -> Result<(), Error> {
+        // Objects are moved without being removed
+        self.clear_read_cache();
+        // Databases in memory can't be resumed, so their progress isn't persisted
+        let range = self.len.map(|_| self.offset - self.header_size);
+        let sidecar = self.path.as_deref().map(|path| sidecar_path(path, range));
+        let saved = match &sidecar {
+            Some(sidecar) => match fs::read(sidecar) {
+                Ok(bytes) => Some(Progress::from_bytes(&bytes)?),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err.into()),
+            },
+            None => None,
+        };
+        let mut progress = match saved {
+            Some(progress) => progress,
+            None => {
+                self.defragment()?;
+                Progress::default()
+            }
+        };
+        let save = |progress: &Progress| match &sidecar {
+            Some(sidecar) => save_progress(sidecar, progress),
+            None => Ok(()),
+        };
+        let (segment_blocks, blocks) = (segment_blocks.max(1), self.blocks()?);
+
+        loop {
+            if let Some(pending) = progress.pending.take() {
+                self.seek_block(pending.to)?;
+                self.file.write_all(&pending.raw)?;
+
+                // Blocks that had their objects moved, but weren't overwritten, are now empty
+                for block in progress.write.max(pending.from)..progress.read {
+                    self.seek_block(block)?;
+                    self.file.write_all(&[Metadata::Empty as u8])?;
+                }
+
+                self.file.sync_data()?;
+                save(&progress)?;
+            }
+
+            if progress.read >= blocks {
+                break;
+            }
+
+            if let Some(segments) = &mut max_segments {
+                if *segments == 0 {
+                    return Ok(());
+                }
+                *segments -= 1;
+            }
+
+            // Gathers every object that starts in the segment (and its continuations)
+            let end = progress.read.saturating_add(segment_blocks);
+            let (mut raw, mut block, mut in_object) = (vec![], progress.read, false);
+            while block < blocks {
+                let mut content = Vec::with_capacity(Self::BLOCK_SIZE as usize);
+                self.seek_block(block)?;
+                Read::by_ref(&mut self.file)
+                    .take(Self::BLOCK_SIZE)
+                    .read_to_end(&mut content)?;
+                content.resize(Self::BLOCK_SIZE as usize, Metadata::Empty as u8);
+
+                if Metadata::is_start(content[0]) {
+                    if block >= end {
+                        break;
+                    }
+                    in_object = true;
+                } else if content[0] != Metadata::Continuation as u8 {
+                    in_object = false;
+                }
+
+                if in_object {
+                    raw.extend(content);
+                }
+                block += 1;
+            }
+
+            let pending = Pending {
+                from: progress.read,
+                to: progress.write,
+                raw,
+            };
+            progress.read = block;
+            progress.write += pending.raw.len() as u64 / Self::BLOCK_SIZE;
+            progress.pending = Some(pending);
+            save(&progress)?;
+        }
+
+        self.set_blocks(progress.write)?;
+        self.file.sync_all()?;
+        self.next_block = progress.write;
+        self.clear_free_runs();
+        // Objects were moved, so their blocks must be indexed again
+        if self.sequence_index.is_some() {
+            self.index_sequences()?;
+        }
+        if self.next_id.is_some() {
+            self.index_ids()?;
+        }
+        if let Some(sidecar) = &sidecar {
+            fs::remove_file(sidecar)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::serialize;
+
+    fn fill(filename: &str) -> Vec<String> {
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<String> = Cabide::new(filename, None).unwrap();
+
+        let mut values = vec![];
+        for i in 0..60 {
+            let value = format!("{:02}", i).repeat(i % 20 + 1);
+            values.push((cbd.write(&value).unwrap(), value));
+        }
+
+        for (block, _) in values.iter().step_by(3) {
+            cbd.remove(*block).unwrap();
+        }
+
+        values
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| i % 3 != 0)
+            .map(|(_, (_, value))| value)
+            .collect()
+    }
+
+    #[test]
+    fn progress_bytes() {
+        let progress = Progress {
+            read: 0x0102,
+            write: 1,
+            pending: Some(Pending {
+                from: 2,
+                to: 3,
+                raw: vec![4, 5],
+            }),
+        };
+        let bytes = progress.to_bytes();
+        assert_eq!(&bytes[..8], &[2, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&bytes[33..], &[2, 0, 0, 0, 0, 0, 0, 0, 4, 5]);
+
+        let decoded = Progress::from_bytes(&bytes).unwrap();
+        assert_eq!((decoded.read, decoded.write), (0x0102, 1));
+        let pending = decoded.pending.unwrap();
+        assert_eq!((pending.from, pending.to, pending.raw), (2, 3, vec![4, 5]));
+        assert!(Progress::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Progress::from_bytes(&Progress::default().to_bytes())
+            .unwrap()
+            .pending
+            .is_none());
+    }
+
+    #[test]
+    fn resumable() {
+        let (interrupted, one_shot) = ("compact_interrupted.test", "compact_one_shot.test");
+        let values = fill(interrupted);
+        fs::copy(interrupted, one_shot).unwrap();
+
+        let mut cbd: Cabide<String> = Cabide::new(interrupted, None).unwrap();
+        cbd.compact_segments(7, Some(3)).unwrap();
+        assert!(sidecar_path(Path::new(interrupted), None).is_file());
+
+        // this drops the last cabide, therefore closes the file
+        cbd = Cabide::new(interrupted, None).unwrap();
+        cbd.compact_resumable(7).unwrap();
+        assert!(!sidecar_path(Path::new(interrupted), None).exists());
+        assert_eq!(cbd.filter(|_| true), values);
+
+        let mut other: Cabide<String> = Cabide::new(one_shot, None).unwrap();
+        other.compact_resumable(u64::MAX).unwrap();
+        assert_eq!(fs::read(interrupted).unwrap(), fs::read(one_shot).unwrap());
+
+        fs::remove_file(interrupted).unwrap();
+        fs::remove_file(one_shot).unwrap();
+    }
+
+    #[test]
+    fn resumable_ranges() {
+        let filename = "compact_ranges.test";
+        std::fs::File::create(filename).unwrap();
+        let region = 400 * crate::protocol::BLOCK_SIZE;
+        let open = |offset| Cabide::<String>::new_at(filename, offset, region, None).unwrap();
+        let (mut first, mut second) = (open(0), open(region));
+        let mut values = (vec![], vec![]);
+        for i in 0..60 {
+            let value = format!("{:02}", i).repeat(i % 20 + 1);
+            values.0.push((first.write(&value).unwrap(), value.clone()));
+            values.1.push((second.write(&value).unwrap(), value));
+        }
+        for (block, _) in values.0.iter().step_by(3) {
+            first.remove(*block).unwrap();
+        }
+        for (block, _) in values.1.iter().step_by(2) {
+            second.remove(*block).unwrap();
+        }
+
+        let expected = |values: &[(u64, String)], step| -> Vec<String> {
+            let kept = values.iter().enumerate().filter(|(i, _)| i % step != 0);
+            kept.map(|(_, (_, value))| value.clone()).collect()
+        };
+
+        // Each range resumes its own compaction, never the other's
+        first.compact_segments(7, Some(3)).unwrap();
+        second.compact_resumable(7).unwrap();
+        assert_eq!(second.filter(|_| true), expected(&values.1, 2));
+        assert!(sidecar_path(Path::new(filename), Some(0)).is_file());
+        first.compact_resumable(7).unwrap();
+        assert!(!sidecar_path(Path::new(filename), Some(0)).exists());
+        assert_eq!(first.filter(|_| true), expected(&values.0, 3));
+        assert_eq!(second.filter(|_| true), expected(&values.1, 2));
+        drop((first, second));
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn remapped() {
+        let filename = "compact_remapped.test";
+        fill(filename);
+        let mut cbd: Cabide<String> = Cabide::builder()
+            .fragmentation(true)
+            .prefill(500)
+            .open(filename)
+            .unwrap();
+        // No free run fits it, so it's fragmented
+        let fragmented = cbd.write(&"fragmented".repeat(10)).unwrap();
+        let objects: BTreeMap<u64, String> = cbd.filter_with_blocks(|_| true).into_iter().collect();
+        assert!(cbd.read_metadata(fragmented + 1).unwrap() != Some(Metadata::Continuation as u8));
+
+        // Continuous objects need no room for links
+        let needed: u64 = objects
+            .values()
+            .map(|obj| cbd.needed_blocks(&serialize(obj).unwrap()))
+            .sum();
+        let mapping = cbd.compact().unwrap();
+        assert_eq!(cbd.blocks().unwrap(), needed);
+        assert_eq!(mapping.len(), objects.len());
+        for (old, new) in &mapping {
+            assert_eq!(&cbd.read(*new).unwrap(), &objects[old]);
+        }
+        // Order is kept, but the fragmented object was moved after the others
+        let news: Vec<u64> = mapping.iter().map(|(_, new)| *new).collect();
+        assert_eq!(news, cbd.object_blocks().unwrap());
+        assert_eq!(mapping.last().unwrap().0, fragmented);
+        assert_eq!(cbd.write(&"appended".to_owned()).unwrap(), needed);
+        std::fs::remove_file(filename).unwrap();
+    }
+}
@@ -0,0 +1,409 @@
+use crate::Error;
+use memmap2::Mmap;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Deref;
+use std::path::PathBuf;
+
+/// Where the database's bytes are kept
+#[derive(Debug)]
+pub(crate) enum Storage {
+    /// Regular file, the only one that persists
+    File(File),
+    /// Buffer in memory, see `Cabide::from_bytes`
+    Memory(Cursor<Vec<u8>>),
+}
+
+impl Storage {
+    /// Returns length in bytes of the storage
+    #[inline]
+    pub(crate) fn len(&self) -> io::Result<u64> {
+        match self {
+            Self::File(file) => Ok(file.metadata()?.len()),
+            Self::Memory(cursor) => Ok(cursor.get_ref().len() as u64),
+        }
+    }
+
+    /// Truncates or extends the storage, new bytes are zeroed
+    #[inline]
+    pub(crate) fn set_len(&mut self, len: u64) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.set_len(len),
+            Self::Memory(cursor) => {
+                let len = usize::try_from(len).map_err(|_| {
+                    io::Error::new(io::ErrorKind::OutOfMemory, "Buffer is too long")
+                })?;
+                cursor.get_mut().resize(len, 0);
+                Ok(())
+            }
+        }
+    }
+
+    /// Uses `File::sync_data`, memory has nothing to sync
+    #[inline]
+    pub(crate) fn sync_data(&self) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.sync_data(),
+            Self::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Uses `File::sync_all`, memory has nothing to sync
+    #[inline]
+    pub(crate) fn sync_all(&self) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.sync_all(),
+            Self::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Returns the file, if the storage is one
+    #[inline]
+    pub(crate) fn as_file(&self) -> Option<&File> {
+        match self {
+            Self::File(file) => Some(file),
+            Self::Memory(_) => None,
+        }
+    }
+}
+
+impl Read for Storage {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.read(buf),
+            Self::Memory(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Write for Storage {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.write(buf),
+            Self::Memory(cursor) => cursor.write(buf),
+        }
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            Self::Memory(cursor) => cursor.flush(),
+        }
+    }
+}
+
+impl Seek for Storage {
+    #[inline]
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(file) => file.seek(pos),
+            Self::Memory(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+/// Storage that keeps track of where its cursor is, so seeking to where it already is doesn't need a syscall
+///
+/// Sequential reads (like `Cabide::filter` going block by block) mostly seek to where the last read stopped,
+/// if the file is mapped reads inside the map don't touch the file at all
+#[derive(Debug)]
+pub(crate) struct TrackedFile {
+    /// Storage being read from and written to
+    file: Storage,
+    /// Position of the file's cursor, `None` if unknown (like after an error)
+    position: Option<u64>,
+    /// Number of seeks that actually moved the cursor
+    pub(crate) seeks: u64,
+    /// Number of bytes read
+    pub(crate) read_bytes: u64,
+    /// Number of reads that weren't served by the map, so they needed a syscall
+    pub(crate) read_calls: u64,
+    /// Number of times the storage was synced to disk
+    pub(crate) syncs: u64,
+    /// Read-only map of the file, if reads are served by it, see `CabideBuilder::mmap`
+    map: Option<Mmap>,
+    /// If the file may have grown past the map, so it's remapped before falling back to file IO
+    map_stale: bool,
+    /// If the file's cursor isn't at `position`, since seeks and reads served by the map don't move it
+    desynced: bool,
+    /// Sidecar removed before the storage is first changed, since it would no longer describe it
+    pub(crate) stale_on_write: Option<PathBuf>,
+    /// If the storage was opened without write access, see `Cabide::open_read_only`
+    pub(crate) read_only: bool,
+}
+
+impl TrackedFile {
+    /// Wraps storage, its cursor's position is only known after the first seek
+    #[inline]
+    pub(crate) fn new(file: Storage) -> Self {
+        Self {
+            file,
+            position: None,
+            seeks: 0,
+            read_bytes: 0,
+            read_calls: 0,
+            syncs: 0,
+            map: None,
+            map_stale: false,
+            desynced: false,
+            stale_on_write: None,
+            read_only: false,
+        }
+    }
+
+    /// Fails with `Error::ReadOnly` if the storage can't be changed
+    #[inline]
+    pub(crate) fn writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Waits for the contents of the storage to reach the disk, see [`Storage::sync_data`]
+    #[inline]
+    pub(crate) fn sync_data(&mut self) -> io::Result<()> {
+        self.syncs += 1;
+        self.file.sync_data()
+    }
+
+    /// Waits for the contents and metadata of the storage to reach the disk, see [`Storage::sync_all`]
+    #[inline]
+    pub(crate) fn sync_all(&mut self) -> io::Result<()> {
+        self.syncs += 1;
+        self.file.sync_all()
+    }
+
+    /// Maps the file read-only, so reads inside the map don't need syscalls, does nothing for memory
+    pub(crate) fn map(&mut self) -> io::Result<()> {
+        // Map must be gone before the file shrinks, accessing it past the end of the file is a SIGBUS
+        self.map = None;
+        self.map_stale = false;
+        if let Storage::File(file) = &self.file {
+            // Only this handle changes the file while it's mapped, see `CabideBuilder::mmap`
+            self.map = Some(unsafe { Mmap::map(file)? });
+        }
+        Ok(())
+    }
+
+    /// Truncates or extends the storage, see [`Storage::set_len`]
+    #[inline]
+    pub(crate) fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.writable()?;
+        self.invalidate()?;
+        if self.map.is_none() {
+            return self.file.set_len(len);
+        }
+        self.map = None;
+        self.file.set_len(len)?;
+        self.map()
+    }
+
+    /// Moves the file's cursor to `position`, if reads served by the map or seeks left it behind
+    #[inline]
+    fn sync_cursor(&mut self) -> io::Result<()> {
+        if let (true, Some(position)) = (self.desynced, self.position) {
+            self.seeks += 1;
+            self.position = None;
+            self.file.seek(SeekFrom::Start(position))?;
+            self.position = Some(position);
+        }
+        self.desynced = false;
+        Ok(())
+    }
+
+    /// Copies from the map at the current position, `None` if it's unknown or past the map
+    fn read_mapped(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        let position = match self.position {
+            Some(position) if self.map.is_some() => position,
+            _ => return Ok(None),
+        };
+        let mapped = self.map.as_ref().map_or(0, |map| map.len() as u64);
+        if position >= mapped && self.map_stale {
+            self.map()?;
+        }
+
+        let map = match &self.map {
+            Some(map) => map,
+            None => return Ok(None),
+        };
+        let start = match usize::try_from(position) {
+            Ok(start) if start < map.len() => start,
+            _ => return Ok(None),
+        };
+        let bytes = buf.len().min(map.len() - start);
+        buf[..bytes].copy_from_slice(&map[start..start + bytes]);
+        self.desynced = true;
+        Ok(Some(bytes))
+    }
+
+    /// Removes the sidecar that is stale once the storage changes, if there is one
+    #[inline]
+    fn invalidate(&mut self) -> io::Result<()> {
+        if let Some(sidecar) = &self.stale_on_write {
+            match fs::remove_file(sidecar) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => self.stale_on_write = None,
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the buffer out of a storage in memory, leaving it empty, returns `None` for files
+    #[inline]
+    pub(crate) fn take_bytes(&mut self) -> Option<Vec<u8>> {
+        self.position = None;
+        match &mut self.file {
+            Storage::File(_) => None,
+            Storage::Memory(cursor) => Some(std::mem::take(cursor.get_mut())),
+        }
+    }
+
+    /// Moves the position by the number of bytes read or written, it's unknown if they failed
+    #[inline]
+    fn advance(&mut self, result: io::Result<usize>) -> io::Result<usize> {
+        self.position = match &result {
+            Ok(bytes) => self.position.map(|position| position + *bytes as u64),
+            Err(_) => None,
+        };
+        result
+    }
+}
+
+impl Deref for TrackedFile {
+    type Target = Storage;
+
+    #[inline(always)]
+    fn deref(&self) -> &Storage {
+        &self.file
+    }
+}
+
+impl Read for TrackedFile {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = match self.read_mapped(buf)? {
+            Some(bytes) => Ok(bytes),
+            None => {
+                self.sync_cursor()?;
+                self.read_calls += 1;
+                self.file.read(buf)
+            }
+        };
+        if let Ok(bytes) = &result {
+            self.read_bytes += *bytes as u64;
+        }
+        self.advance(result)
+    }
+}
+
+impl Write for TrackedFile {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writable()?;
+        self.invalidate()?;
+        self.sync_cursor()?;
+        let result = self.file.write(buf);
+        let result = self.advance(result);
+        // Map sees writes inside it, but the file may have grown past it
+        if let (Some(map), Some(position)) = (&self.map, self.position) {
+            self.map_stale |= position > map.len() as u64;
+        }
+        result
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for TrackedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if let (SeekFrom::Start(start), Some(position)) = (pos, self.position) {
+            if start == position {
+                return Ok(position);
+            }
+        }
+        // Reads served by the map don't need the file's cursor, it's only moved before a syscall
+        if let (SeekFrom::Start(start), Some(_)) = (pos, &self.map) {
+            self.desynced = true;
+            self.position = Some(start);
+            return Ok(start);
+        }
+        self.sync_cursor()?;
+
+        self.seeks += 1;
+        self.position = None;
+        let position = self.file.seek(pos)?;
+        self.position = Some(position);
+        Ok(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Cabide;
+
+    #[test]
+    fn filter_skips_seeks() {
+        std::fs::File::create("cursor_filter.test").unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new("cursor_filter.test", None).unwrap();
+        for i in 0..100 {
+            cbd.write(&vec![i; (i % 3) as usize * 30]).unwrap();
+        }
+        let blocks = cbd.blocks().unwrap();
+
+        // Without tracking every object needed at least 3 seeks, plus one for each block after the first
+        cbd.file.seeks = 0;
+        assert_eq!(cbd.filter(|_| true).len(), 100);
+        assert!(cbd.file.seeks <= blocks, "{} > {}", cbd.file.seeks, blocks);
+
+        // Seeks that didn't need a syscall still move the cursor to the right place
+        let objects = cbd.filter_with_blocks(|_| true);
+        for (block, obj) in objects.into_iter().rev() {
+            assert_eq!(cbd.read(block).unwrap(), obj);
+        }
+        std::fs::remove_file("cursor_filter.test").unwrap();
+    }
+
+    #[test]
+    fn mmap_skips_reads() {
+        let filename = "cursor_mmap.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<u32> = Cabide::builder().mmap(true).open(filename).unwrap();
+        for i in 0..100_000 {
+            cbd.write(&i).unwrap();
+        }
+        cbd.remove(500).unwrap();
+
+        // File grew past the map while writing, so the first read past it remaps
+        cbd.file.read_calls = 0;
+        cbd.file.seeks = 0;
+        assert_eq!(cbd.filter(|_| true).len(), 99_999);
+        // Only finding the end of the file needs one, blocks past the map could have been written by others
+        assert!(cbd.file.read_calls <= 1 && cbd.file.seeks <= 1);
+        cbd.file.read_calls = 0;
+        assert_eq!(cbd.first(|i| *i > 99_990), Some(99_991));
+        assert_eq!(cbd.file.read_calls, 0);
+
+        // Writes inside the map are seen by it, shrinking the file remaps it
+        assert_eq!(cbd.write(&7).unwrap(), 500);
+        assert_eq!(cbd.read(500).unwrap(), 7);
+        cbd.truncate().unwrap();
+        assert_eq!(cbd.write(&1).unwrap(), 0);
+        assert_eq!(cbd.filter(|_| true), vec![1]);
+        drop(cbd);
+
+        // Without the map every block needs a read
+        let mut cbd: Cabide<u32> = Cabide::new(filename, Some(100_000)).unwrap();
+        cbd.file.read_calls = 0;
+        assert_eq!(cbd.filter(|_| true), vec![1]);
+        assert!(cbd.file.read_calls > 0);
+        std::fs::remove_file(filename).unwrap();
+    }
+}
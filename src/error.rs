@@ -1,24 +1,93 @@
-use std::{fmt, io};
+use std::{fmt, io, path::PathBuf};
 
 /// Enumerates all errors possible in this crate
 #[derive(Debug)]
 pub enum Error {
     /// IO errors, the basis of this crate since everything is binded to a file
     Io(io::Error),
-    /// Means deserialization failed, file is either corrupted or the type is wrong
+    /// Means the blocks are corrupted, their framing (or the fields stored with the object) is invalid
     CorruptedBlock,
+    /// (De)serialization of an object failed, it holds bincode's reason (like an invalid enum tag or UTF-8),
+    /// when reading, the file is either corrupted or the type is wrong
+    Serde(bincode::Error),
     /// Happens if you try to read from a block that is in the middle of an object
-    ContinuationBlock,
+    ContinuationBlock { block: u64 },
     /// Happens if you try to read from a empty block
-    EmptyBlock,
+    EmptyBlock { block: u64 },
     /// Happens if you try to read from a non-existing block or file
     NotExistant,
+    /// HashCabide was written by a different hash function than the one it's being opened with
+    HashMismatch { stored: String, given: String },
+    /// Object needs more than the `max` bytes allowed, `claimed` is its stored length, `None` if it has none
+    /// (objects written before lengths were stored, the decoder only reports that the limit was exceeded)
+    ObjectTooLarge { claimed: Option<u64>, max: u64 },
+    /// Object starting at `block` spans more blocks than allowed, its chain of continuations is corrupted
+    ObjectTooLong { block: u64 },
+    /// OrderCabide's order function isn't a total order (like floats compared with NaN), so it can't be sorted
+    InvalidOrdering,
+    /// Database must be binded to a regular file, since every access seeks to a block
+    NotSeekable { path: PathBuf },
+    /// Objects were stored with a different layout than the type's (`stored` is `None` if the file has none),
+    /// see `CabideBuilder::layout_check`
+    LayoutChanged { stored: Option<u64>, given: u64 },
+    /// Bounded database (see `Cabide::new_at`) has no room for the `needed` blocks, only `available` are left
+    OutOfSpace { needed: u64, available: u64 },
+    /// Happens if you try to read from a block past the end of the database, that only has `blocks` blocks
+    BlockOutOfBounds { block: u64, blocks: u64 },
+    /// Block size has no room for the metadata and the content, see `protocol::MIN_BLOCK_SIZE`
+    InvalidBlockSize { block_size: u64 },
+    /// File was written with blocks of a different size than the `BLOCK_SIZE` parameter of `Cabide`
+    BlockSizeMismatch { stored: u64, given: u64 },
+    /// File has objects but no header, so there is no room to store the header's fields, only empty files
+    /// can get one
+    MissingHeader,
+    /// User metadata has `len` bytes, but the header only has room for `max`, see `Cabide::set_user_metadata`
+    UserMetadataTooLarge { len: u64, max: u64 },
+    /// Object was rewritten since it had the `expected` sequence number, it now has `stored`, see
+    /// `Cabide::update_if`
+    VersionConflict { expected: u64, stored: u64 },
+    /// File would grow to `needed` bytes, past the `max` allowed, see `CabideBuilder::max_bytes`
+    SizeLimitExceeded { needed: u64, max: u64 },
+    /// Object can't be written where it was asked to, since `block` belongs to another object, see
+    /// `Cabide::write_at`
+    BlockInUse { block: u64 },
+    /// Object starting at `block` doesn't match its checksum, its blocks were corrupted after being written,
+    /// see `CabideBuilder::checksums`
+    ChecksumMismatch { block: u64 },
+    /// Database can't be changed, since it was opened with `Cabide::open_read_only`
+    ReadOnly,
 }
 
 impl From<io::Error> for Error {
     #[inline(always)]
     fn from(io: io::Error) -> Self {
-        Self::Io(io)
+        // Errors of this crate that went through `io::Error` (like the ones returned by `Read` impls) are
+        // unwrapped back
+        if !io.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            return Self::Io(io);
+        }
+        match io.into_inner().map(|inner| inner.downcast::<Error>()) {
+            Some(Ok(err)) => *err,
+            _ => unreachable!("io error was checked to wrap an Error"),
+        }
+    }
+}
+
+impl From<bincode::Error> for Error {
+    #[inline(always)]
+    fn from(err: bincode::Error) -> Self {
+        Self::Serde(err)
+    }
+}
+
+impl From<Error> for io::Error {
+    /// Keeps IO errors as they were, the others mean the data (or how it's being interpreted) is invalid
+    #[inline]
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(io) => io,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
     }
 }
 
@@ -26,16 +95,196 @@ impl fmt::Display for Error {
     #[inline]
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::Io(err) => write!(fmt, "{}", err.to_string()),
-            Error::CorruptedBlock => write!(
+            Error::Io(err) => write!(fmt, "{}", err),
+            Error::CorruptedBlock => write!(fmt, "Unable to parse a block, file is corrupted"),
+            Error::Serde(err) => write!(fmt, "Unable to (de)serialize an object: {}", err),
+            Error::ContinuationBlock { block } => {
+                write!(fmt, "Block {} is in the middle of an object", block)
+            }
+            Error::EmptyBlock { block } => write!(fmt, "Block {} is empty", block),
+            Error::NotExistant => write!(fmt, "Block/file doesn't exist"),
+            Error::HashMismatch { stored, given } => write!(
                 fmt,
-                "Unable to deserialize a block, file is corrupted or type is wrong"
+                "Data was hashed by \"{}\", but \"{}\" was given",
+                stored, given
             ),
-            Error::ContinuationBlock => write!(fmt, "Continuation Block"),
-            Error::EmptyBlock => write!(fmt, "Empty Block"),
-            Error::NotExistant => write!(fmt, "Block/file doesn't exist"),
+            Error::ObjectTooLarge {
+                claimed: Some(claimed),
+                max,
+            } => write!(
+                fmt,
+                "Object claims {} bytes, but at most {} are allowed",
+                claimed, max
+            ),
+            Error::ObjectTooLarge { claimed: None, max } => {
+                write!(fmt, "Object claims more than the {} bytes allowed", max)
+            }
+            Error::ObjectTooLong { block } => write!(
+                fmt,
+                "Object at block {} spans more blocks than allowed",
+                block
+            ),
+            Error::InvalidOrdering => write!(
+                fmt,
+                "Order function isn't a total order, data can't be sorted by it"
+            ),
+            Error::NotSeekable { path } => write!(
+                fmt,
+                "{} isn't a regular file, it can't be seeked",
+                path.display()
+            ),
+            Error::LayoutChanged {
+                stored: Some(stored),
+                given,
+            } => write!(
+                fmt,
+                "Objects were stored with layout {:016x}, but the type's layout is {:016x}",
+                stored, given
+            ),
+            Error::LayoutChanged {
+                stored: None,
+                given,
+            } => write!(
+                fmt,
+                "File has no layout stored, so the type's layout {:016x} can't be checked",
+                given
+            ),
+            Error::OutOfSpace { needed, available } => write!(
+                fmt,
+                "Database's range is full, {} blocks are needed but {} are available",
+                needed, available
+            ),
+            Error::BlockOutOfBounds { block, blocks } => write!(
+                fmt,
+                "Block {} is out of bounds, database has {} blocks",
+                block, blocks
+            ),
+            Error::InvalidBlockSize { block_size } => write!(
+                fmt,
+                "Block size {} is invalid, blocks need at least {} bytes",
+                block_size,
+                crate::protocol::MIN_BLOCK_SIZE
+            ),
+            Error::BlockSizeMismatch { stored, given } => write!(
+                fmt,
+                "File has blocks of {} bytes, but blocks of {} bytes were required",
+                stored, given
+            ),
+            Error::MissingHeader => {
+                write!(fmt, "File has objects but no header to store fields in")
+            }
+            Error::UserMetadataTooLarge { len, max } => write!(
+                fmt,
+                "User metadata has {} bytes, but at most {} are allowed",
+                len, max
+            ),
+            Error::VersionConflict { expected, stored } => write!(
+                fmt,
+                "Object was expected to have version {}, but it has version {}",
+                expected, stored
+            ),
+            Error::SizeLimitExceeded { needed, max } => write!(
+                fmt,
+                "File would grow to {} bytes, but at most {} are allowed",
+                needed, max
+            ),
+            Error::BlockInUse { block } => {
+                write!(fmt, "Block {} belongs to another object", block)
+            }
+            Error::ChecksumMismatch { block } => write!(
+                fmt,
+                "Object at block {} doesn't match its checksum, file is corrupted",
+                block
+            ),
+            Error::ReadOnly => write!(fmt, "Database was opened read-only"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    #[inline]
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Serde(err) => Some(err),
+            _ => None,
         }
     }
 }
 
-impl std::error::Error for Error {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn into_io() {
+        let io = io::Error::from(Error::Io(io::Error::new(io::ErrorKind::NotFound, "gone")));
+        assert_eq!(io.kind(), io::ErrorKind::NotFound);
+        assert_eq!(io.to_string(), "gone");
+
+        let errors = vec![
+            Error::CorruptedBlock,
+            Error::Serde(Box::new(bincode::ErrorKind::InvalidBoolEncoding(2))),
+            Error::ContinuationBlock { block: 1 },
+            Error::EmptyBlock { block: 1 },
+            Error::NotExistant,
+            Error::HashMismatch {
+                stored: "a".to_owned(),
+                given: "b".to_owned(),
+            },
+            Error::ObjectTooLarge {
+                claimed: Some(2),
+                max: 1,
+            },
+            Error::ObjectTooLarge {
+                claimed: None,
+                max: 1,
+            },
+            Error::ObjectTooLong { block: 1 },
+            Error::InvalidOrdering,
+            Error::NotSeekable {
+                path: PathBuf::from("/dev/null"),
+            },
+            Error::LayoutChanged {
+                stored: None,
+                given: 1,
+            },
+            Error::OutOfSpace {
+                needed: 2,
+                available: 1,
+            },
+            Error::BlockOutOfBounds {
+                block: 2,
+                blocks: 1,
+            },
+            Error::InvalidBlockSize { block_size: 1 },
+            Error::BlockSizeMismatch {
+                stored: 1,
+                given: 2,
+            },
+            Error::MissingHeader,
+            Error::UserMetadataTooLarge { len: 2, max: 1 },
+            Error::VersionConflict {
+                expected: 1,
+                stored: 2,
+            },
+            Error::SizeLimitExceeded { needed: 2, max: 1 },
+            Error::BlockInUse { block: 1 },
+            Error::ChecksumMismatch { block: 1 },
+            Error::ReadOnly,
+        ];
+        for err in errors {
+            let message = err.to_string();
+            let io = io::Error::from(err);
+            assert_eq!(io.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(io.to_string(), message);
+            assert!(io.into_inner().unwrap().downcast::<Error>().is_ok());
+        }
+
+        // Goes back to the original error
+        let io = io::Error::from(Error::ReadOnly);
+        assert!(matches!(Error::from(io), Error::ReadOnly));
+        let io = io::Error::new(io::ErrorKind::NotFound, "gone");
+        assert!(matches!(Error::from(io), Error::Io(_)));
+    }
+}
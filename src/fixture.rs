@@ -0,0 +1,119 @@
+//! Deterministic data to fill databases with, so tests can assert the blocks objects are written to
+//!
+//! Only available in this crate's tests or with the `test-util` feature
+
+use std::ops::Range;
+
+/// Seeded generator of pseudo-random data, the same seed always generates the same sequence
+///
+/// It's SplitMix64, fast and good enough for fixtures, but not for anything that needs real randomness
+///
+/// ```rust
+/// use cabide::fixture::Generator;
+///
+/// let (mut first, mut second) = (Generator::new(42), Generator::new(42));
+/// assert_eq!(first.string(10), second.string(10));
+/// assert_eq!(first.range(0..100), second.range(0..100));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Generator {
+    /// Advanced by a constant for every generated number
+    state: u64,
+}
+
+impl Generator {
+    /// Characters `string` picks from
+    const ALPHANUMERIC: &'static [u8] =
+        b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+    /// Starts the sequence of specified seed
+    #[inline]
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns the next number of the sequence
+    #[inline]
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns number in specified range, which must not be empty
+    #[inline]
+    pub fn range(&mut self, range: Range<u64>) -> u64 {
+        assert!(range.start < range.end, "range {:?} is empty", range);
+        range.start + self.next_u64() % (range.end - range.start)
+    }
+
+    /// Returns true or false, with the same chance
+    #[inline]
+    pub fn bool(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+
+    /// Returns `len` bytes
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+
+    /// Returns `len` alphanumeric characters
+    pub fn string(&mut self, len: usize) -> String {
+        (0..len)
+            .map(|_| {
+                let index = self.range(0..Self::ALPHANUMERIC.len() as u64);
+                Self::ALPHANUMERIC[index as usize] as char
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cabide;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Data {
+        name: String,
+        maybe_number: Option<u8>,
+        blob: Vec<u8>,
+    }
+
+    /// Fills a database with data generated from specified seed, removing some of it, returns its bytes
+    fn fill(filename: &str, seed: u64) -> (Vec<u64>, Vec<u8>) {
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new(filename, None).unwrap();
+        let mut generator = Generator::new(seed);
+        let mut blocks = vec![];
+        for _ in 0..200 {
+            let len = generator.range(1..20) as usize;
+            let data = Data {
+                name: generator.string(len),
+                maybe_number: Some(generator.next_u64() as u8).filter(|_| generator.bool()),
+                blob: generator.bytes(len * 4),
+            };
+            blocks.push(cbd.write(&data).unwrap());
+            if generator.range(0..4) == 0 {
+                let block = blocks.swap_remove(generator.range(0..blocks.len() as u64) as usize);
+                cbd.remove(block).unwrap();
+            }
+        }
+        drop(cbd);
+
+        let bytes = std::fs::read(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        (blocks, bytes)
+    }
+
+    #[test]
+    fn reproducible() {
+        let first = fill("fixture_first.test", 7);
+        assert_eq!(first, fill("fixture_second.test", 7));
+        assert_ne!(first, fill("fixture_other.test", 8));
+    }
+}
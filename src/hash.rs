@@ -1,33 +1,199 @@
 use crate::{Cabide, Error};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::{collections::HashMap, fs, hash::Hash, io, path::Path, path::PathBuf};
 
-pub struct HashCabide<T> {
+/// Codomain of the hash function, each bucket is mapped to its own file
+///
+/// Buckets are identified by their bytes, which are used to build the file's path
+pub trait Bucket: Sized + Copy + Eq + Hash {
+    /// Bytes that identify the bucket, most significant first
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Inverse of `to_bytes`, `None` if the bytes don't represent a bucket
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_bucket {
+    ($($ty:ty),*) => {
+        $(
+            impl Bucket for $ty {
+                #[inline]
+                fn to_bytes(&self) -> Vec<u8> {
+                    self.to_be_bytes().to_vec()
+                }
+
+                #[inline]
+                fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                    let mut array = [0; std::mem::size_of::<$ty>()];
+                    if bytes.len() != array.len() {
+                        return None;
+                    }
+                    array.copy_from_slice(bytes);
+                    Some(Self::from_be_bytes(array))
+                }
+            }
+        )*
+    };
+}
+
+impl_bucket!(u8, u16, u32, u64);
+
+impl<const N: usize> Bucket for [u8; N] {
+    #[inline]
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    #[inline]
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut array = [0; N];
+        if bytes.len() != N {
+            return None;
+        }
+        array.copy_from_slice(bytes);
+        Some(array)
+    }
+}
+
+/// File inside of the folder that holds the hash function's identity
+const IDENTITY_FILE: &str = ".hash_function";
+
+pub struct HashCabide<T, H = u8> {
     folder: PathBuf,
-    cabides: HashMap<u8, Cabide<T>>,
-    hash_function: Box<dyn Fn(&T) -> u8>,
+    /// Number of path components a bucket is split into (`depth - 1` folders + the file)
+    depth: usize,
+    cabides: HashMap<H, Cabide<T>>,
+    hash_function: Box<dyn Fn(&T) -> H>,
 }
 
-impl<T> HashCabide<T> {
-    pub fn new<P>(folder: P, hash_function: Box<dyn Fn(&T) -> u8>) -> Result<Self, Error>
+impl<T, H: Bucket> HashCabide<T, H> {
+    /// Opens every bucket file directly inside the folder
+    pub fn new<P>(folder: P, hash_function: Box<dyn Fn(&T) -> H>) -> Result<Self, Error>
     where
         P: Into<PathBuf>,
     {
-        let (folder, mut cabides) = (folder.into(), HashMap::default());
-        for value in 0..255 {
-            let path = folder.join(value.to_string());
-            if path.is_file() {
-                cabides.insert(value, Cabide::new(path, None)?);
-            }
+        Self::with_sharding(folder, 1, hash_function)
+    }
+
+    /// Nests buckets in folders, each of the first `depth - 1` bytes of the hash becomes a folder,
+    /// the remaining bytes name the bucket file (`ab/cd` for the hash `0xabcd` with depth 2)
+    ///
+    /// Avoids one flat directory with millions of files for very large key spaces
+    pub fn with_sharding<P>(
+        folder: P,
+        depth: usize,
+        hash_function: Box<dyn Fn(&T) -> H>,
+    ) -> Result<Self, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let (folder, depth) = (folder.into(), depth.max(1));
+        let mut cabides = HashMap::default();
+        if folder.is_dir() {
+            Self::open_buckets(&folder, depth, &mut vec![], &mut cabides)?;
         }
 
         Ok(Self {
             folder,
+            depth,
             cabides,
             hash_function,
         })
     }
 
+    /// Validates that the data was written by the hash function identified by `identity`
+    ///
+    /// The identity is persisted in the folder on first use, opening it later with a different identity
+    /// fails with `Error::HashMismatch`, since reads would be silently misrouted
+    ///
+    /// ```rust
+    /// use cabide::{Error, HashCabide};
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # let _ = std::fs::remove_dir_all("test17.folder");
+    /// let by_first_byte = |name: &String| name.as_bytes()[0];
+    /// let mut cbd: HashCabide<String> =
+    ///     HashCabide::new("test17.folder", Box::new(by_first_byte))?.with_identity("first-byte")?;
+    /// cbd.write(&"Alice".to_owned())?;
+    ///
+    /// let by_length = |name: &String| name.len() as u8;
+    /// let res = HashCabide::<String>::new("test17.folder", Box::new(by_length))?.with_identity("length");
+    /// assert!(matches!(res, Err(Error::HashMismatch { .. })));
+    /// # std::fs::remove_dir_all("test17.folder")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_identity(self, identity: &str) -> Result<Self, Error> {
+        let path = self.folder.join(IDENTITY_FILE);
+        match fs::read_to_string(&path) {
+            Ok(stored) if stored == identity => {}
+            Ok(stored) => {
+                return Err(Error::HashMismatch {
+                    stored,
+                    given: identity.to_owned(),
+                })
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                fs::create_dir_all(&self.folder)?;
+                fs::write(&path, identity)?;
+            }
+            Err(err) => return Err(err.into()),
+        }
+        Ok(self)
+    }
+
+    /// Walks the sharding folders, opening every bucket file found
+    fn open_buckets(
+        folder: &Path,
+        depth: usize,
+        prefix: &mut Vec<u8>,
+        cabides: &mut HashMap<H, Cabide<T>>,
+    ) -> Result<(), Error> {
+        for entry in fs::read_dir(folder)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if depth > 1 && path.is_dir() {
+                if let Some(byte) = parse_hex(name).filter(|bytes| bytes.len() == 1) {
+                    prefix.extend(byte);
+                    Self::open_buckets(&path, depth - 1, prefix, cabides)?;
+                    prefix.pop();
+                }
+            } else if path.is_file() {
+                let mut bytes = prefix.clone();
+                if prefix.is_empty() && depth == 1 {
+                    // Flat buckets of a single byte keep their decimal names (`H` is a single byte if it
+                    // can be built from one)
+                    match name
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|_| H::from_bytes(&[0]).is_some())
+                    {
+                        Some(byte) => bytes.push(byte),
+                        None => bytes.extend(parse_hex(name).unwrap_or_default()),
+                    }
+                } else {
+                    bytes.extend(parse_hex(name).unwrap_or_default());
+                }
+
+                if let Some(bucket) = H::from_bytes(&bytes) {
+                    cabides.insert(bucket, Cabide::new(path, None)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Path of the file that holds specified bucket
+    #[inline]
+    fn bucket_path(&self, bucket: H) -> PathBuf {
+        bucket_path(&self.folder, self.depth, bucket)
+    }
+
     #[inline]
     pub fn blocks(&self) -> Result<u64, Error> {
         let mut blocks = 0;
@@ -36,16 +202,84 @@ impl<T> HashCabide<T> {
         }
         Ok(blocks)
     }
+
+    /// Waits for the contents of every bucket to reach the disk, see [`Cabide::sync_data`]
+    #[inline]
+    pub fn sync_data(&mut self) -> Result<(), Error> {
+        for cabide in self.cabides.values_mut() {
+            cabide.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the contents and metadata of every bucket to reach the disk, see [`Cabide::sync_all`]
+    #[inline]
+    pub fn sync_all(&mut self) -> Result<(), Error> {
+        for cabide in self.cabides.values_mut() {
+            cabide.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Waits for every change to every bucket to reach the disk, see [`Cabide::flush`]
+    #[inline]
+    pub fn flush(&mut self) -> Result<(), Error> {
+        for cabide in self.cabides.values_mut() {
+            cabide.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Path of the file that holds specified bucket, in a folder with `depth` path components per bucket
+fn bucket_path<H: Bucket>(folder: &Path, depth: usize, bucket: H) -> PathBuf {
+    let bytes = bucket.to_bytes();
+    if depth == 1 && bytes.len() == 1 {
+        return folder.join(bytes[0].to_string());
+    }
+
+    let folders = (depth - 1).min(bytes.len().saturating_sub(1));
+    let mut path = folder.to_path_buf();
+    for byte in &bytes[..folders] {
+        path.push(format!("{:02x}", byte));
+    }
+    path.push(to_hex(&bytes[folders..]));
+    path
 }
 
-impl<T: Serialize> HashCabide<T> {
+/// Opens the file of a bucket that has none yet, creating its sharding folders
+fn create_bucket<T>(path: &Path) -> Result<Cabide<T>, Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Cabide::new(path, None)
+}
+
+#[inline]
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[inline]
+fn parse_hex(string: &str) -> Option<Vec<u8>> {
+    if string.is_empty() || string.len() % 2 != 0 || !string.is_ascii() {
+        return None;
+    }
+
+    (0..string.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&string[i..i + 2], 16).ok())
+        .collect()
+}
+
+impl<T: Serialize, H: Bucket> HashCabide<T, H> {
     #[inline]
-    pub fn write(&mut self, obj: &T) -> Result<(u8, u64), Error> {
+    pub fn write(&mut self, obj: &T) -> Result<(H, u64), Error> {
         let hash = (self.hash_function)(obj);
         let block = if let Some(cabide) = self.cabides.get_mut(&hash) {
             cabide.write(obj)?
         } else {
-            let mut cabide = Cabide::new(self.folder.join(hash.to_string()), None)?;
+            let mut cabide = create_bucket(&self.bucket_path(hash))?;
             let block = cabide.write(obj)?;
             self.cabides.insert(hash, cabide);
             block
@@ -54,12 +288,84 @@ impl<T: Serialize> HashCabide<T> {
     }
 }
 
-impl<T> HashCabide<T>
+/// Read-modify-write access to a single record of a single bucket, see [`HashCabide::entry`]
+pub struct Entry<'a, T, H> {
+    cabides: &'a mut HashMap<H, Cabide<T>>,
+    hash: H,
+    path: PathBuf,
+    /// Record found in the bucket (starting block, value)
+    found: Option<(u64, T)>,
+}
+
+impl<T, H: Bucket> HashCabide<T, H>
+where
+    for<'de> T: Serialize + Deserialize<'de>,
+{
+    /// Finds first record selected by `find` in the bucket, only that bucket is scanned
+    ///
+    /// The bucket's file is only created if something is inserted
+    pub fn entry(&mut self, hash: H, find: impl Fn(&T) -> bool) -> Result<Entry<'_, T, H>, Error> {
+        let found = match self.cabides.get_mut(&hash) {
+            Some(cabide) => cabide.position(find)?,
+            None => None,
+        };
+
+        Ok(Entry {
+            path: self.bucket_path(hash),
+            cabides: &mut self.cabides,
+            hash,
+            found,
+        })
+    }
+}
+
+impl<'a, T, H: Bucket> Entry<'a, T, H>
+where
+    for<'de> T: Serialize + Deserialize<'de>,
+{
+    /// Modifies the record if it was found, writing it back to its bucket
+    pub fn and_modify(mut self, f: impl FnOnce(&mut T)) -> Result<Self, Error> {
+        if let Some((block, mut obj)) = self.found.take() {
+            f(&mut obj);
+
+            let cabide = self.cabides.get_mut(&self.hash).ok_or(Error::NotExistant)?;
+            // Record keeps its block if it still fits, otherwise the old copy is only freed once the new one
+            // is written, so it isn't lost if writing it fails
+            let block = cabide.update(block, &obj)?;
+            self.found = Some((block, obj));
+        }
+        Ok(self)
+    }
+
+    /// Writes `default` to the bucket if no record was found, returns the record's key
+    pub fn or_insert(self, default: T) -> Result<(H, u64), Error> {
+        if let Some((block, _)) = self.found {
+            return Ok((self.hash, block));
+        }
+
+        let cabide = match self.cabides.get_mut(&self.hash) {
+            Some(cabide) => cabide,
+            None => {
+                let cabide = create_bucket(&self.path)?;
+                self.cabides.entry(self.hash).or_insert(cabide)
+            }
+        };
+        Ok((self.hash, cabide.write(&default)?))
+    }
+
+    /// Returns the record found, if any
+    #[inline]
+    pub fn get(&self) -> Option<&T> {
+        self.found.as_ref().map(|(_, obj)| obj)
+    }
+}
+
+impl<T, H: Bucket> HashCabide<T, H>
 where
     for<'de> T: Deserialize<'de>,
 {
     #[inline]
-    pub fn read(&mut self, (hash, block): (u8, u64)) -> Result<T, Error> {
+    pub fn read(&mut self, (hash, block): (H, u64)) -> Result<T, Error> {
         self.cabides
             .get_mut(&hash)
             .ok_or(Error::NotExistant)?
@@ -67,16 +373,16 @@ where
     }
 
     #[inline]
-    pub fn filter(&mut self, filter: impl Fn(&T) -> bool) -> Vec<T> {
+    pub fn filter(&mut self, mut filter: impl FnMut(&T) -> bool) -> Vec<T> {
         let mut vec = vec![];
         for cabide in self.cabides.values_mut() {
-            vec.extend(cabide.filter(&filter));
+            vec.extend(cabide.filter(&mut filter));
         }
         vec
     }
 
     #[inline]
-    pub fn remove(&mut self, (hash, block): (u8, u64)) -> Result<T, Error> {
+    pub fn remove(&mut self, (hash, block): (H, u64)) -> Result<T, Error> {
         self.cabides
             .get_mut(&hash)
             .ok_or(Error::NotExistant)?
@@ -84,11 +390,377 @@ where
     }
 
     #[inline]
-    pub fn remove_with(&mut self, filter: impl Fn(&T) -> bool) -> Vec<T> {
+    pub fn remove_with(&mut self, mut filter: impl FnMut(&T) -> bool) -> Result<Vec<T>, Error> {
         let mut vec = vec![];
         for cabide in self.cabides.values_mut() {
-            vec.extend(cabide.remove_with(&filter));
+            vec.extend(cabide.remove_with(&mut filter)?);
+        }
+        Ok(vec)
+    }
+}
+
+/// Same as [`HashCabide`], but every bucket has its own lock, so it's shared between threads and only
+/// accesses to the same bucket wait for each other
+///
+/// ```rust
+/// use cabide::ConcurrentHashCabide;
+/// use std::sync::Arc;
+///
+/// # fn main() -> Result<(), cabide::Error> {
+/// # let _ = std::fs::remove_dir_all("test51.folder");
+/// let cbd: Arc<ConcurrentHashCabide<u64>> = Arc::new(ConcurrentHashCabide::new(
+///     "test51.folder",
+///     Box::new(|value: &u64| (value % 4) as u8),
+/// )?);
+///
+/// let threads: Vec<_> = (0..4)
+///     .map(|thread| {
+///         let cbd = Arc::clone(&cbd);
+///         std::thread::spawn(move || cbd.write(&thread))
+///     })
+///     .collect();
+/// for thread in threads {
+///     let key = thread.join().unwrap()?;
+///     assert_eq!(cbd.read(key)?, key.0 as u64);
+/// }
+/// # std::fs::remove_dir_all("test51.folder")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ConcurrentHashCabide<T, H = u8> {
+    folder: PathBuf,
+    /// Number of path components a bucket is split into (`depth - 1` folders + the file)
+    depth: usize,
+    /// Only locked for writing when a bucket is created, every bucket is locked on its own
+    cabides: RwLock<HashMap<H, Arc<Mutex<Cabide<T>>>>>,
+    hash_function: Box<dyn Fn(&T) -> H + Send + Sync>,
+}
+
+/// Locks bucket, a panic while it was locked can't leave it half-written, since every object is written
+/// to the file at once
+#[inline]
+fn lock<T>(cabide: &Mutex<Cabide<T>>) -> MutexGuard<'_, Cabide<T>> {
+    cabide.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<T, H: Bucket> ConcurrentHashCabide<T, H> {
+    /// Opens every bucket file directly inside the folder
+    pub fn new<P>(
+        folder: P,
+        hash_function: Box<dyn Fn(&T) -> H + Send + Sync>,
+    ) -> Result<Self, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        Self::with_sharding(folder, 1, hash_function)
+    }
+
+    /// Nests buckets in folders, see [`HashCabide::with_sharding`]
+    pub fn with_sharding<P>(
+        folder: P,
+        depth: usize,
+        hash_function: Box<dyn Fn(&T) -> H + Send + Sync>,
+    ) -> Result<Self, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let (folder, depth) = (folder.into(), depth.max(1));
+        let mut cabides = HashMap::default();
+        if folder.is_dir() {
+            HashCabide::<T, H>::open_buckets(&folder, depth, &mut vec![], &mut cabides)?;
+        }
+
+        let cabides = cabides
+            .into_iter()
+            .map(|(hash, cabide)| (hash, Arc::new(Mutex::new(cabide))))
+            .collect();
+        Ok(Self {
+            folder,
+            depth,
+            cabides: RwLock::new(cabides),
+            hash_function,
+        })
+    }
+
+    /// Returns the bucket, creating its file if `create` is set and it has none
+    fn bucket(&self, hash: H, create: bool) -> Result<Option<Arc<Mutex<Cabide<T>>>>, Error> {
+        let cabides = self.cabides.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(cabide) = cabides.get(&hash) {
+            return Ok(Some(Arc::clone(cabide)));
+        }
+        drop(cabides);
+        if !create {
+            return Ok(None);
+        }
+
+        // Another thread may have created it while no lock was held
+        let mut cabides = self.cabides.write().unwrap_or_else(PoisonError::into_inner);
+        let cabide = match cabides.get(&hash) {
+            Some(cabide) => Arc::clone(cabide),
+            None => {
+                let cabide = create_bucket(&bucket_path(&self.folder, self.depth, hash))?;
+                let cabide = Arc::new(Mutex::new(cabide));
+                cabides.insert(hash, Arc::clone(&cabide));
+                cabide
+            }
+        };
+        Ok(Some(cabide))
+    }
+
+    /// Every bucket, so they can be locked one at a time without holding the lock of the map
+    #[inline]
+    fn buckets(&self) -> Vec<Arc<Mutex<Cabide<T>>>> {
+        let cabides = self.cabides.read().unwrap_or_else(PoisonError::into_inner);
+        cabides.values().cloned().collect()
+    }
+
+    #[inline]
+    pub fn blocks(&self) -> Result<u64, Error> {
+        let mut blocks = 0;
+        for cabide in self.buckets() {
+            blocks += lock(&cabide).blocks()?;
+        }
+        Ok(blocks)
+    }
+
+    /// Waits for the contents of every bucket to reach the disk, see [`Cabide::sync_data`]
+    #[inline]
+    pub fn sync_data(&self) -> Result<(), Error> {
+        for cabide in self.buckets() {
+            lock(&cabide).sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the contents and metadata of every bucket to reach the disk, see [`Cabide::sync_all`]
+    #[inline]
+    pub fn sync_all(&self) -> Result<(), Error> {
+        for cabide in self.buckets() {
+            lock(&cabide).sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Waits for every change to every bucket to reach the disk, see [`Cabide::flush`]
+    #[inline]
+    pub fn flush(&self) -> Result<(), Error> {
+        for cabide in self.buckets() {
+            lock(&cabide).flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Serialize, H: Bucket> ConcurrentHashCabide<T, H> {
+    #[inline]
+    pub fn write(&self, obj: &T) -> Result<(H, u64), Error> {
+        let hash = (self.hash_function)(obj);
+        let cabide = self.bucket(hash, true)?.ok_or(Error::NotExistant)?;
+        let block = lock(&cabide).write(obj)?;
+        Ok((hash, block))
+    }
+}
+
+impl<T, H: Bucket> ConcurrentHashCabide<T, H>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    #[inline]
+    pub fn read(&self, (hash, block): (H, u64)) -> Result<T, Error> {
+        let cabide = self.bucket(hash, false)?.ok_or(Error::NotExistant)?;
+        let obj = lock(&cabide).read(block);
+        obj
+    }
+
+    /// Buckets are scanned one at a time, so objects written to other buckets during the scan may be missed
+    #[inline]
+    pub fn filter(&self, mut filter: impl FnMut(&T) -> bool) -> Vec<T> {
+        let mut vec = vec![];
+        for cabide in self.buckets() {
+            vec.extend(lock(&cabide).filter(&mut filter));
         }
         vec
     }
+
+    #[inline]
+    pub fn remove(&self, (hash, block): (H, u64)) -> Result<T, Error> {
+        let cabide = self.bucket(hash, false)?.ok_or(Error::NotExistant)?;
+        let obj = lock(&cabide).remove(block);
+        obj
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sharding() {
+        let folder = "hash_sharding.test";
+        let _ = fs::remove_dir_all(folder);
+
+        let hash_fn = |value: &u64| -> u16 { (*value as u16).wrapping_mul(0x9e37) };
+        let mut cbd = HashCabide::with_sharding(folder, 2, Box::new(hash_fn)).unwrap();
+
+        let mut keys = vec![];
+        for value in 0..50u64 {
+            keys.push((cbd.write(&value).unwrap(), value));
+        }
+        cbd.sync_data().unwrap();
+        cbd.sync_all().unwrap();
+        cbd.flush().unwrap();
+
+        let ((bucket, _), _) = keys[7];
+        let [first, second] = bucket.to_be_bytes();
+        let path = Path::new(folder)
+            .join(format!("{:02x}", first))
+            .join(format!("{:02x}", second));
+        assert!(path.is_file());
+
+        // this drops the last HashCabide, re-discovering the buckets from the folders
+        cbd = HashCabide::with_sharding(folder, 2, Box::new(hash_fn)).unwrap();
+        for (key, value) in keys {
+            assert_eq!(cbd.read(key).unwrap(), value);
+        }
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Counter {
+        name: String,
+        count: u64,
+    }
+
+    #[test]
+    fn entry_counter() {
+        let folder = "hash_entry.test";
+        let _ = fs::remove_dir_all(folder);
+        fs::create_dir(folder).unwrap();
+
+        let hash_fn = |counter: &Counter| -> u8 { counter.name.as_bytes()[0] };
+        let mut cbd = HashCabide::new(folder, Box::new(hash_fn)).unwrap();
+        let apples = cbd
+            .write(&Counter {
+                name: "apples".to_owned(),
+                count: 0,
+            })
+            .unwrap();
+
+        for _ in 0..3 {
+            for name in &["apples", "pears"] {
+                cbd.entry(name.as_bytes()[0], |counter| counter.name == *name)
+                    .unwrap()
+                    .and_modify(|counter| counter.count += 1)
+                    .unwrap()
+                    .or_insert(Counter {
+                        name: name.to_string(),
+                        count: 1,
+                    })
+                    .unwrap();
+            }
+        }
+
+        let entry = cbd.entry(b'a', |counter| counter.name == "apples").unwrap();
+        assert_eq!(entry.get().map(|counter| counter.count), Some(3));
+        // Updated in place, since it still fits its block
+        let unused = Counter {
+            name: String::new(),
+            count: 0,
+        };
+        assert_eq!(entry.or_insert(unused).unwrap(), apples);
+        let entry = cbd.entry(b'p', |counter| counter.name == "pears").unwrap();
+        assert_eq!(entry.get().map(|counter| counter.count), Some(3));
+        assert_eq!(cbd.filter(|_| true).len(), 2);
+
+        // Records that can't be written back are kept as they were
+        let path = bucket_path(Path::new(folder), 1, b'a');
+        let full = Cabide::builder()
+            .max_bytes(fs::metadata(&path).unwrap().len())
+            .open(&path)
+            .unwrap();
+        cbd.cabides.insert(b'a', full);
+        let res = cbd
+            .entry(b'a', |counter| counter.name == "apples")
+            .unwrap()
+            .and_modify(|counter| counter.name = "apples".repeat(20));
+        assert!(matches!(res, Err(Error::SizeLimitExceeded { .. })));
+        let entry = cbd.entry(b'a', |counter| counter.name == "apples").unwrap();
+        assert_eq!(entry.get().map(|counter| counter.count), Some(3));
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn identity() {
+        let folder = "hash_identity.test";
+        let _ = fs::remove_dir_all(folder);
+
+        let hash_fn = |value: &u64| -> u8 { *value as u8 };
+        let mut cbd = HashCabide::new(folder, Box::new(hash_fn))
+            .unwrap()
+            .with_identity("modulo-256")
+            .unwrap();
+        let key = cbd.write(&300).unwrap();
+
+        // this drops the last HashCabide, re-validating the identity
+        cbd = HashCabide::new(folder, Box::new(hash_fn))
+            .unwrap()
+            .with_identity("modulo-256")
+            .unwrap();
+        assert_eq!(cbd.read(key).unwrap(), 300);
+
+        let other_fn = |value: &u64| -> u8 { (*value >> 8) as u8 };
+        match HashCabide::new(folder, Box::new(other_fn))
+            .unwrap()
+            .with_identity("shift-8")
+        {
+            Err(Error::HashMismatch { stored, given }) => {
+                assert_eq!((stored.as_str(), given.as_str()), ("modulo-256", "shift-8"))
+            }
+            Err(err) => panic!("expected hash mismatch, got {:?}", err),
+            Ok(_) => panic!("expected hash mismatch"),
+        }
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn concurrent_writes() {
+        let folder = "hash_concurrent.test";
+        let _ = fs::remove_dir_all(folder);
+
+        let hash_fn = |value: &u64| -> u8 { (value % 8) as u8 };
+        let cbd = Arc::new(ConcurrentHashCabide::new(folder, Box::new(hash_fn)).unwrap());
+        let threads: Vec<_> = (0..8u64)
+            .map(|thread| {
+                let cbd = Arc::clone(&cbd);
+                std::thread::spawn(move || {
+                    // Every thread writes to its own bucket
+                    (0..100)
+                        .map(|i| {
+                            let value = i * 8 + thread;
+                            (cbd.write(&value).unwrap(), value)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut keys = vec![];
+        for thread in threads {
+            keys.extend(thread.join().unwrap());
+        }
+
+        let mut values = cbd.filter(|_| true);
+        values.sort_unstable();
+        assert_eq!(values, (0..800).collect::<Vec<_>>());
+        cbd.sync_all().unwrap();
+        cbd.flush().unwrap();
+
+        // this drops the last ConcurrentHashCabide, re-discovering the buckets
+        drop(cbd);
+        let mut cbd = HashCabide::new(folder, Box::new(hash_fn)).unwrap();
+        for (key, value) in keys {
+            assert_eq!(key.0 as u64, value % 8);
+            assert_eq!(cbd.read(key).unwrap(), value);
+        }
+        fs::remove_dir_all(folder).unwrap();
+    }
 }
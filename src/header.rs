@@ -0,0 +1,423 @@
+use crate::{bincode_options, fnv1a, Error, FNV_OFFSET};
+use bincode::Options;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
+
+/// Starts every header, blocks never start with it since their first byte is a `Metadata`
+const MAGIC: &[u8; 6] = b"CABIDE";
+
+/// Version of the header format, bumped if it changes in a way older versions can't read
+const VERSION: u16 = 1;
+
+/// Space reserved for the header, so fields can be added to it without moving the blocks
+pub(crate) const HEADER_SIZE: u64 = 256;
+
+/// Maximum size in bytes of the user metadata, so it always fits in the header with the other fields
+pub const MAX_USER_METADATA_SIZE: u64 = 128;
+
+/// Bytes before the fields, `[MAGIC][version][header size]`
+const PREFIX_SIZE: usize = MAGIC.len() + size_of::<u16>() + size_of::<u64>();
+
+/// Identifies each field of the header, 0 is the padding after the last one
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Layout = 1,
+    BlockSize,
+    UserMetadata,
+}
+
+/// Information about the database stored before its first block, files only have one if an option needs it
+///
+/// Every field is `[id][length as u32][value]`, integers are little endian, unknown fields are skipped
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Header {
+    /// Fingerprint of the layout of the objects, see [`crate::CabideBuilder::layout_check`]
+    pub(crate) layout: Option<u64>,
+    /// Size in bytes of each block, see [`crate::Cabide::BLOCK_SIZE`]
+    pub(crate) block_size: Option<u64>,
+    /// Bytes stored by the application, see [`crate::Cabide::set_user_metadata`]
+    pub(crate) user_metadata: Option<Vec<u8>>,
+}
+
+impl Header {
+    /// Reads the header that starts at `offset`, returns it with its size or `None` if there is no header
+    pub(crate) fn read(
+        file: &mut (impl Read + Seek),
+        offset: u64,
+    ) -> Result<Option<(Self, u64)>, Error> {
+        let mut prefix = Vec::with_capacity(PREFIX_SIZE);
+        file.seek(SeekFrom::Start(offset))?;
+        Read::by_ref(file)
+            .take(PREFIX_SIZE as u64)
+            .read_to_end(&mut prefix)?;
+        if !prefix.starts_with(MAGIC) {
+            return Ok(None);
+        }
+        if prefix.len() < PREFIX_SIZE {
+            return Err(Error::CorruptedBlock);
+        }
+
+        let version = u16::from_le_bytes([prefix[6], prefix[7]]);
+        let size = u64::from_le_bytes(
+            <[u8; 8]>::try_from(&prefix[8..]).map_err(|_| Error::CorruptedBlock)?,
+        );
+        if version > VERSION || size < PREFIX_SIZE as u64 {
+            return Err(Error::CorruptedBlock);
+        }
+
+        let mut fields = Vec::new();
+        Read::by_ref(file)
+            .take(size - PREFIX_SIZE as u64)
+            .read_to_end(&mut fields)?;
+
+        let (mut header, mut rest) = (Self::default(), fields.as_slice());
+        while let [id, length @ ..] = rest {
+            if *id == 0 {
+                break;
+            }
+            let (length, value) = match length {
+                [a, b, c, d, value @ ..] => (u32::from_le_bytes([*a, *b, *c, *d]) as usize, value),
+                _ => return Err(Error::CorruptedBlock),
+            };
+            let value = value.get(..length).ok_or(Error::CorruptedBlock)?;
+            let integer = || <[u8; 8]>::try_from(value).map_err(|_| Error::CorruptedBlock);
+            if *id == Field::Layout as u8 {
+                header.layout = Some(u64::from_le_bytes(integer()?));
+            } else if *id == Field::BlockSize as u8 {
+                header.block_size = Some(u64::from_le_bytes(integer()?));
+            } else if *id == Field::UserMetadata as u8 {
+                header.user_metadata = Some(value.to_vec());
+            }
+            rest = &rest[1 + size_of::<u32>() + length..];
+        }
+        Ok(Some((header, size)))
+    }
+
+    /// Writes the header at `offset`, returns its size
+    pub(crate) fn write(&self, file: &mut (impl Write + Seek), offset: u64) -> Result<u64, Error> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(&VERSION.to_le_bytes());
+        bytes.extend(&HEADER_SIZE.to_le_bytes());
+        if let Some(layout) = self.layout {
+            push_field(&mut bytes, Field::Layout, &layout.to_le_bytes());
+        }
+        if let Some(block_size) = self.block_size {
+            push_field(&mut bytes, Field::BlockSize, &block_size.to_le_bytes());
+        }
+        if let Some(user_metadata) = &self.user_metadata {
+            push_field(&mut bytes, Field::UserMetadata, user_metadata);
+        }
+        debug_assert!(bytes.len() as u64 <= HEADER_SIZE);
+        bytes.resize(HEADER_SIZE as usize, 0);
+
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&bytes)?;
+        Ok(HEADER_SIZE)
+    }
+}
+
+/// Appends field with specified value to the encoded header
+#[inline]
+fn push_field(bytes: &mut Vec<u8>, id: Field, value: &[u8]) {
+    bytes.push(id as u8);
+    bytes.extend(&(value.len() as u32).to_le_bytes());
+    bytes.extend(value);
+}
+
+/// Fingerprint of the layout of `T`, the hash of `T::default()` serialized
+pub(crate) fn layout_fingerprint<T: Serialize + Default>() -> Result<u64, Error> {
+    let sample = bincode_options().serialize(&T::default())?;
+    Ok(fnv1a(FNV_OFFSET, &sample))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{protocol::BLOCK_SIZE, Cabide};
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+    struct Student {
+        name: String,
+        dre: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+    struct StudentWithClasses {
+        name: String,
+        dre: u64,
+        classes: Vec<u64>,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+    struct RenamedStudent {
+        full_name: String,
+        registration: u64,
+    }
+
+    #[test]
+    fn layout_changed() {
+        let filename = "header_layout.test";
+        let _ = std::fs::remove_file(filename);
+        let mut cbd: Cabide<Student> = Cabide::builder().layout_check().open(filename).unwrap();
+        let student = Student {
+            name: "Ana".to_owned(),
+            dre: 1,
+        };
+        assert_eq!(cbd.write(&student).unwrap(), 0);
+        drop(cbd);
+
+        let res = Cabide::<StudentWithClasses>::builder()
+            .layout_check()
+            .open(filename);
+        assert!(matches!(
+            res,
+            Err(Error::LayoutChanged {
+                stored: Some(_),
+                ..
+            })
+        ));
+
+        // Renaming fields doesn't change how bincode stores them
+        let mut renamed: Cabide<RenamedStudent> =
+            Cabide::builder().layout_check().open(filename).unwrap();
+        assert_eq!(renamed.read(0).unwrap().full_name, "Ana");
+        drop(renamed);
+
+        // The check is opt-in, but the header is always skipped
+        let mut cbd: Cabide<Student> = Cabide::new(filename, None).unwrap();
+        assert_eq!(cbd.read(0).unwrap(), student);
+        assert_eq!(cbd.blocks().unwrap(), 1);
+        cbd.truncate().unwrap();
+        drop(cbd);
+        let cbd: Cabide<Student> = Cabide::builder().layout_check().open(filename).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 0);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn layout_needs_header() {
+        let filename = "header_missing.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Student> = Cabide::new(filename, None).unwrap();
+        cbd.write(&Student::default()).unwrap();
+        drop(cbd);
+
+        // Existing files without a header can't be checked
+        let res = Cabide::<Student>::builder().layout_check().open(filename);
+        assert!(matches!(
+            res,
+            Err(Error::LayoutChanged { stored: None, .. })
+        ));
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn stored_block_size() {
+        let filename = "header_block_size.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Student, 4096> = Cabide::new(filename, None).unwrap();
+        let students: Vec<Student> = (0..10)
+            .map(|dre| Student {
+                name: "Bob".repeat(dre as usize * 100),
+                dre,
+            })
+            .collect();
+        for student in &students {
+            cbd.write(student).unwrap();
+        }
+        drop(cbd);
+
+        // Default block size isn't the stored one, so it can't open the file
+        let res = Cabide::<Student>::new(filename, None);
+        assert!(matches!(
+            res,
+            Err(Error::BlockSizeMismatch {
+                stored: 4096,
+                given: BLOCK_SIZE
+            })
+        ));
+        let mut cbd: Cabide<Student, 4096> = Cabide::new(filename, None).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 10);
+        assert_eq!(cbd.filter(|_| true), students);
+        drop(cbd);
+
+        // Files with the default block size don't need a header
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Student> = Cabide::new(filename, None).unwrap();
+        cbd.write(&students[0]).unwrap();
+        let mut file = std::fs::File::open(filename).unwrap();
+        assert_eq!(Header::read(&mut file, 0).unwrap(), None);
+        drop(cbd);
+
+        // Headers written for other reasons also keep other block sizes out
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Student> = Cabide::new(filename, None).unwrap();
+        cbd.set_user_metadata(b"v1").unwrap();
+        cbd.write(&students[0]).unwrap();
+        drop(cbd);
+        assert!(matches!(
+            Cabide::<Student, 64>::new(filename, None),
+            Err(Error::BlockSizeMismatch {
+                stored: BLOCK_SIZE,
+                given: 64
+            })
+        ));
+        let mut cbd: Cabide<Student> = Cabide::new(filename, None).unwrap();
+        assert_eq!(cbd.read(0).unwrap(), students[0]);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    fn const_round_trip<const B: usize>(filename: &str) {
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Student, B> = Cabide::new(filename, None).unwrap();
+        assert_eq!(
+            (cbd.block_size(), cbd.content_size()),
+            (B as u64, B as u64 - 2)
+        );
+        let students: Vec<(u64, Student)> = (0..20)
+            .map(|dre| Student {
+                name: "Alice".repeat(dre as usize * 20),
+                dre,
+            })
+            .map(|student| (cbd.write(&student).unwrap(), student))
+            .collect();
+        drop(cbd);
+
+        let mut file = std::fs::File::open(filename).unwrap();
+        let (header, _) = Header::read(&mut file, 0).unwrap().unwrap();
+        assert_eq!(header.block_size, Some(B as u64));
+        let mut cbd: Cabide<Student, B> = Cabide::new(filename, None).unwrap();
+        for (block, student) in &students {
+            assert_eq!(&cbd.read(*block).unwrap(), student);
+        }
+        assert_eq!(cbd.filter_with_blocks(|_| true), students);
+    }
+
+    #[test]
+    fn const_block_size() {
+        let filename = "header_const_block_size.test";
+        const_round_trip::<64>(filename);
+        assert!(matches!(
+            Cabide::<Student, 4096>::new(filename, None),
+            Err(Error::BlockSizeMismatch {
+                stored: 64,
+                given: 4096
+            })
+        ));
+        // Default block size must also match the stored one
+        assert!(matches!(
+            Cabide::<Student>::new(filename, None),
+            Err(Error::BlockSizeMismatch {
+                stored: 64,
+                given: BLOCK_SIZE
+            })
+        ));
+
+        const_round_trip::<4096>(filename);
+        assert!(matches!(
+            Cabide::<Student, 64>::new(filename, None),
+            Err(Error::BlockSizeMismatch {
+                stored: 4096,
+                given: 64
+            })
+        ));
+
+        // Files without a header have blocks of the default size
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Student> = Cabide::new(filename, None).unwrap();
+        cbd.write(&Student::default()).unwrap();
+        drop(cbd);
+        assert!(matches!(
+            Cabide::<Student, 64>::new(filename, None),
+            Err(Error::BlockSizeMismatch {
+                stored: BLOCK_SIZE,
+                given: 64
+            })
+        ));
+
+        // Too small blocks can't hold an object's metadata
+        assert!(matches!(
+            Cabide::<Student, 1>::new(filename, None),
+            Err(Error::InvalidBlockSize { .. })
+        ));
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn user_metadata() {
+        let filename = "header_user_metadata.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Student, 64> = Cabide::new(filename, None).unwrap();
+        assert_eq!(cbd.user_metadata(), Vec::<u8>::new());
+        cbd.write(&Student::default()).unwrap();
+        cbd.set_user_metadata(b"v1").unwrap();
+        cbd.set_user_metadata(b"config").unwrap();
+        let max = vec![1; MAX_USER_METADATA_SIZE as usize + 1];
+        assert!(matches!(
+            cbd.set_user_metadata(&max),
+            Err(Error::UserMetadataTooLarge {
+                len,
+                max: MAX_USER_METADATA_SIZE,
+            }) if len == MAX_USER_METADATA_SIZE + 1
+        ));
+        drop(cbd);
+
+        // Other fields are kept
+        let mut cbd: Cabide<Student, 64> = Cabide::new(filename, None).unwrap();
+        assert_eq!(cbd.user_metadata(), b"config");
+        assert_eq!(cbd.block_size(), 64);
+        assert_eq!(cbd.read(0).unwrap(), Student::default());
+        drop(cbd);
+
+        // Headers can only be added to empty files
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Student> = Cabide::new(filename, None).unwrap();
+        cbd.set_user_metadata(&max[1..]).unwrap();
+        cbd.write(&Student::default()).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 1);
+        drop(cbd);
+        let cbd: Cabide<Student> = Cabide::new(filename, None).unwrap();
+        assert_eq!(cbd.user_metadata(), &max[1..]);
+
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Student> = Cabide::new(filename, None).unwrap();
+        cbd.write(&Student::default()).unwrap();
+        assert!(matches!(
+            cbd.set_user_metadata(b"v1"),
+            Err(Error::MissingHeader)
+        ));
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn skips_unknown_fields() {
+        let filename = "header_fields.test";
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(filename)
+            .unwrap();
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(&VERSION.to_le_bytes());
+        bytes.extend(&40u64.to_le_bytes());
+        push_field(&mut bytes, Field::Layout, &7u64.to_le_bytes());
+        bytes.extend(&[200, 2, 0, 0, 0, 9, 9]);
+        bytes.resize(40, 0);
+        file.write_all(&bytes).unwrap();
+        file.write_all(&[1]).unwrap();
+
+        let header = Header::read(&mut file, 0).unwrap();
+        let layout = Header {
+            layout: Some(7),
+            ..Header::default()
+        };
+        assert_eq!(header, Some((layout, 40)));
+        std::fs::remove_file(filename).unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+use crate::{Cabide, Error};
+use serde::Deserialize;
+
+/// Lazily reads every object of a database (with its starting block) in block order, see [`Cabide::iter`]
+///
+/// Empty blocks and blocks in the middle of objects are skipped, other errors are returned, the iteration
+/// goes on from the block after the one that failed
+#[derive(Debug)]
+pub struct CabideIter<'a, T, const BLOCK_SIZE: usize = { crate::protocol::BLOCK_SIZE as usize }> {
+    /// Database the objects are read from
+    cabide: &'a mut Cabide<T, BLOCK_SIZE>,
+    /// Next block to be read
+    block: u64,
+    /// Blocks written when the iteration started, objects written after it are ignored
+    blocks: u64,
+}
+
+impl<'a, T, const B: usize> CabideIter<'a, T, B> {
+    #[inline]
+    pub(crate) fn new(cabide: &'a mut Cabide<T, B>, blocks: u64) -> Self {
+        Self {
+            cabide,
+            block: 0,
+            blocks,
+        }
+    }
+}
+
+impl<T, const B: usize> Iterator for CabideIter<'_, T, B>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    type Item = Result<(u64, T), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.block < self.blocks {
+            // Fragmented objects may have blocks before their continuations, so they can't be skipped
+            let block = self.block;
+            self.block += 1;
+            match self.cabide.read(block) {
+                Ok(data) => return Some(Ok((block, data))),
+                Err(Error::EmptyBlock { .. }) | Err(Error::ContinuationBlock { .. }) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        None
+    }
+}
@@ -29,7 +29,7 @@
 //! for _ in 0..100 {
 //!     let data = random_data();
 //!     let primary_key = cbd.write(&data)?;
-//!     assert_eq!(cbd.read(primary_key), data);
+//!     assert_eq!(cbd.read(primary_key)?, data);
 //! }
 //!
 //! cbd.remove(40)?;
@@ -44,31 +44,124 @@
 //! # }
 //! ```
 
+mod btree;
+mod builder;
+mod cache;
+mod compact;
+mod cursor;
 mod error;
+#[cfg(any(test, feature = "test-util"))]
+pub mod fixture;
 mod hash;
+mod header;
+mod iter;
 mod order;
+mod pod;
 pub mod protocol;
+mod reader;
+mod tagged;
 
+pub use crate::btree::BTreeCabide;
+pub use crate::builder::{CabideBuilder, Codec};
+use crate::cache::ReadCache;
+use crate::cursor::{Storage, TrackedFile};
 pub use crate::error::Error;
-pub use crate::hash::HashCabide;
+pub use crate::hash::{Bucket, ConcurrentHashCabide, Entry, HashCabide};
+use crate::header::Header;
+pub use crate::header::MAX_USER_METADATA_SIZE;
+pub use crate::iter::CabideIter;
 pub use crate::order::OrderCabide;
-use crate::protocol::{Metadata, BLOCK_SIZE, CONTENT_SIZE, END_BYTE};
+pub use crate::pod::PodCabide;
+use crate::protocol::{
+    Metadata, BLOCK_SIZE, CHECKSUM_SIZE, END_BYTE, ID_SIZE, LENGTH_SIZE, LINK_BYTE, LINK_SIZE,
+    PAD_BYTE, SEQUENCE_SIZE,
+};
+use crate::reader::BlockReader;
+pub use crate::tagged::TaggedCabide;
 
-use bincode::{deserialize, serialize};
+use bincode::Options;
 use serde::{Deserialize, Serialize};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::{collections::BTreeMap, fs::File, fs::OpenOptions, marker::PhantomData, path::Path};
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{atomic::AtomicUsize, Arc};
+use std::{collections::BTreeMap, fs, fs::File, fs::OpenOptions, marker::PhantomData};
+use std::{fmt, ops::Bound, ops::Range, time::SystemTime};
+
+/// Default maximum size of an object, protects reads from corrupted or malicious length fields
+pub const DEFAULT_MAX_OBJECT_SIZE: u64 = 1 << 30;
+
+/// Default number of bytes read at once when scanning the file for empty blocks
+pub const DEFAULT_SCAN_CHUNK_SIZE: u64 = 1 << 20;
 
 pub static READ_BLOCKS_COUNT: AtomicUsize = AtomicUsize::new(0);
 
+/// Starting value of the FNV-1a hash
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Feeds bytes to a FNV-1a hash, it's fast and stable between platforms and versions
+#[inline]
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    const FNV_PRIME: u64 = 0x0100_0000_01b3;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Lookup table of the CRC32 (IEEE) of every byte, built at compile time
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+};
+
+/// Feeds bytes to a CRC32 (IEEE, like zlib's), the CRC of the bytes fed so far is `crc`, starting at 0
+#[inline]
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in bytes {
+        crc = CRC32_TABLE[((crc ^ u32::from(*byte)) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Encoding of the objects, integers are fixed size and little endian so files are portable between platforms
+#[inline]
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        .allow_trailing_bytes()
+}
+
 /// Abstracts typed database binded to a specific file
 ///
 /// Specified type will be (de)serialized from/to the file
 ///
 /// If the type changes to have different field order, field types or if more fields are added deserialization may be broken, please keep the type unchanged or migrate the database first
 ///
-/// Free blocks in the middle of the file will be cached and prefered, but no data is fragmented over them
+/// Free blocks in the middle of the file will be cached and prefered, but no data is fragmented over them,
+/// unless [`CabideBuilder::fragmentation`] is enabled
+///
+/// `BLOCK_SIZE` is the size in bytes of each block (including its metadata), bigger blocks waste less space with
+/// metadata for big objects, files that stored another one (or have none and another size than the default
+/// [`protocol::BLOCK_SIZE`]) fail to open with [`Error::BlockSizeMismatch`]
 ///
 /// ```rust
 /// use serde::{Serialize, Deserialize};
@@ -114,18 +207,154 @@ pub static READ_BLOCKS_COUNT: AtomicUsize = AtomicUsize::new(0);
 /// # }
 /// ```
 #[derive(Debug)]
-pub struct Cabide<T> {
+pub struct Cabide<T, const BLOCK_SIZE: usize = { crate::protocol::BLOCK_SIZE as usize }> {
     /// File which typed database is binded to
-    file: File,
+    file: TrackedFile,
+    /// Path of the binded file, used to find its sidecar files, `None` if the database is in memory
+    path: Option<PathBuf>,
+    /// Byte where the database's first block starts in the file
+    offset: u64,
+    /// Fields of the header right before the first block, all unset if there is none
+    header: Header,
+    /// Size in bytes of the header right before the first block, 0 if there is none
+    header_size: u64,
+    /// Maximum length in bytes of the database, if it's bounded (more data may follow it in the file)
+    len: Option<u64>,
     /// Caches number of next empty block
     next_block: u64,
     /// (number of continuous empty blocks -> list of "starting block"s)
     empty_blocks: BTreeMap<usize, Vec<u64>>,
+    /// If free blocks in the middle of the file should be re-used by writes
+    reuse_free_blocks: bool,
+    /// If objects may be split across free blocks that aren't continuous
+    fragmentation: bool,
+    /// How free blocks are chosen to be re-used
+    allocation: Allocation,
+    /// Chooses the free blocks to be re-used instead of `allocation` and `empty_blocks`, if set
+    allocator: Option<Box<dyn Allocator>>,
+    /// Maximum length in bytes the file can grow to, if it's limited
+    max_bytes: Option<u64>,
+    /// Maximum size in bytes of an object, bigger objects can't be read
+    max_object_size: u64,
+    /// Maximum number of blocks of an object, derived from the maximum size if not set
+    max_object_blocks: Option<u64>,
+    /// Transforms serialized objects, if any
+    codec: Option<Box<dyn Codec>>,
+    /// Minimum size of the serialized objects transformed by the codec, if objects are flagged with it
+    codec_threshold: Option<u64>,
+    /// Sequence number of the next written object, if objects are prefixed by one
+    next_sequence: Option<u64>,
+    /// (sequence number -> starting block) of every object, if sequence numbers are indexed
+    sequence_index: Option<BTreeMap<u64, u64>>,
+    /// Id of the next object written for the first time, if objects are prefixed by a stable id
+    next_id: Option<u64>,
+    /// (stable id -> starting block) of every object, empty if objects have no stable ids
+    id_index: BTreeMap<u64, u64>,
+    /// If objects are prefixed by a CRC32 of their content, verified when they are read
+    checksums: bool,
+    /// If objects are prefixed by their enum variant, used by `TaggedCabide`
+    tagged: bool,
+    /// Extracts when an object expires, if it does
+    expiry: Option<Expiry<T>>,
+    /// How the file is synced to disk when the database is dropped, if it is
+    sync_on_drop: Option<SyncMethod>,
+    /// If `write`, `remove` and `update` wait for the file to reach the disk before returning
+    sync_on_write: bool,
+    /// Bytes read at once when scanning the file for empty blocks
+    scan_chunk_size: u64,
+    /// Starting blocks of the corrupted objects emptied by scans, if they are quarantined
+    quarantined: Option<Vec<u64>>,
+    /// If the free blocks are stored in a sidecar file, see [`CabideBuilder::persist_free_blocks`]
+    persist_free_blocks: bool,
+    /// Recently read objects, see [`CabideBuilder::read_cache`]
+    read_cache: Option<ReadCache<T>>,
+    /// Reused by writes to serialize objects into, so they don't allocate
+    content_buffer: Vec<u8>,
+    /// Reused by writes to frame the blocks of objects into, before writing them at once
+    block_buffer: Vec<u8>,
     /// Marks that database must contain a single type
     _marker: PhantomData<T>,
 }
 
-impl<T> Cabide<T> {
+/// How data is flushed to disk
+///
+/// `Data` only waits for the contents (and the length) of the file to be durable, `All` also waits for
+/// the rest of its metadata (like modification time), which is slower and rarely needed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMethod {
+    /// Uses `File::sync_data`
+    Data,
+    /// Uses `File::sync_all`
+    All,
+}
+
+/// When data is flushed to disk, trading throughput for durability, see [`CabideBuilder::sync_mode`]
+///
+/// Without syncing, written objects may only be in the OS page cache, which a crash of the machine loses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Leaves it to the OS (or to explicit calls to [`Cabide::flush`])
+    Never,
+    /// `write`, `remove` and `update` (and the methods built on them) only return once their changes are
+    /// durable, using `File::sync_all`
+    OnEveryWrite,
+    /// Uses `File::sync_all` when the database is dropped, like `CabideBuilder::sync_on_drop`
+    OnDrop,
+}
+
+/// Strategy used to choose which free blocks in the middle of the file are re-used by a write
+///
+/// Free blocks are kept as runs of continuous empty blocks, the choice only depends on the runs that
+/// exist (and the order they were freed in), so it's reproducible
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Allocation {
+    /// Smallest run that fits the object, among runs of the same size the most recently freed one
+    BestFit,
+    /// Run that fits the object with the lowest starting block
+    FirstFit,
+}
+
+/// Chooses which free blocks in the middle of the file are re-used by writes, replacing the built-in
+/// [`Allocation`] strategies, see [`CabideBuilder::allocator`]
+///
+/// The database tells it about every run of free blocks (found when opening the file or freed by a
+/// removal), the blocks after the last object are never given to it, since they are appended to
+pub trait Allocator: fmt::Debug + Send + Sync {
+    /// Returns starting block of `blocks` continuous free blocks, which stop being free, or `None` if there
+    /// is no run big enough, then the object is appended to the file
+    fn allocate(&mut self, blocks: u64) -> Option<u64>;
+
+    /// Marks `len` continuous blocks, starting at `start`, as free, they may be next to other free runs
+    fn free(&mut self, start: u64, len: u64);
+
+    /// Forgets every free block, called before the file is scanned again (like in [`Cabide::reload`]) or
+    /// after it's emptied
+    fn clear(&mut self);
+}
+
+/// Objects of a page with their starting blocks, and the block the next page starts at, see [`Cabide::page`]
+pub type Page<T> = (Vec<(u64, T)>, Option<u64>);
+
+/// Extracts when an object expires, if it does
+type ExpiresAt<T> = dyn Fn(&T) -> Option<SystemTime> + Send + Sync;
+
+/// Function that extracts when an object expires
+struct Expiry<T>(Box<ExpiresAt<T>>);
+
+impl<T> fmt::Debug for Expiry<T> {
+    #[inline]
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Expiry")
+    }
+}
+
+/// Sequence number and stable id given to a written object, if they are enabled
+struct Written {
+    sequence: Option<u64>,
+    id: Option<u64>,
+}
+
+impl<T, const B: usize> Cabide<T, B> {
     /// Binds database to specified file, creating it if non existent
     ///
     /// Pads file to have specified number of blocks, pre-filling it
@@ -153,553 +382,6007 @@ impl<T> Cabide<T> {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn new<P>(filename: P, mut blocks: Option<u64>) -> Result<Self, Error>
+    #[inline]
+    pub fn new<P>(filename: P, blocks: Option<u64>) -> Result<Self, Error>
     where
         P: AsRef<Path>,
     {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .read(true)
-            .open(filename)?;
-        let (mut next_block, mut empty_blocks) = (0, BTreeMap::default());
-
-        let current_length = file.metadata()?.len();
-        // If file already has data we need to parse it to generate an up-to-date Cabide
-        if current_length > 0 {
-            next_block = ((current_length as f64) / (BLOCK_SIZE as f64)).ceil() as u64;
-
-            // If less pre-filled blocks than currently exist are asked for we ignore them
-            blocks = blocks.filter(|blocks| next_block.saturating_sub(1) < *blocks);
-
-            // Holds empty blocks chain
-            let mut empty_block = None;
+        CabideBuilder {
+            prefill: blocks,
+            ..CabideBuilder::new()
+        }
+        .open(filename)
+    }
 
-            // We need to find the empty blocks in the middle of the file
-            for curr_block in 0..next_block {
-                let mut metadata = [0];
+    /// Binds database to specified file, pre-filling it with specified number of blocks only if the file
+    /// doesn't exist, existing files are opened as they are, no matter their length
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # let _ = std::fs::remove_file("test40.file");
+    /// // Creates the file, so it's pre-filled
+    /// let mut cbd: Cabide<u8> = Cabide::create_or_open("test40.file", 100)?;
+    /// assert_eq!(cbd.blocks()?, 100);
+    /// cbd.truncate()?;
+    ///
+    /// cbd = Cabide::create_or_open("test40.file", 100)?;
+    /// assert_eq!(cbd.blocks()?, 0);
+    /// # std::fs::remove_file("test40.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_or_open<P>(filename: P, prefill_if_new: u64) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = filename.as_ref();
+        let prefill = match fs::metadata(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Some(prefill_if_new),
+            // Other errors are reported by `open`
+            _ => None,
+        };
+        CabideBuilder {
+            prefill,
+            ..CabideBuilder::new()
+        }
+        .open(path)
+    }
 
-                file.seek(SeekFrom::Start(curr_block * BLOCK_SIZE))?;
-                if Read::by_ref(&mut file).take(1).read(&mut metadata)? == 0 {
-                    // EOF
-                    break;
-                }
+    /// Same as [`Cabide::new`], but the file is opened with specified options (like `O_DIRECT` or the
+    /// permissions of the created file), read and write access are always added
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    /// use std::fs::OpenOptions;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # let _ = std::fs::remove_file("test43.file");
+    /// let mut options = OpenOptions::new();
+    /// #[cfg(unix)]
+    /// {
+    ///     use std::os::unix::fs::OpenOptionsExt;
+    ///     options.mode(0o600);
+    /// }
+    ///
+    /// let mut cbd: Cabide<u8> = Cabide::with_open_options("test43.file", None, options)?;
+    /// cbd.write(&1)?;
+    /// # std::fs::remove_file("test43.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_open_options<P>(
+        filename: P,
+        blocks: Option<u64>,
+        options: OpenOptions,
+    ) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        CabideBuilder {
+            prefill: blocks,
+            ..CabideBuilder::new()
+        }
+        .open_options(options)
+        .open(filename)
+    }
 
-                if let Some((current, mut size)) = empty_block.take() {
-                    if metadata[0] == Metadata::Empty as u8 {
-                        // Free blocks chain keeps going
-                        size += 1;
-                        empty_block = Some((current, size));
-                    } else {
-                        // Free blocks chain ended, we must store it
-                        empty_blocks
-                            .entry(size)
-                            .and_modify(|vec: &mut Vec<u64>| vec.push(current))
-                            .or_insert_with(|| vec![current]);
-                    }
-                } else if metadata[0] == Metadata::Empty as u8 {
-                    // First block of empty chain
-                    empty_block = Some((curr_block, 1));
-                }
-            }
+    /// Same as [`Cabide::new`], but allows disabling the re-use of free blocks in the middle of the file
+    ///
+    /// When `reuse_free_blocks` is false writes are always appended to the end of the file, trading space for
+    /// sequential read locality, holes will accumulate until the file is rewritten
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test12.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::with_free_block_reuse("test12.file", None, false)?;
+    ///
+    /// for i in 0..100 {
+    ///     cbd.write(&i)?;
+    /// }
+    ///
+    /// cbd.remove(30)?;
+    /// assert_eq!(cbd.write(&30)?, 100);
+    /// # std::fs::remove_file("test12.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_free_block_reuse<P>(
+        filename: P,
+        blocks: Option<u64>,
+        reuse_free_blocks: bool,
+    ) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        CabideBuilder {
+            prefill: blocks,
+            ..CabideBuilder::new()
         }
+        .append_only(!reuse_free_blocks)
+        .open(filename)
+    }
 
-        // Pre-fills the file if desired
-        if let Some(blocks) = blocks {
-            // `set_len` works assuming that `Metadata::Empty` is 0
-            // So we assert it at compile time
-            const _METADATA_EMPTY_MUST_BE_ZERO: u8 = 0 - (Metadata::Empty as u8);
+    /// Binds database to specified file without write access, so files that can't be written to (like the
+    /// ones in a read-only mount) can still be read
+    ///
+    /// Fails with `Error::NotExistant` if the file doesn't exist, every change (like `write`, `remove` or
+    /// `update`) fails with `Error::ReadOnly`
+    ///
+    /// ```rust
+    /// use cabide::{Cabide, Error};
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test67.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test67.file", None)?;
+    /// let block = cbd.write(&1)?;
+    ///
+    /// let mut read_only: Cabide<u8> = Cabide::open_read_only("test67.file")?;
+    /// assert_eq!(read_only.read(block)?, 1);
+    /// assert!(matches!(read_only.write(&2), Err(Error::ReadOnly)));
+    /// # std::fs::remove_file("test67.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn open_read_only<P>(filename: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        CabideBuilder::new().read_only(true).open(filename)
+    }
 
-            file.set_len(blocks * BLOCK_SIZE)?;
+    /// Binds database to the `[offset, offset + len)` byte range of specified file, creating it if non existent
+    ///
+    /// Allows packing multiple tables in a single file, every block number is relative to `offset`,
+    /// writes past the end of the range fail with `Error::OutOfSpace` and pre-filling never shrinks the file
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test14.file")?;
+    /// let mut first: Cabide<u8> = Cabide::new_at("test14.file", 0, 300, Some(10))?;
+    /// let mut second: Cabide<u8> = Cabide::new_at("test14.file", 300, 300, Some(10))?;
+    ///
+    /// assert_eq!(first.write(&1)?, 0);
+    /// assert_eq!(second.write(&2)?, 0);
+    /// assert_eq!(first.read(0)?, 1);
+    /// assert_eq!(second.read(0)?, 2);
+    /// # std::fs::remove_file("test14.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_at<P>(filename: P, offset: u64, len: u64, blocks: Option<u64>) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        CabideBuilder {
+            prefill: blocks,
+            ..CabideBuilder::new()
         }
-
-        Ok(Self {
-            file,
-            next_block,
-            empty_blocks,
-            _marker: PhantomData,
-        })
+        .range(offset, len)
+        .open(filename)
     }
 
-    /// Returns number of blocks written to file (some may be empty)
+    /// Binds database to a buffer in memory instead of a file, nothing is persisted, use
+    /// [`Cabide::into_bytes`] to get the buffer back (like to send the whole database somewhere else)
+    ///
     /// ```rust
     /// use cabide::Cabide;
     ///
-    /// // Opens file without pre-filling it, creates it since it's first run
     /// # fn main() -> Result<(), cabide::Error> {
-    /// # std::fs::File::create("test10.file")?;
-    /// let mut cbd: Cabide<u8> = Cabide::new("test10.file", Some(1000))?;
-    /// assert_eq!(cbd.blocks()?, 1000);
-    /// # std::fs::remove_file("test10.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::from_bytes(vec![])?;
+    /// let block = cbd.write(&"in memory".to_owned())?;
+    /// let bytes = cbd.into_bytes().unwrap();
+    ///
+    /// let mut cbd: Cabide<String> = Cabide::from_bytes(bytes)?;
+    /// assert_eq!(cbd.read(block)?, "in memory");
     /// # Ok(())
     /// # }
     /// ```
     #[inline]
-    pub fn blocks(&self) -> Result<u64, Error> {
-        Ok(((self.file.metadata()?.len() as f64) / (BLOCK_SIZE as f64)).ceil() as u64)
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        CabideBuilder::new().open_bytes(bytes)
     }
 
+    /// Returns the buffer of a database in memory (see [`Cabide::from_bytes`]), `None` if it's binded to a file
     #[inline]
-    pub fn truncate(&mut self) -> Result<(), Error> {
-        self.file.set_len(0)?;
-        self.next_block = 0;
-        self.empty_blocks.clear();
-        Ok(())
+    pub fn into_bytes(mut self) -> Option<Vec<u8>> {
+        self.file.take_bytes()
     }
-}
 
-impl<T> Cabide<T>
-where
-    for<'de> T: Deserialize<'de>,
-{
-    #[inline(always)]
-    fn read_update_metadata(&mut self, block: u64, empty_read_blocks: bool) -> Result<T, Error> {
-        let mut content = vec![];
-        let mut empty_block = None;
-        self.file.seek(SeekFrom::Start(block * BLOCK_SIZE))?;
+    /// Starts configuring a database, for options not covered by the other constructors
+    #[inline]
+    pub fn builder() -> CabideBuilder<T, B> {
+        CabideBuilder::new()
+    }
 
-        let mut metadata = [0];
-        let mut expected_metadata = Metadata::Start;
-
-        // Reads block until EOF
-        while Read::by_ref(&mut self.file).take(1).read(&mut metadata)? != 0 {
-            if content.is_empty() && metadata[0] != expected_metadata as u8 {
-                // If its the first block and the metadata mismatch
-                if metadata[0] == Metadata::Empty as u8 {
-                    // If first block is empty we error
-                    return Err(Error::EmptyBlock);
-                } else {
-                    // If first block is in the middle of an object (continuation) we error
-                    debug_assert_eq!(metadata[0], Metadata::Continuation as u8);
-                    return Err(Error::ContinuationBlock);
-                }
-            } else if metadata[0] != expected_metadata as u8 {
-                // Stop reading if all of the object has been read
-                break;
-            }
+    pub(crate) fn from_file(
+        file: File,
+        path: &Path,
+        options: CabideBuilder<T, B>,
+    ) -> Result<Self, Error> {
+        Self::from_storage(Storage::File(file), Some(path.to_path_buf()), options)
+    }
 
-            READ_BLOCKS_COUNT.fetch_add(1, Ordering::SeqCst);
+    pub(crate) fn from_storage(
+        mut file: Storage,
+        path: Option<PathBuf>,
+        options: CabideBuilder<T, B>,
+    ) -> Result<Self, Error> {
+        let block_size = Self::BLOCK_SIZE;
+        protocol::check_block_size(block_size)?;
+        let (mut offset, mut len) = match options.range {
+            Some((offset, len)) => (offset, Some(len)),
+            None => (0, None),
+        };
 
-            // Overwrite the metadata if needed (in case of removal)
-            if empty_read_blocks {
-                if let Some((_, blocks)) = &mut empty_block {
-                    *blocks += 1;
-                } else {
-                    empty_block = Some((block, 1));
+        // Header is only written if an option needs it, but it's always skipped
+        let (header, header_size) = match Header::read(&mut file, offset)? {
+            Some((header, size)) => {
+                if let Some(given) = options.layout.filter(|given| header.layout != Some(*given)) {
+                    return Err(Error::LayoutChanged {
+                        stored: header.layout,
+                        given,
+                    });
                 }
 
-                self.file.seek(SeekFrom::Current(-1))?;
-                self.file.write_all(&[Metadata::Empty as u8])?;
+                // Files can only be read with the block size they were written with
+                let stored = header.block_size.unwrap_or(BLOCK_SIZE);
+                if stored != block_size {
+                    return Err(Error::BlockSizeMismatch {
+                        stored,
+                        given: block_size,
+                    });
+                }
+                (header, size)
             }
+            None => {
+                let is_empty = file.len()? <= offset;
+                if let Some(given) = options.layout.filter(|_| !is_empty) {
+                    return Err(Error::LayoutChanged {
+                        stored: None,
+                        given,
+                    });
+                }
 
-            Read::by_ref(&mut self.file)
-                .take(CONTENT_SIZE)
-                .read_to_end(&mut content)?;
+                // Blocks of any other size are only written after a header that stores it
+                if !is_empty && block_size != BLOCK_SIZE {
+                    return Err(Error::BlockSizeMismatch {
+                        stored: BLOCK_SIZE,
+                        given: block_size,
+                    });
+                }
 
-            // We must seek the last byte, which may be a END_BLOCK or a padding byte
-            self.file.seek(SeekFrom::Current(1))?;
+                if is_empty
+                    && !options.read_only
+                    && (options.layout.is_some() || block_size != BLOCK_SIZE)
+                {
+                    let header = Header {
+                        layout: options.layout,
+                        block_size: Some(block_size),
+                        user_metadata: None,
+                    };
+                    let size = header.write(&mut file, offset)?;
+                    (header, size)
+                } else {
+                    (Header::default(), 0)
+                }
+            }
+        };
+        offset += header_size;
+        len = len.map(|len| len.saturating_sub(header_size));
 
-            // Makes sure we stop reading if object changes
-            expected_metadata = Metadata::Continuation;
+        let mut cabide = Self {
+            file: TrackedFile::new(file),
+            path,
+            offset,
+            header,
+            header_size,
+            len,
+            next_block: 0,
+            empty_blocks: BTreeMap::default(),
+            reuse_free_blocks: !options.append_only,
+            fragmentation: options.fragmentation,
+            allocation: options.allocation,
+            allocator: options.allocator,
+            max_bytes: options.max_bytes,
+            max_object_size: options.max_object_size,
+            max_object_blocks: options.max_object_blocks,
+            codec: options.codec,
+            codec_threshold: options.codec_threshold,
+            next_sequence: None,
+            sequence_index: None,
+            next_id: None,
+            id_index: BTreeMap::new(),
+            checksums: options.checksums,
+            tagged: options.tagged,
+            expiry: None,
+            sync_on_drop: options.sync_on_drop,
+            sync_on_write: options.sync_on_write,
+            scan_chunk_size: options.scan_chunk_size,
+            quarantined: options.quarantine_on_corruption.then(Vec::new),
+            persist_free_blocks: options.persist_free_blocks,
+            read_cache: options.read_cache.map(ReadCache::new),
+            content_buffer: vec![],
+            block_buffer: vec![],
+            _marker: PhantomData,
+        };
+        cabide.file.read_only = options.read_only;
+        if options.mmap {
+            cabide.file.map()?;
         }
 
-        if let Some((index, size)) = empty_block {
-            self.empty_blocks
-                .entry(size)
-                .and_modify(|vec| vec.push(index as u64))
-                .or_insert_with(|| vec![index as u64]);
+        // If file already has data we need to parse it to generate an up-to-date Cabide
+        if options.sequence_numbers {
+            cabide.next_sequence = Some(0);
+            if options.sequence_index {
+                cabide.sequence_index = Some(BTreeMap::new());
+            }
         }
-
-        // Objects may be padded with Metadata::Empty, so we must truncate it
-        while content.last() == Some(&(Metadata::Empty as u8)) {
-            content.truncate(content.len() - 1);
+        if options.stable_ids {
+            cabide.next_id = Some(0);
         }
-
-        // All blocks have a END_BYTE before the (optional) padding, so we remove it if it was read
-        // because of the padding
-        if content.last() == Some(&END_BYTE) {
-            content.truncate(content.len() - 1);
+        if !cabide.load_free_blocks() {
+            cabide.scan()?;
         }
+        cabide.index_objects()?;
+        // Any sidecar left is removed on the first change, since it no longer describes the file
+        cabide.file.stale_on_write = cabide.free_blocks_sidecar();
 
-        let obj = deserialize(&content).map_err(|_| Error::CorruptedBlock)?;
-        Ok(obj)
+        if let Some(blocks) = options.prefill {
+            cabide.set_prefill(blocks)?;
+        }
+        Ok(cabide)
     }
 
-    /// Mark object blocks as empty, cacheing them, returns removed content
+    /// Grows the file to have at least specified number of blocks, pre-filling it with empty blocks
+    ///
+    /// Does nothing if the file already has more blocks, the new ones are written to by appending
     ///
     /// ```rust
     /// use cabide::Cabide;
     ///
     /// # fn main() -> Result<(), cabide::Error> {
-    /// # std::fs::File::create("test3.file")?;
-    /// // Opens database, creates it since it's first run
-    /// let mut cbd: Cabide<u8> = Cabide::new("test3.file", None)?;
-    ///
-    /// for i in 0..100 {
-    ///     cbd.write(&i)?;
-    /// }
-    ///
-    /// assert_eq!(cbd.remove(30)?, 30);
-    /// assert_eq!(cbd.remove(3)?, 3);
+    /// # std::fs::File::create("test22.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test22.file", Some(10))?;
+    /// cbd.set_prefill(100)?;
+    /// assert_eq!(cbd.blocks()?, 100);
     ///
-    /// // Writing re-uses freed blocks in the middle of the file (using the last ones first)
-    /// assert_eq!(cbd.write(&100)?, 3);
-    /// assert_eq!(cbd.remove(3)?, 100);
-    /// # std::fs::remove_file("test3.file")?;
+    /// cbd.set_prefill(30)?;
+    /// assert_eq!(cbd.blocks()?, 100);
+    /// # std::fs::remove_file("test22.file")?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn remove(&mut self, block: u64) -> Result<T, Error> {
-        self.read_update_metadata(block, true)
+    pub fn set_prefill(&mut self, blocks: u64) -> Result<(), Error> {
+        if blocks > self.blocks()? {
+            self.set_blocks(blocks)?;
+        }
+        Ok(())
     }
 
-    /// Returns object deserialized from specified starting block (and its continuations)
+    /// Rebuilds the cached state (empty blocks, next empty block, next sequence number and the indexes) from
+    /// the file
+    ///
+    /// Needed if the file was changed by something else while it was open (like another process or a
+    /// restored snapshot), it's cheaper than opening the database again
     ///
     /// ```rust
     /// use cabide::Cabide;
     ///
-    /// // Opens file pre-filling it, creates it since it's first run
     /// # fn main() -> Result<(), cabide::Error> {
-    /// # std::fs::File::create("test4.file")?;
-    /// let mut cbd: Cabide<u8> = Cabide::new("test4.file", None)?;
-    ///
-    /// for i in 0..100 {
-    ///     cbd.write(&i)?;
-    /// }
+    /// # std::fs::File::create("test27.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test27.file", None)?;
+    /// let mut other: Cabide<u8> = Cabide::new("test27.file", None)?;
     ///
-    /// assert_eq!(cbd.read(30)?, 30);
-    /// assert_eq!(cbd.read(3)?, 3);
-    /// # std::fs::remove_file("test4.file")?;
+    /// assert_eq!(other.write(&1)?, 0);
+    /// cbd.reload()?;
+    /// assert_eq!(cbd.write(&2)?, 1);
+    /// # std::fs::remove_file("test27.file")?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn read(&mut self, block: u64) -> Result<T, Error> {
-        return self.read_update_metadata(block, false);
+    pub fn reload(&mut self) -> Result<(), Error> {
+        self.clear_read_cache();
+        self.scan()?;
+        self.index_objects()
     }
 
-    /// Returns first element to be selected by the `filter` function
-    ///
-    /// Works in O(n), testing each block until the first is found
-    ///
-    /// ```rust
-    /// use serde::{Serialize, Deserialize};
-    /// use cabide::Cabide;
+    /// Rebuilds the state read from the objects themselves, their sequence numbers and stable ids
+    fn index_objects(&mut self) -> Result<(), Error> {
+        // Sequence numbers must keep increasing from the biggest one already written
+        if self.next_sequence.is_some() {
+            self.next_sequence = Some(self.index_sequences()?);
+        }
+        if self.next_id.is_some() {
+            self.next_id = Some(self.index_ids()?);
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the sequence number index (if there is one), returns the sequence number after the biggest one
+    pub(crate) fn index_sequences(&mut self) -> Result<u64, Error> {
+        let mut index = self.sequence_index.take();
+        if let Some(index) = &mut index {
+            index.clear();
+        }
+
+        let mut next = 0;
+        for block in self.object_blocks()? {
+            let sequence = self.read_sequence(block)?;
+            next = next.max(sequence + 1);
+            if let Some(index) = &mut index {
+                index.insert(sequence, block);
+            }
+        }
+        self.sequence_index = index;
+        Ok(next)
+    }
+
+    /// Rebuilds the stable id index (ids must be enabled), returns the id after the biggest one
+    pub(crate) fn index_ids(&mut self) -> Result<u64, Error> {
+        self.id_index.clear();
+        let mut next = 0;
+        for block in self.object_blocks()? {
+            let id = self.read_id(block)?;
+            next = next.max(id + 1);
+            self.id_index.insert(id, block);
+        }
+        Ok(next)
+    }
+
+    /// Path of the sidecar that may hold the free blocks, `None` if the database can't have one
     ///
-    /// #[derive(Debug, Serialize, Deserialize)]
-    /// struct Student {
-    ///     name: String,
-    ///     dre: u64,
-    ///     classes: Vec<u16>
-    /// }
+    /// Every handle removes it before changing the file, even if it doesn't store the free blocks itself
+    fn free_blocks_sidecar(&self) -> Option<PathBuf> {
+        let mut path = self
+            .path
+            .as_ref()
+            .filter(|_| self.len.is_none())?
+            .as_os_str()
+            .to_owned();
+        path.push(".idx");
+        Some(path.into())
+    }
+
+    /// Path of the sidecar that holds the free blocks, `None` if the database doesn't store them
+    #[inline]
+    fn free_blocks_path(&self) -> Option<PathBuf> {
+        let persisted =
+            self.persist_free_blocks && self.allocator.is_none() && !self.file.read_only;
+        self.free_blocks_sidecar().filter(|_| persisted)
+    }
+
+    /// Stores the free blocks in a sidecar file (see [`CabideBuilder::persist_free_blocks`]), so the next
+    /// open doesn't need to scan the file, the file is synced first
     ///
-    /// # use rand::{distributions::*, random, thread_rng};
-    /// # fn random_student(classes: Vec<u16>) -> Student {
-    /// #     // this is slow af since it doesn't cache thread_rng
-    /// #     let mut dre = random();
-    /// #     while dre == 10101010 {
-    /// #         dre = random();
-    /// #     }
-    /// #     Student {
-    /// #         name: (0..7)
-    /// #             .map(|_| Alphanumeric.sample(&mut thread_rng()))
-    /// #             .collect(),
-    /// #         dre,
-    /// #         classes: classes.clone(),
-    /// #     }
-    /// # }
-    /// # fn main() -> Result<(), cabide::Error> {
-    /// # std::fs::File::create("test5.file")?;
-    /// // Opens file pre-filling it, creates it since it's first run
-    /// let mut cbd: Cabide<Student> = Cabide::new("test5.file", Some(1000))?;
-    /// assert_eq!(cbd.blocks()?, 1000);
-    ///
-    /// for i in 0..20 {
-    ///     cbd.write(&random_student(vec![1023, random(), random()]))?;
-    /// }
+    /// Already done when the database is dropped, does nothing if the database has no sidecar or the file
+    /// was removed
+    pub fn flush_index(&mut self) -> Result<(), Error> {
+        let (path, sidecar) = match (&self.path, self.free_blocks_path()) {
+            (Some(path), Some(sidecar)) => (path, sidecar),
+            _ => return Ok(()),
+        };
+        match fs::metadata(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            result => result?,
+        };
+
+        let mut temp = sidecar.as_os_str().to_owned();
+        temp.push(".tmp");
+        let bytes = bincode_options().serialize(&(
+            self.file.len()?,
+            self.next_block,
+            &self.empty_blocks,
+        ))?;
+        // Sidecar must never be newer than contents that aren't durable
+        self.file.sync_data()?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp)?;
+        file.write_all(&bytes)?;
+        file.sync_data()?;
+        fs::rename(&temp, &sidecar)?;
+        self.file.stale_on_write = Some(sidecar);
+        Ok(())
+    }
+
+    /// Loads the free blocks from the sidecar, returns false if it can't be trusted (missing, older than the
+    /// file, corrupted or describing another length) and the file must be scanned
+    fn load_free_blocks(&mut self) -> bool {
+        let (path, sidecar) = match (&self.path, self.free_blocks_path()) {
+            (Some(path), Some(sidecar)) => (path, sidecar),
+            _ => return false,
+        };
+        let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+        match (modified(&sidecar), modified(path)) {
+            (Ok(sidecar), Ok(file)) if sidecar >= file => {}
+            _ => return false,
+        }
+
+        let decoded = fs::read(&sidecar).ok().and_then(|bytes| {
+            bincode_options()
+                .deserialize::<(u64, u64, BTreeMap<usize, Vec<u64>>)>(&bytes)
+                .ok()
+        });
+        let (len, next_block, empty_blocks) = match decoded {
+            Some(decoded) => decoded,
+            None => return false,
+        };
+        let fits = empty_blocks.iter().all(|(blocks, starts)| {
+            starts
+                .iter()
+                .all(|start| start.checked_add(*blocks as u64) <= Some(next_block))
+        });
+        if !fits || self.file.len().ok() != Some(len) || Some(next_block) > self.blocks().ok() {
+            return false;
+        }
+
+        self.next_block = next_block;
+        self.empty_blocks = empty_blocks;
+        true
+    }
+
+    /// Finds the empty blocks in the middle of the file and the next empty block
     ///
-    /// cbd.write(&Student {
-    ///     name: "Mr Legit Student".to_owned(),
-    ///     dre: 10101010,
-    ///     classes: vec![3],
-    /// })?;
+    /// Empty blocks at the end of the file aren't cached, the next empty block is the first of them
+    fn scan(&mut self) -> Result<(), Error> {
+        self.next_block = self.blocks()?;
+        self.clear_free_runs();
+
+        // Holds empty blocks chain
+        let mut empty_block = None;
+
+        // Many blocks are read at once, seeking to each metadata byte makes opening big files slow
+        let block_size = Self::BLOCK_SIZE;
+        let chunk_blocks = (self.scan_chunk_size / block_size).max(1);
+        let mut chunk =
+            Vec::with_capacity((chunk_blocks.min(self.next_block) * block_size) as usize);
+        let mut first_block = 0;
+
+        while first_block < self.next_block {
+            let blocks = chunk_blocks.min(self.next_block - first_block);
+            self.seek_block(first_block)?;
+            chunk.clear();
+            Read::by_ref(&mut self.file)
+                .take(blocks * block_size)
+                .read_to_end(&mut chunk)?;
+
+            let metadata_bytes = chunk.iter().step_by(block_size as usize);
+            for (curr_block, metadata) in (first_block..).zip(metadata_bytes) {
+                let metadata = *metadata;
+                if let Some((current, mut size)) = empty_block.take() {
+                    if metadata == Metadata::Empty as u8 {
+                        // Free blocks chain keeps going
+                        size += 1;
+                        empty_block = Some((current, size));
+                    } else {
+                        // Free blocks chain ended, we must store it
+                        self.push_free_run(current, size);
+                    }
+                } else if metadata == Metadata::Empty as u8 {
+                    // First block of empty chain
+                    empty_block = Some((curr_block, 1));
+                }
+            }
+
+            // EOF
+            if (chunk.len() as u64) < blocks * block_size {
+                break;
+            }
+            first_block += blocks;
+        }
+
+        // Trailing empty blocks are written to by appending
+        if let Some((current, _)) = empty_block {
+            self.next_block = current;
+        }
+        Ok(())
+    }
+
+    /// Number of blocks scans must read, the blocks after the last object are empty (like pre-filled ones)
     ///
-    /// let student = cbd.first(|student| student.dre == 10101010).unwrap();
-    /// assert_eq!(&student.name, "Mr Legit Student");
-    /// # std::fs::remove_file("test5.file")?;
-    /// # Ok(())
-    /// # }
-    /// ```
+    /// Objects written by other handles after the last scan are only seen after [`Cabide::reload`]
     #[inline]
-    pub fn first(&mut self, filter: impl Fn(&T) -> bool) -> Option<T> {
-        for block in 0..self.blocks().unwrap_or(0) {
-            match self.read(block) {
-                Ok(data) => {
-                    if filter(&data) {
-                        return Some(data);
+    fn written_blocks(&self) -> u64 {
+        self.next_block
+    }
+
+    /// Fails if the file would need to grow past its limit to have blocks up to `end` (exclusive)
+    fn check_size_limit(&self, end: u64) -> Result<(), Error> {
+        let max = match self.max_bytes {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+        let needed = end
+            .checked_mul(Self::BLOCK_SIZE)
+            .and_then(|length| length.checked_add(self.offset))
+            .ok_or_else(|| self.out_of_bounds(end))?;
+        // Growing isn't needed if the blocks already exist (like pre-filled ones)
+        if needed > max && needed > self.file.len()? {
+            return Err(Error::SizeLimitExceeded { needed, max });
+        }
+        Ok(())
+    }
+
+    /// Resizes the file to have specified number of blocks
+    ///
+    /// Bounded ranges can't be shrinked without affecting what comes after it, so the blocks are emptied instead
+    fn set_blocks(&mut self, blocks: u64) -> Result<(), Error> {
+        // `set_len` works assuming that `Metadata::Empty` is 0
+        protocol::debug_assert_layout(Self::BLOCK_SIZE);
+
+        let length = blocks * Self::BLOCK_SIZE;
+        self.check_size_limit(blocks)?;
+        match self.len {
+            None => self.file.set_len(self.offset + length)?,
+            Some(len) if length > len => {
+                return Err(Error::OutOfSpace {
+                    needed: blocks,
+                    available: len / Self::BLOCK_SIZE,
+                })
+            }
+            Some(_) => {
+                let current = self.blocks()?;
+                if blocks > current {
+                    let file_length = self.file.len()?;
+                    if file_length < self.offset + length {
+                        self.file.set_len(self.offset + length)?;
+                    }
+                } else {
+                    let zeroes = vec![Metadata::Empty as u8; Self::BLOCK_SIZE as usize];
+                    self.seek_block(blocks)?;
+                    for _ in blocks..current {
+                        self.file.write_all(&zeroes)?;
                     }
                 }
-                Err(Error::EmptyBlock) => continue,
-                Err(Error::ContinuationBlock) => continue,
-                _ => return None,
             }
         }
-        None
+        Ok(())
     }
 
-    /// Returns list of element selected by the `filter` function
+    /// Size in bytes of each block, including its 2 bytes of metadata, the `BLOCK_SIZE` parameter
+    pub const BLOCK_SIZE: u64 = B as u64;
+
+    /// Space in bytes available for content in each block, the block size minus its metadata
+    pub const CONTENT_SIZE: u64 = (B as u64).saturating_sub(2);
+
+    /// Returns size in bytes of each block, including its 2 bytes of metadata, see [`Cabide::BLOCK_SIZE`]
     ///
     /// ```rust
-    /// use serde::{Serialize, Deserialize};
-    /// use cabide::Cabide;
+    /// use cabide::{Cabide, Error};
     ///
-    /// #[derive(Debug, Serialize, Deserialize)]
-    /// struct Student {
-    ///     name: String,
-    ///     dre: u64,
-    ///     classes: Vec<u16>
-    /// }
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test32.file")?;
+    /// let cbd: Cabide<u8, 64> = Cabide::new("test32.file", None)?;
+    /// assert_eq!(cbd.block_size(), 64);
+    /// assert_eq!(cbd.content_size(), 62);
+    /// # std::fs::remove_file("test32.file")?;
     ///
-    /// # use rand::{distributions::*, random, thread_rng};
-    /// # fn random_student(classes: Vec<u16>) -> Student {
-    /// #     // this is slow af since it doesn't cache thread_rng
-    /// #     Student {
-    /// #         name: (0..7)
-    /// #             .map(|_| Alphanumeric.sample(&mut thread_rng()))
-    /// #             .collect(),
-    /// #         dre: random(),
-    /// #         classes,
-    /// #     }
+    /// # std::fs::File::create("test70.file")?;
+    /// let mut cbd: Cabide<u8, 4096> = Cabide::new("test70.file", None)?;
+    /// assert_eq!(cbd.block_size(), 4096);
+    /// cbd.write(&1)?;
+    /// drop(cbd);
+    ///
+    /// let res = Cabide::<u8, 64>::new("test70.file", None);
+    /// assert!(matches!(res, Err(Error::BlockSizeMismatch { stored: 4096, given: 64 })));
+    /// let res = Cabide::<u8>::new("test70.file", None);
+    /// assert!(matches!(res, Err(Error::BlockSizeMismatch { stored: 4096, given: 30 })));
+    /// # std::fs::remove_file("test70.file")?;
+    /// # Ok(())
     /// # }
-    /// # fn main() -> Result<(), cabide::Error> {
-    /// # std::fs::File::create("test6.file")?;
-    /// // Opens file pre-filling it, creates it since it's first run
-    /// let mut cbd: Cabide<Student> = Cabide::new("test6.file", Some(1000))?;
+    /// ```
+    #[inline]
+    pub fn block_size(&self) -> u64 {
+        Self::BLOCK_SIZE
+    }
+
+    /// Returns space in bytes available for content in each block, see [`Cabide::CONTENT_SIZE`]
+    #[inline]
+    pub fn content_size(&self) -> u64 {
+        Self::CONTENT_SIZE
+    }
+
+    /// Returns bytes stored with [`Cabide::set_user_metadata`], empty if none were
+    #[inline]
+    pub fn user_metadata(&self) -> Vec<u8> {
+        self.header.user_metadata.clone().unwrap_or_default()
+    }
+
+    /// Stores bytes in the file's header, like the version of the application's schema, at most
+    /// [`MAX_USER_METADATA_SIZE`] bytes (otherwise `Error::UserMetadataTooLarge` is returned)
     ///
-    /// cbd.write(&Student {
-    ///     name: "Mr Legit Student".to_owned(),
-    ///     dre: 10101010,
-    ///     classes: vec![3],
-    /// })?;
+    /// Files that don't have a header only get one if they are empty, otherwise `Error::MissingHeader` is
+    /// returned (headers are written when opening if [`CabideBuilder::layout_check`] is enabled or the block
+    /// size isn't the default)
     ///
-    /// for i in 0..20 {
-    ///     cbd.write(&random_student(vec![1023, random(), random()]))?;
-    /// }
+    /// ```rust
+    /// use cabide::Cabide;
     ///
-    /// let students = cbd.filter(|student| student.classes.contains(&1023));
-    /// assert_eq!(students.len(), 20);
-    /// # std::fs::remove_file("test6.file")?;
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test41.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test41.file", None)?;
+    /// cbd.set_user_metadata(b"schema v2")?;
+    /// cbd.write(&1)?;
+    ///
+    /// // this drops the last cabide, therefore closes the file
+    /// cbd = Cabide::new("test41.file", None)?;
+    /// assert_eq!(cbd.user_metadata(), b"schema v2");
+    /// assert_eq!(cbd.read(0)?, 1);
+    /// # std::fs::remove_file("test41.file")?;
     /// # Ok(())
     /// # }
     /// ```
-    #[inline]
-    pub fn filter(&mut self, filter: impl Fn(&T) -> bool) -> Vec<T> {
-        let mut vec = vec![];
-        for block in 0..self.blocks().unwrap_or(0) {
-            match self.read(block) {
-                Ok(data) => {
-                    if filter(&data) {
-                        vec.push(data);
-                    }
-                }
-                Err(Error::EmptyBlock) => continue,
-                Err(Error::ContinuationBlock) => continue,
-                // We ignore IO errors, this may be a mistake (or not, only future will know)
-                _ => continue,
-            }
+    pub fn set_user_metadata(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if bytes.len() as u64 > MAX_USER_METADATA_SIZE {
+            return Err(Error::UserMetadataTooLarge {
+                len: bytes.len() as u64,
+                max: MAX_USER_METADATA_SIZE,
+            });
         }
-        vec
+
+        let mut header = self.header.clone();
+        header.user_metadata = Some(bytes.to_vec());
+        if self.header_size > 0 {
+            self.header_size = header.write(&mut self.file, self.offset - self.header_size)?;
+            self.header = header;
+            return Ok(());
+        }
+
+        // Blocks would need to be moved to make room for the header
+        if self.file.len()? > self.offset {
+            return Err(Error::MissingHeader);
+        }
+        header.block_size = Some(Self::BLOCK_SIZE);
+        let header_size = header.write(&mut self.file, self.offset)?;
+        self.offset += header_size;
+        self.len = self.len.map(|len| len.saturating_sub(header_size));
+        self.header_size = header_size;
+        self.header = header;
+        Ok(())
     }
 
-    /// Sorry, docs are still on their way for this
+    /// Returns starting blocks of the corrupted objects emptied by scans, in the order they were found, see
+    /// [`CabideBuilder::quarantine_on_corruption`]
     #[inline]
-    pub fn remove_with(&mut self, filter: impl Fn(&T) -> bool) -> Vec<T> {
-        let mut vec = vec![];
-        for block in 0..self.blocks().unwrap_or(0) {
-            match self.read(block) {
-                Ok(data) => {
-                    if filter(&data) {
-                        self.remove(block).unwrap();
-                        vec.push(data);
-                    }
-                }
-                Err(Error::EmptyBlock) => continue,
-                Err(Error::ContinuationBlock) => continue,
-                // We ignore IO errors, this may be a mistake (or not, only future will know)
-                _ => continue,
-            }
+    pub fn quarantined_blocks(&self) -> &[u64] {
+        self.quarantined.as_deref().unwrap_or(&[])
+    }
+
+    /// Returns number of blocks written to file (some may be empty)
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// // Opens file without pre-filling it, creates it since it's first run
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test10.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test10.file", Some(1000))?;
+    /// assert_eq!(cbd.blocks()?, 1000);
+    /// # std::fs::remove_file("test10.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn blocks(&self) -> Result<u64, Error> {
+        let blocks = self
+            .file
+            .len()?
+            .saturating_sub(self.offset)
+            .div_ceil(Self::BLOCK_SIZE);
+        // Ranges only have whole blocks, a partial one would reach into whatever comes after the range
+        Ok(self
+            .len
+            .map_or(blocks, |len| blocks.min(len / Self::BLOCK_SIZE)))
+    }
+
+    #[inline]
+    pub fn truncate(&mut self) -> Result<(), Error> {
+        self.set_blocks(0)?;
+        self.clear_read_cache();
+        self.next_block = 0;
+        self.clear_free_runs();
+        if let Some(index) = &mut self.sequence_index {
+            index.clear();
         }
-        vec
+        self.id_index.clear();
+        Ok(())
     }
-}
 
-impl<T: Serialize> Cabide<T> {
-    /// Writes data to database, splitting data in multiple blocks if needed
+    /// Drops the empty blocks after the last object (like pre-filled ones never written to), truncating the
+    /// file, returns the number of blocks left
     ///
-    /// Re-uses removed blocks, doesn't fragment data
+    /// Empty blocks before the last object are kept, see [`Cabide::compact`] to get rid of them too
     ///
-    /// ```
+    /// ```rust
     /// use cabide::Cabide;
     ///
     /// # fn main() -> Result<(), cabide::Error> {
-    /// # std::fs::File::create("test7.file")?;
-    /// let mut cbd: Cabide<u8> = Cabide::new("test7.file", None)?;
-    ///
-    /// // Each u8 will always fit in one block
-    /// for i in 0..1000 {
-    ///     assert_eq!(cbd.write(&rand::random())?, i);
+    /// # std::fs::File::create("test63.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test63.file", Some(1000))?;
+    /// for i in 0..5 {
+    ///     cbd.write(&i)?;
     /// }
+    /// cbd.remove(4)?;
     ///
-    /// cbd.remove(30)?;
-    /// cbd.remove(58)?;
-    ///
-    /// // Since there are empty blocks in the middle of the file we re-use one of them
-    /// // (the last one to be available that fits the data)
-    /// assert_eq!(cbd.write(&rand::random())?, 58);
-    /// # std::fs::remove_file("test7.file")?;
+    /// assert_eq!(cbd.shrink_to_fit()?, 4);
+    /// assert_eq!(cbd.blocks()?, 4);
+    /// # std::fs::remove_file("test63.file")?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn write(&mut self, obj: &T) -> Result<u64, Error> {
-        let raw = serialize(obj).map_err(|_| Error::CorruptedBlock)?;
-        let blocks_needed = raw.len() / (CONTENT_SIZE as usize);
+    pub fn shrink_to_fit(&mut self) -> Result<u64, Error> {
+        let mut blocks = self.next_block;
+        while blocks > 0 && self.read_metadata(blocks - 1)? == Some(Metadata::Empty as u8) {
+            blocks -= 1;
+        }
+        self.set_blocks(blocks)?;
+        self.next_block = blocks;
 
-        let (mut starting_block, mut remaining_blocks, mut delete_block) = (None, None, None);
-        // First we check if there are empty blocks with the needed size
-        for (blocks, block_vec) in &mut self.empty_blocks {
-            if *blocks * (CONTENT_SIZE as usize) >= raw.len() {
-                starting_block = block_vec.pop();
-
-                if let Some(starting_block) = starting_block {
-                    let index = starting_block as usize;
-                    remaining_blocks = Some((*blocks - blocks_needed, index + blocks_needed));
-                    break;
-                } else if delete_block.is_none() {
-                    // We need to handle empty leafs, but we only handle one at a time
-                    delete_block = Some(*blocks);
-                }
+        // Free runs after the last object were emptied with it, so they end at the end of the file
+        if self.allocator.is_some() {
+            self.scan()?;
+        } else {
+            for block_vec in self.empty_blocks.values_mut() {
+                block_vec.retain(|start| *start < blocks);
             }
+            self.empty_blocks
+                .retain(|_, block_vec| !block_vec.is_empty());
         }
+        Ok(blocks)
+    }
 
-        // If BTreeMap leaf's has no starting block we remove it
-        if let Some(blocks) = delete_block {
-            self.empty_blocks.remove(&blocks);
+    /// Hints the OS that specified blocks will be read soon, so it starts reading them ahead, making a scan
+    /// of a file that isn't cached faster
+    ///
+    /// Uses `posix_fadvise` where it exists (like Linux), does nothing elsewhere
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test38.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test38.file", Some(100))?;
+    ///
+    /// cbd.prefetch(0..cbd.blocks()?)?;
+    /// assert_eq!(cbd.filter(|_| true), vec![]);
+    /// # std::fs::remove_file("test38.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prefetch(&self, blocks: Range<u64>) -> Result<(), Error> {
+        if blocks.is_empty() {
+            return Ok(());
         }
+        let start = self.block_start(blocks.start)?;
+        let len = (blocks.end - blocks.start)
+            .checked_mul(Self::BLOCK_SIZE)
+            .ok_or_else(|| self.out_of_bounds(blocks.end))?;
 
-        // Returns unused free blocks from the extracted chain to the empty_blocks list
-        if let Some((blocks, index)) = remaining_blocks {
-            self.empty_blocks
-                .entry(blocks)
-                .and_modify(|vec| vec.push(index as u64))
-                .or_insert_with(|| vec![index as u64]);
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            // Memory has nothing to prefetch
+            let file = match self.file.as_file() {
+                Some(file) => file,
+                None => return Ok(()),
+            };
+            let offset =
+                |value| libc::off_t::try_from(value).map_err(|_| self.out_of_bounds(blocks.end));
+            let (start, len) = (offset(start)?, offset(len)?);
+            // SAFETY: the file descriptor is owned by `file`, it's open while it's borrowed
+            let ret = unsafe {
+                libc::posix_fadvise(file.as_raw_fd(), start, len, libc::POSIX_FADV_WILLNEED)
+            };
+            if ret != 0 {
+                return Err(io::Error::from_raw_os_error(ret).into());
+            }
         }
+        #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+        let _ = (start, len);
+        Ok(())
+    }
 
-        let starting_block = if let Some(block) = starting_block {
-            block
-        } else {
-            // If there wasn't any fragmented empty block we take the next available one
-            // We need to update self.next_block taking into account how many bytes we are writing
-            let block = self.next_block;
-            self.next_block += ((raw.len() as f64) / (CONTENT_SIZE as f64)).ceil() as u64;
-            block
-        };
+    /// Waits for the contents of the file to reach the disk, see [`SyncMethod`]
+    #[inline]
+    pub fn sync_data(&mut self) -> Result<(), Error> {
+        self.file.sync_data()?;
+        Ok(())
+    }
 
-        self.file
-            .seek(SeekFrom::Start(starting_block * BLOCK_SIZE))?;
-
-        let (mut written, mut blocks, mut metadata) = (0, 0, Metadata::Start);
-        // Split encoded data in chunks, appending the metadata to each block before writing the chunks
-        for buff in raw.chunks(CONTENT_SIZE as usize) {
-            written += self.file.write(&[metadata as u8])?;
-            written += self.file.write(buff)?;
-            written += self.file.write(&[END_BYTE])?;
-            metadata = Metadata::Continuation;
-            blocks += 1;
-        }
-
-        // Last chunk may need to be padded
-        let null_byte = Metadata::Empty
-            .as_char()
-            .to_string()
-            .repeat((blocks * BLOCK_SIZE) as usize - written);
-        self.file.write_all(null_byte.as_bytes())?;
-        Ok(starting_block)
+    /// Waits for the contents and metadata of the file to reach the disk, see [`SyncMethod`]
+    #[inline]
+    pub fn sync_all(&mut self) -> Result<(), Error> {
+        self.file.sync_all()?;
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Waits for every change to reach the disk, same as `sync_all`, see [`SyncMode`]
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test65.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::new("test65.file", None)?;
+    /// let block = cbd.write(&"durable".to_owned())?;
+    /// cbd.flush()?;
+    /// assert_eq!(cbd.read(block)?, "durable");
+    /// # std::fs::remove_file("test65.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.sync_all()
+    }
+
+    /// Flushes the file if every write is synced (see [`SyncMode::OnEveryWrite`]) and `result` succeeded
+    #[inline]
+    fn synced<R>(&mut self, result: Result<R, Error>) -> Result<R, Error> {
+        if self.sync_on_write && result.is_ok() {
+            self.flush()?;
+        }
+        result
+    }
+
+    /// Copies the database to `dest`, which can be opened as a `Cabide<T>` with the same options
+    ///
+    /// The copy is written to a temporary file and renamed into place, so `dest` is never left
+    /// half-written, a bounded database only has its range copied
+    ///
+    /// If the free blocks are persisted (see [`CabideBuilder::persist_free_blocks`]) the sidecar is
+    /// flushed and copied next to `dest` too, so opening the snapshot doesn't need to scan it
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test21.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test21.file", None)?;
+    /// cbd.write(&1)?;
+    /// cbd.snapshot("test21.snapshot")?;
+    /// cbd.remove(0)?;
+    ///
+    /// let mut snapshot: Cabide<u8> = Cabide::new("test21.snapshot", None)?;
+    /// assert_eq!(snapshot.read(0)?, 1);
+    /// # std::fs::remove_file("test21.file")?;
+    /// # std::fs::remove_file("test21.snapshot")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn snapshot(&mut self, dest: impl AsRef<Path>) -> Result<(), Error> {
+        let dest = dest.as_ref();
+        let mut temp = dest.as_os_str().to_owned();
+        temp.push(".tmp");
+        let sidecar = self.free_blocks_path();
+        if sidecar.is_some() {
+            self.flush_index()?;
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp)?;
+        // Header is copied too, since the snapshot must be opened with the same options
+        let length = self.header_size + self.blocks()? * Self::BLOCK_SIZE;
+        self.file
+            .seek(SeekFrom::Start(self.offset - self.header_size))?;
+        io::copy(&mut Read::by_ref(&mut self.file).take(length), &mut file)?;
+        file.sync_all()?;
+        fs::rename(&temp, dest)?;
+
+        // Copied after the snapshot is in place, since an older sidecar is ignored
+        if let Some(sidecar) = sidecar.filter(|sidecar| sidecar.exists()) {
+            let mut dest_sidecar = dest.as_os_str().to_owned();
+            dest_sidecar.push(".idx");
+            let mut temp = dest_sidecar.clone();
+            temp.push(".tmp");
+            fs::copy(&sidecar, &temp)?;
+            OpenOptions::new().write(true).open(&temp)?.sync_all()?;
+            fs::rename(&temp, &dest_sidecar)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the starting block of every object, only reading the metadata of each block
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test15.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::new("test15.file", Some(10))?;
+    ///
+    /// let first = cbd.write(&"spans multiple blocks".repeat(4))?;
+    /// let second = cbd.write(&"a single block".to_owned())?;
+    /// assert_eq!(cbd.object_blocks()?, vec![first, second]);
+    /// # std::fs::remove_file("test15.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn object_blocks(&mut self) -> Result<Vec<u64>, Error> {
+        let mut vec = vec![];
+        for block in 0..self.written_blocks() {
+            match self.read_metadata(block)? {
+                Some(metadata) if Metadata::is_start(metadata) => vec.push(block),
+                Some(_) => continue,
+                None => break,
+            }
+        }
+        Ok(vec)
+    }
+
+    /// Returns number of objects, only reading the metadata of each block, so nothing is deserialized
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test58.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::new("test58.file", Some(10))?;
+    ///
+    /// cbd.write(&"spans multiple blocks".repeat(4))?;
+    /// let block = cbd.write(&"a single block".to_owned())?;
+    /// cbd.write(&"another one".to_owned())?;
+    /// cbd.remove(block)?;
+    /// assert_eq!(cbd.count()?, 2);
+    /// # std::fs::remove_file("test58.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn count(&mut self) -> Result<u64, Error> {
+        let mut count = 0;
+        for block in 0..self.written_blocks() {
+            match self.read_metadata(block)? {
+                Some(metadata) if Metadata::is_start(metadata) => count += 1,
+                Some(_) => continue,
+                None => break,
+            }
+        }
+        Ok(count)
+    }
+
+    /// Hashes the content of every object in block order, so databases with the same objects (in the same
+    /// order) have the same fingerprint, no matter where the empty blocks are
+    ///
+    /// Uses FNV-1a, which is stable between runs and platforms, but isn't cryptographically secure
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test17.file")?;
+    /// # std::fs::File::create("test72.file")?;
+    /// let mut first: Cabide<u8> = Cabide::new("test17.file", None)?;
+    /// let mut second: Cabide<u8> = Cabide::new("test72.file", Some(10))?;
+    ///
+    /// for i in 0..5 {
+    ///     first.write(&i)?;
+    ///     second.write(&i)?;
+    /// }
+    /// assert_eq!(first.fingerprint()?, second.fingerprint()?);
+    ///
+    /// first.remove(2)?;
+    /// assert_ne!(first.fingerprint()?, second.fingerprint()?);
+    /// # std::fs::remove_file("test17.file")?;
+    /// # std::fs::remove_file("test72.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fingerprint(&mut self) -> Result<u64, Error> {
+        let mut hash = FNV_OFFSET;
+        let mut content = vec![];
+        for block in self.object_blocks()? {
+            content.clear();
+            let mut reader = BlockReader::new(self, block, false)?;
+            let result = reader.read_to_end(&mut content).map_err(Error::from);
+            reader.finish(result)?;
+
+            // Length delimits the objects, so moving bytes between them changes the fingerprint
+            hash = fnv1a(hash, &(content.len() as u64).to_le_bytes());
+            hash = fnv1a(hash, &content);
+        }
+        Ok(hash)
+    }
+
+    /// Returns the serialized object at specified block, without deserializing it, like to migrate objects
+    /// written by an incompatible version of `T`
+    ///
+    /// Its content header (like the sequence number) is skipped and the codec is reverted, so the bytes are
+    /// what bincode wrote
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test60.file")?;
+    /// let mut cbd: Cabide<u32> = Cabide::new("test60.file", None)?;
+    /// let block = cbd.write(&0x0102)?;
+    /// assert_eq!(cbd.read_raw(block)?, vec![2, 1, 0, 0]);
+    /// # std::fs::remove_file("test60.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_raw(&mut self, block: u64) -> Result<Vec<u8>, Error> {
+        let (header_len, has_codec) = (self.header_len(), self.codec.is_some());
+        let flag = Some(self.codec_flag_position()).filter(|_| self.codec_threshold.is_some());
+        let mut content = vec![];
+        let mut reader = BlockReader::new(self, block, false)?;
+        let result = reader.read_to_end(&mut content).map_err(Error::from);
+        reader.finish(result)?;
+        if content.len() < header_len {
+            return Err(Error::CorruptedBlock);
+        }
+        self.verify_checksum(block, &content[..header_len], &content[header_len..])?;
+
+        // Objects smaller than the codec threshold were stored as they are
+        let encoded = has_codec && flag.map_or(true, |flag| content[flag] != 0);
+        content.drain(..header_len);
+        match &self.codec {
+            Some(codec) if encoded => codec.decode(content),
+            _ => Ok(content),
+        }
+    }
+
+    /// Sets function that extracts when an object expires (`None` means it never does), used by `reap_expired`
+    #[inline]
+    pub fn set_expiry(
+        &mut self,
+        expires_at: impl Fn(&T) -> Option<SystemTime> + Send + Sync + 'static,
+    ) {
+        self.expiry = Some(Expiry(Box::new(expires_at)));
+    }
+
+    /// Sets maximum size in bytes of an object, reads of bigger objects fail with `Error::ObjectTooLarge`
+    /// instead of allocating however much memory a corrupted length field asks for
+    ///
+    /// Defaults to [`DEFAULT_MAX_OBJECT_SIZE`]
+    #[inline]
+    pub fn set_max_object_size(&mut self, max: u64) {
+        self.max_object_size = max;
+    }
+
+    /// Sets maximum number of blocks of an object, reads that go past it fail with `Error::ObjectTooLong`
+    /// instead of following a corrupted chain of continuation blocks until the end of the file
+    ///
+    /// Defaults to the number of blocks needed by an object of the maximum size
+    #[inline]
+    pub fn set_max_object_blocks(&mut self, max: u64) {
+        self.max_object_blocks = Some(max);
+    }
+
+    /// Size of the header that prefixes the content of every object
+    ///
+    /// It's `[sequence number][stable id][codec flag][checksum][tag]`, each part is only there if its option is
+    /// enabled
+    #[inline]
+    fn header_len(&self) -> usize {
+        let checksum = self.checksums as usize * CHECKSUM_SIZE as usize;
+        self.checksum_position() + checksum + self.tagged as usize
+    }
+
+    /// Position in the content header of the stable id, right after the sequence number
+    #[inline]
+    fn id_position(&self) -> usize {
+        match self.next_sequence {
+            Some(_) => SEQUENCE_SIZE as usize,
+            None => 0,
+        }
+    }
+
+    /// Position in the content header of the flag telling if the object was transformed by the codec, right
+    /// after the sequence number and the stable id (it's only there if there is a codec threshold)
+    #[inline]
+    fn codec_flag_position(&self) -> usize {
+        match self.next_id {
+            Some(_) => self.id_position() + ID_SIZE as usize,
+            None => self.id_position(),
+        }
+    }
+
+    /// Position in the content header of the checksum, right after the codec flag (it's only there if
+    /// checksums are enabled)
+    #[inline]
+    fn checksum_position(&self) -> usize {
+        self.codec_flag_position() + self.codec_threshold.is_some() as usize
+    }
+
+    /// Returns the checksum of an object, covering its whole content header (but the checksum itself) and
+    /// the content that follows it
+    fn checksum(&self, header: &[u8], content: &[u8]) -> u32 {
+        let position = self.checksum_position();
+        let crc = crc32(0, &header[..position]);
+        let crc = crc32(crc, &header[position + CHECKSUM_SIZE as usize..]);
+        crc32(crc, content)
+    }
+
+    /// Fails with `Error::ChecksumMismatch` if the object at specified block doesn't match the checksum in
+    /// its content header, does nothing if checksums are disabled
+    fn verify_checksum(&self, block: u64, header: &[u8], content: &[u8]) -> Result<(), Error> {
+        if !self.checksums {
+            return Ok(());
+        }
+        let position = self.checksum_position();
+        let mut stored = [0; CHECKSUM_SIZE as usize];
+        stored.copy_from_slice(&header[position..position + CHECKSUM_SIZE as usize]);
+        if u32::from_le_bytes(stored) != self.checksum(header, content) {
+            return Err(Error::ChecksumMismatch { block });
+        }
+        Ok(())
+    }
+
+    /// Reads the first `len` bytes of the content of the object at specified block, without decoding it
+    fn read_header(&mut self, block: u64, len: usize) -> Result<Vec<u8>, Error> {
+        let mut header = vec![0; len];
+        let mut reader = BlockReader::new(self, block, false)?;
+        let result = reader.read_exact(&mut header).map_err(Error::from);
+        reader.finish(result)?;
+        Ok(header)
+    }
+
+    /// Removes sequence number and stable id of the object with specified content header from their indexes,
+    /// if there are any
+    #[inline]
+    fn unindex(&mut self, header: &[u8]) {
+        if let (Some(index), Some(sequence)) = (
+            &mut self.sequence_index,
+            header.get(..SEQUENCE_SIZE as usize),
+        ) {
+            let mut bytes = [0; SEQUENCE_SIZE as usize];
+            bytes.copy_from_slice(sequence);
+            index.remove(&u64::from_le_bytes(bytes));
+        }
+
+        let position = self.id_position();
+        if let (Some(_), Some(id)) = (
+            self.next_id,
+            header.get(position..position + ID_SIZE as usize),
+        ) {
+            let mut bytes = [0; ID_SIZE as usize];
+            bytes.copy_from_slice(id);
+            self.id_index.remove(&u64::from_le_bytes(bytes));
+        }
+    }
+
+    /// Returns sequence number of the object at specified block, sequence numbers must be enabled
+    fn read_sequence(&mut self, block: u64) -> Result<u64, Error> {
+        let mut sequence = [0; SEQUENCE_SIZE as usize];
+        sequence.copy_from_slice(&self.read_header(block, SEQUENCE_SIZE as usize)?);
+        Ok(u64::from_le_bytes(sequence))
+    }
+
+    /// Returns stable id of the object at specified block, stable ids must be enabled
+    fn read_id(&mut self, block: u64) -> Result<u64, Error> {
+        let position = self.id_position();
+        let header = self.read_header(block, position + ID_SIZE as usize)?;
+        let mut id = [0; ID_SIZE as usize];
+        id.copy_from_slice(&header[position..]);
+        Ok(u64::from_le_bytes(id))
+    }
+
+    /// Returns the variant tag of the object at specified block, it must be tagged
+    pub(crate) fn read_tag(&mut self, block: u64) -> Result<u8, Error> {
+        let header = self.read_header(block, self.header_len())?;
+        Ok(header[header.len() - 1])
+    }
+
+    /// Maximum number of blocks an object may span
+    #[inline]
+    fn object_blocks_limit(&self) -> u64 {
+        self.max_object_blocks
+            .unwrap_or_else(|| (self.max_object_size + LENGTH_SIZE) / self.content_size() + 1)
+    }
+
+    /// Moves the file cursor to the start of specified block
+    #[inline]
+    fn seek_block(&mut self, block: u64) -> Result<(), Error> {
+        self.file.seek(SeekFrom::Start(self.block_start(block)?))?;
+        Ok(())
+    }
+
+    /// Returns position in the file of the first byte of specified block
+    ///
+    /// Huge block numbers (like from a corrupted index) would overflow, instead of wrapping to another block
+    /// they are out of bounds
+    #[inline(always)]
+    pub(crate) fn block_start(&self, block: u64) -> Result<u64, Error> {
+        block
+            .checked_mul(Self::BLOCK_SIZE)
+            .and_then(|start| start.checked_add(self.offset))
+            .ok_or_else(|| self.out_of_bounds(block))
+    }
+
+    /// Error for a block past the end of the database
+    #[inline]
+    pub(crate) fn out_of_bounds(&self, block: u64) -> Error {
+        match self.blocks() {
+            Ok(blocks) => Error::BlockOutOfBounds { block, blocks },
+            Err(err) => err,
+        }
+    }
+
+    /// Empties the blocks of the object at specified block if `err` means it's corrupted and corrupted
+    /// objects are quarantined, errors are ignored since scans ignore them anyway
+    fn quarantine(&mut self, block: u64, err: &Error) {
+        match err {
+            Error::CorruptedBlock
+            | Error::Serde(_)
+            | Error::ChecksumMismatch { .. }
+            | Error::ObjectTooLarge { .. }
+            | Error::ObjectTooLong { .. }
+                if self.quarantined.is_some() && !self.file.read_only => {}
+            _ => return,
+        }
+
+        let _ = self.empty_object(block);
+        if let Some(quarantined) = &mut self.quarantined {
+            quarantined.push(block);
+        }
+    }
+
+    /// Drops the object starting at specified block from the read cache, if it's there
+    #[inline]
+    pub(crate) fn uncache(&mut self, block: u64) {
+        if let Some(cache) = &mut self.read_cache {
+            cache.invalidate(block);
+        }
+    }
+
+    /// Drops every object from the read cache, for changes that move objects around
+    #[inline]
+    pub(crate) fn clear_read_cache(&mut self) {
+        if let Some(cache) = &mut self.read_cache {
+            cache.clear();
+        }
+    }
+
+    /// Marks the block and the continuations after it as empty (following the links of fragmented objects),
+    /// without reading their content, caching them, returns how many blocks were emptied
+    fn empty_object(&mut self, block: u64) -> Result<u64, Error> {
+        self.uncache(block);
+        let (mut start, mut emptied) = (block, 0);
+        loop {
+            let mut blocks = 0;
+            while blocks == 0
+                || self.read_metadata(start + blocks)? == Some(Metadata::Continuation as u8)
+            {
+                self.seek_block(start + blocks)?;
+                self.file.write_all(&[Metadata::Empty as u8])?;
+                blocks += 1;
+            }
+            self.free_run(start, blocks);
+            emptied += blocks;
+
+            // Emptied blocks are never linked to, so corrupted links can't loop
+            match self.read_link(start + blocks - 1)? {
+                Some(next) if self.read_metadata(next)? == Some(Metadata::Fragment as u8) => {
+                    start = next
+                }
+                _ => return Ok(emptied),
+            }
+        }
+    }
+
+    /// Returns the block linked to by specified block, if it's the last block of an extent of a fragmented
+    /// object
+    fn read_link(&mut self, block: u64) -> Result<Option<u64>, Error> {
+        // Links need room for the metadata before them
+        if Self::BLOCK_SIZE <= LINK_SIZE + 1 {
+            return Ok(None);
+        }
+        let mut link = [0; LINK_SIZE as usize + 1];
+        let start = self.block_start(block)? + Self::BLOCK_SIZE - link.len() as u64;
+        self.file.seek(SeekFrom::Start(start))?;
+        match self.file.read_exact(&mut link) {
+            Ok(()) if link[LINK_SIZE as usize] == LINK_BYTE => {}
+            Ok(()) => return Ok(None),
+            // Last block of a truncated file
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let mut next = [0; LINK_SIZE as usize];
+        next.copy_from_slice(&link[..LINK_SIZE as usize]);
+        Ok(Some(u64::from_le_bytes(next)))
+    }
+
+    /// Places content (already prefixed by its header and encoded) in free blocks or at the end of the file,
+    /// framing its blocks into `framed`, returns its starting block
+    ///
+    /// Only re-uses runs of at least `min_extent` free blocks, unless it's fragmented
+    pub(crate) fn write_raw(
+        &mut self,
+        raw: &[u8],
+        min_extent: u64,
+        framed: &mut Vec<u8>,
+    ) -> Result<u64, Error> {
+        let block_size = Self::BLOCK_SIZE;
+        let needed = self.needed_blocks(raw);
+        // First we check if there are empty blocks with the needed size (if re-using them is desired)
+        if let Some(block) = self.allocate(needed, min_extent) {
+            return self.write_extents(raw, &[(block, needed)], framed);
+        }
+        if self.fragmentation && min_extent <= 1 {
+            if let Some(extents) = self.allocate_fragments(LENGTH_SIZE + raw.len() as u64) {
+                return self.write_extents(raw, &extents, framed);
+            }
+        }
+
+        // If there wasn't any fragmented empty block we take the next available one
+        // We need to update self.next_block taking into account how many bytes we are writing
+        let block = self.next_block;
+        let end = block
+            .checked_add(needed)
+            .and_then(|end| end.checked_mul(block_size))
+            .ok_or_else(|| self.out_of_bounds(block))?;
+        if let Some(len) = self.len.filter(|len| end > *len) {
+            // Nothing is written, so whatever comes after the range is never touched
+            return Err(Error::OutOfSpace {
+                needed,
+                available: (len / block_size).saturating_sub(block),
+            });
+        }
+        self.check_size_limit(block + needed)?;
+
+        self.next_block += needed;
+        self.write_extents(raw, &[(block, needed)], framed)
+    }
+
+    /// Returns how many continuous blocks hold specified content, prefixed by its length
+    #[inline]
+    fn needed_blocks(&self, raw: &[u8]) -> u64 {
+        (LENGTH_SIZE + raw.len() as u64).div_ceil(self.content_size())
+    }
+
+    /// Takes free blocks one at a time, until they have room for `len` bytes of content, blocks next to
+    /// each other are merged into `(starting block, blocks)` extents
+    ///
+    /// Every extent but the last one loses room for the link to the next one, if there aren't enough free
+    /// blocks they are cached again and `None` is returned
+    fn allocate_fragments(&mut self, len: u64) -> Option<Vec<(u64, u64)>> {
+        let content_size = self.content_size();
+        if content_size <= LINK_SIZE {
+            return None;
+        }
+
+        let (mut extents, mut room): (Vec<(u64, u64)>, u64) = (vec![], 0);
+        while extents.is_empty() || room < len {
+            let block = match self.allocate(1, 1) {
+                Some(block) => block,
+                None => {
+                    for (start, blocks) in extents {
+                        self.free_run(start, blocks);
+                    }
+                    return None;
+                }
+            };
+
+            room += content_size;
+            match extents.last_mut() {
+                Some((start, blocks)) if *start + *blocks == block => *blocks += 1,
+                Some(_) => {
+                    room -= LINK_SIZE;
+                    extents.push((block, 1));
+                }
+                None => extents.push((block, 1)),
+            }
+        }
+        Some(extents)
+    }
+
+    /// Writes content to the `(starting block, blocks)` extents, prefixed by its length, framing each block
+    /// with its metadata and END_BYTE, or the link to the next extent, returns the first block
+    fn write_extents(
+        &mut self,
+        mut raw: &[u8],
+        extents: &[(u64, u64)],
+        framed: &mut Vec<u8>,
+    ) -> Result<u64, Error> {
+        let (block_size, content_size) = (Self::BLOCK_SIZE, self.content_size() as usize);
+        // Tiny blocks may split the length itself
+        let length = (raw.len() as u64).to_le_bytes();
+        let mut length = &length[..];
+        if let Some((start, _)) = extents.first() {
+            self.uncache(*start);
+        }
+        for (extent, (start, blocks)) in extents.iter().enumerate() {
+            let next = extents.get(extent + 1).map(|(next, _)| *next);
+            framed.clear();
+            for block in 0..*blocks {
+                let metadata = match (block, extent) {
+                    (0, 0) => Metadata::SizedStart,
+                    (0, _) => Metadata::Fragment,
+                    _ => Metadata::Continuation,
+                };
+                framed.push(metadata as u8);
+
+                let link = next.filter(|_| block + 1 == *blocks);
+                let room = match link {
+                    Some(_) => content_size - LINK_SIZE as usize,
+                    None => content_size,
+                };
+                let (prefix, rest) = length.split_at(room.min(length.len()));
+                framed.extend_from_slice(prefix);
+                length = rest;
+                let (chunk, rest) = raw.split_at((room - prefix.len()).min(raw.len()));
+                framed.extend_from_slice(chunk);
+                raw = rest;
+
+                match link {
+                    Some(next) => {
+                        framed.extend_from_slice(&next.to_le_bytes());
+                        framed.push(LINK_BYTE);
+                    }
+                    None => framed.push(END_BYTE),
+                }
+            }
+
+            // Last chunk may need to be padded, a content that fills it still has its END_BYTE
+            framed.resize((blocks * block_size) as usize, PAD_BYTE);
+            self.seek_block(*start)?;
+            self.file.write_all(framed)?;
+        }
+        Ok(extents[0].0)
+    }
+
+    /// Caches a run of freed blocks, merging it with the free runs right before and after it
+    ///
+    /// So objects bigger than each of the freed runs can still re-use them
+    pub(crate) fn free_run(&mut self, mut start: u64, mut blocks: u64) {
+        if self.allocator.is_some() {
+            return self.push_free_run(start, blocks);
+        }
+
+        let end = start + blocks;
+        let neighbors: Vec<(usize, u64)> = self
+            .empty_blocks
+            .iter()
+            .flat_map(|(size, block_vec)| block_vec.iter().map(move |block| (*size, *block)))
+            .filter(|(size, block)| *block + *size as u64 == start || *block == end)
+            .collect();
+
+        for (size, block) in neighbors {
+            if let Some(block_vec) = self.empty_blocks.get_mut(&size) {
+                block_vec.retain(|other| *other != block);
+                if block_vec.is_empty() {
+                    self.empty_blocks.remove(&size);
+                }
+            }
+            start = start.min(block);
+            blocks += size as u64;
+        }
+
+        self.push_free_run(start, blocks);
+    }
+
+    /// Caches a run of free blocks as it is, without merging it
+    fn push_free_run(&mut self, start: u64, blocks: u64) {
+        if let Some(allocator) = &mut self.allocator {
+            return allocator.free(start, blocks);
+        }
+        self.empty_blocks
+            .entry(blocks as usize)
+            .and_modify(|vec| vec.push(start))
+            .or_insert_with(|| vec![start]);
+    }
+
+    /// Stops caching the blocks in `[start, end)` as free, since an object will be written to them, the blocks
+    /// skipped between the last object and `start` become free
+    ///
+    /// Does nothing to custom allocators but moving the next empty block, the file must be scanned again
+    fn take_free_blocks(&mut self, start: u64, end: u64) {
+        if start > self.next_block {
+            self.free_run(self.next_block, start - self.next_block);
+        }
+        self.next_block = self.next_block.max(end);
+        if self.allocator.is_some() {
+            return;
+        }
+
+        let overlapping: Vec<(usize, u64)> = self
+            .empty_blocks
+            .iter()
+            .flat_map(|(size, block_vec)| block_vec.iter().map(move |block| (*size, *block)))
+            .filter(|(size, block)| *block < end && *block + *size as u64 > start)
+            .collect();
+        for (size, block) in overlapping {
+            if let Some(block_vec) = self.empty_blocks.get_mut(&size) {
+                block_vec.retain(|other| *other != block);
+                if block_vec.is_empty() {
+                    self.empty_blocks.remove(&size);
+                }
+            }
+            if block < start {
+                self.push_free_run(block, start - block);
+            }
+            if block + size as u64 > end {
+                self.push_free_run(end, block + size as u64 - end);
+            }
+        }
+    }
+
+    /// Forgets every cached free block
+    pub(crate) fn clear_free_runs(&mut self) {
+        self.empty_blocks.clear();
+        if let Some(allocator) = &mut self.allocator {
+            allocator.clear();
+        }
+    }
+
+    /// Takes a free run with at least `min_extent` blocks to write `needed` blocks into, returns its first
+    /// block, the unused blocks of the run stay free
+    fn allocate(&mut self, needed: u64, min_extent: u64) -> Option<u64> {
+        if !self.reuse_free_blocks {
+            return None;
+        }
+        if let Some(allocator) = &mut self.allocator {
+            let extent = needed.max(min_extent);
+            let start = allocator.allocate(extent)?;
+            if extent > needed {
+                allocator.free(start + needed, extent - needed);
+            }
+            return Some(start);
+        }
+
+        let blocks_needed = needed as usize;
+        let fits = |blocks: usize| blocks >= blocks_needed && blocks as u64 >= min_extent;
+        let (mut starting_block, mut remaining_blocks, mut delete_block) = (None, None, None);
+        match self.allocation {
+            Allocation::BestFit => {
+                for (blocks, block_vec) in &mut self.empty_blocks {
+                    if fits(*blocks) {
+                        starting_block = block_vec.pop();
+
+                        if let Some(starting_block) = starting_block {
+                            let index = starting_block as usize;
+                            remaining_blocks =
+                                Some((*blocks - blocks_needed, index + blocks_needed));
+                            break;
+                        } else if delete_block.is_none() {
+                            // We need to handle empty leafs, but we only handle one at a time
+                            delete_block = Some(*blocks);
+                        }
+                    }
+                }
+            }
+            Allocation::FirstFit => {
+                let first = self
+                    .empty_blocks
+                    .iter()
+                    .filter(|(blocks, _)| fits(**blocks))
+                    .flat_map(|(blocks, block_vec)| {
+                        let blocks = *blocks;
+                        block_vec
+                            .iter()
+                            .enumerate()
+                            .map(move |(position, block)| (*block, blocks, position))
+                    })
+                    .min();
+
+                if let Some((block, blocks, position)) = first {
+                    if let Some(block_vec) = self.empty_blocks.get_mut(&blocks) {
+                        block_vec.remove(position);
+                        if block_vec.is_empty() {
+                            delete_block = Some(blocks);
+                        }
+                    }
+                    starting_block = Some(block);
+                    remaining_blocks =
+                        Some((blocks - blocks_needed, block as usize + blocks_needed));
+                }
+            }
+        }
+
+        // If BTreeMap leaf's has no starting block we remove it
+        if let Some(blocks) = delete_block {
+            self.empty_blocks.remove(&blocks);
+        }
+
+        // Returns unused free blocks from the extracted chain to the empty_blocks list
+        if let Some((blocks, index)) = remaining_blocks.filter(|(blocks, _)| *blocks > 0) {
+            self.push_free_run(index as u64, blocks as u64);
+        }
+        starting_block
+    }
+
+    /// Reads the metadata byte of specified block, `None` means EOF
+    #[inline]
+    fn read_metadata(&mut self, block: u64) -> Result<Option<u8>, Error> {
+        let mut metadata = [0];
+        let start = self.block_start(block)?;
+        if self
+            .len
+            .is_some_and(|len| start - self.offset + Self::BLOCK_SIZE > len)
+        {
+            return Ok(None);
+        }
+
+        self.file.seek(SeekFrom::Start(start))?;
+        if Read::by_ref(&mut self.file).take(1).read(&mut metadata)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(metadata[0]))
+    }
+}
+
+impl<T, const B: usize> Drop for Cabide<T, B> {
+    #[inline]
+    fn drop(&mut self) {
+        // Errors can't be reported when dropping, call `sync_data` or `sync_all` to handle them
+        let _ = match self.sync_on_drop {
+            Some(SyncMethod::Data) => self.file.sync_data(),
+            Some(SyncMethod::All) => self.file.sync_all(),
+            None => Ok(()),
+        };
+        let _ = self.flush_index();
+    }
+}
+
+impl<T, const B: usize> Cabide<T, B>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    /// Reads (and maybe removes) object at specified block as `U`, returns it with the number of blocks read
+    #[inline(always)]
+    fn read_update_metadata<U>(
+        &mut self,
+        block: u64,
+        empty_read_blocks: bool,
+    ) -> Result<(U, u64), Error>
+    where
+        for<'de> U: Deserialize<'de>,
+    {
+        let (max, has_codec) = (self.max_object_size, self.codec.is_some());
+        let (flag, checksums) = (
+            Some(self.codec_flag_position()).filter(|_| self.codec_threshold.is_some()),
+            self.checksums,
+        );
+        let mut header = vec![0; self.header_len()];
+        let mut reader = BlockReader::new(self, block, empty_read_blocks)?;
+        let read_header = reader.read_exact(&mut header).map_err(Error::from);
+
+        // Objects smaller than the codec threshold were stored as they are
+        let encoded = has_codec && flag.map_or(true, |flag| header[flag] != 0);
+        if !encoded && !checksums {
+            // Deserializes straight from the blocks, never holding the whole content in memory
+            let result = read_header.and_then(|_| Self::decode(max, &mut reader));
+            let result = reader.finish_counted(result);
+            if empty_read_blocks && result.is_ok() {
+                self.unindex(&header);
+            }
+            return result;
+        }
+
+        let mut content = vec![];
+        let result =
+            read_header.and_then(|_| reader.read_to_end(&mut content).map_err(Error::from));
+        let (_, blocks) = reader.finish_counted(result)?;
+        if empty_read_blocks {
+            self.unindex(&header);
+        }
+
+        self.verify_checksum(block, &header, &content)?;
+        if let Some(codec) = self.codec.as_ref().filter(|_| encoded) {
+            content = codec.decode(content)?;
+        }
+        Ok((Self::decode(max, content.as_slice())?, blocks))
+    }
+
+    /// Deserializes object, making sure length fields don't make it go over the maximum object size
+    #[inline]
+    fn decode<U>(max: u64, content: impl Read) -> Result<U, Error>
+    where
+        for<'de> U: Deserialize<'de>,
+    {
+        bincode_options()
+            .with_limit(max)
+            .deserialize_from(content)
+            .map_err(|err| match *err {
+                bincode::ErrorKind::SizeLimit => Error::ObjectTooLarge { claimed: None, max },
+                _ => Error::Serde(err),
+            })
+    }
+
+    /// Mark object blocks as empty, cacheing them, returns removed content
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test3.file")?;
+    /// // Opens database, creates it since it's first run
+    /// let mut cbd: Cabide<u8> = Cabide::new("test3.file", None)?;
+    ///
+    /// for i in 0..100 {
+    ///     cbd.write(&i)?;
+    /// }
+    ///
+    /// assert_eq!(cbd.remove(30)?, 30);
+    /// assert_eq!(cbd.remove(3)?, 3);
+    ///
+    /// // Writing re-uses freed blocks in the middle of the file (using the last ones first)
+    /// assert_eq!(cbd.write(&100)?, 3);
+    /// assert_eq!(cbd.remove(3)?, 100);
+    /// # std::fs::remove_file("test3.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn remove(&mut self, block: u64) -> Result<T, Error> {
+        let result = self.read_update_metadata(block, true).map(|(obj, _)| obj);
+        self.synced(result)
+    }
+
+    /// Returns object deserialized from specified starting block (and its continuations)
+    ///
+    /// It's always read from the file, use [`Cabide::read_shared`] to serve repeated reads from the read cache
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// // Opens file pre-filling it, creates it since it's first run
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test4.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test4.file", None)?;
+    ///
+    /// for i in 0..100 {
+    ///     cbd.write(&i)?;
+    /// }
+    ///
+    /// assert_eq!(cbd.read(30)?, 30);
+    /// assert_eq!(cbd.read(3)?, 3);
+    /// # std::fs::remove_file("test4.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read(&mut self, block: u64) -> Result<T, Error> {
+        self.read_update_metadata(block, false).map(|(obj, _)| obj)
+    }
+
+    /// Same as `read`, but the object is shared with the read cache (see [`CabideBuilder::read_cache`]), so
+    /// reading it again is served from memory until it's changed
+    ///
+    /// Without a read cache it's the same as `read`
+    pub fn read_shared(&mut self, block: u64) -> Result<Arc<T>, Error> {
+        if let Some(obj) = self.read_cache.as_mut().and_then(|cache| cache.get(block)) {
+            return Ok(obj);
+        }
+        let obj = Arc::new(self.read(block)?);
+        if let Some(cache) = &mut self.read_cache {
+            cache.insert(block, Arc::clone(&obj));
+        }
+        Ok(obj)
+    }
+
+    /// Same as `read`, but returns `None` if there is no object starting at specified block (it's empty, in
+    /// the middle of an object or past the end of the database), so errors are only returned for IO failures
+    /// and corrupted objects
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test59.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test59.file", Some(10))?;
+    /// let block = cbd.write(&1)?;
+    ///
+    /// assert_eq!(cbd.get(block)?, Some(1));
+    /// assert_eq!(cbd.get(block + 1)?, None);
+    /// assert_eq!(cbd.get(100)?, None);
+    /// # std::fs::remove_file("test59.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn get(&mut self, block: u64) -> Result<Option<T>, Error> {
+        match self.read(block) {
+            Ok(obj) => Ok(Some(obj)),
+            Err(Error::EmptyBlock { .. })
+            | Err(Error::ContinuationBlock { .. })
+            | Err(Error::BlockOutOfBounds { .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Same as `read`, but deserializes the object as `U`, which must be a prefix of `T`'s layout (like a
+    /// struct with only `T`'s first fields, in the same order), so projections don't decode every field
+    ///
+    /// Nothing checks that `U` is compatible with `T`, an incompatible type returns garbage or
+    /// `Error::Serde`
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test35.file")?;
+    /// let mut cbd: Cabide<(u8, String, Vec<u64>)> = Cabide::new("test35.file", None)?;
+    ///
+    /// let block = cbd.write(&(1, "name".to_owned(), vec![2; 100]))?;
+    /// assert_eq!(cbd.read_as::<(u8, String)>(block)?, (1, "name".to_owned()));
+    /// # std::fs::remove_file("test35.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn read_as<U>(&mut self, block: u64) -> Result<U, Error>
+    where
+        for<'de> U: Deserialize<'de>,
+    {
+        self.read_update_metadata(block, false).map(|(obj, _)| obj)
+    }
+
+    /// Same as `read`, but also returns the number of blocks that were read
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test26.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::new("test26.file", None)?;
+    ///
+    /// let small = cbd.write(&"small".to_owned())?;
+    /// let big = cbd.write(&"big".repeat(20))?;
+    ///
+    /// assert_eq!(cbd.read_counted(small)?, ("small".to_owned(), 1));
+    /// assert_eq!(cbd.read_counted(big)?, ("big".repeat(20), 3));
+    /// # std::fs::remove_file("test26.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn read_counted(&mut self, block: u64) -> Result<(T, u64), Error> {
+        self.read_update_metadata(block, false)
+    }
+
+    /// Reads objects from every specified starting block, returning them in the same order
+    ///
+    /// Blocks are read in ascending order, so scattered reads (like from an index) walk the file only once
+    pub fn read_batch(&mut self, blocks: &[u64]) -> Vec<Result<T, Error>> {
+        let mut sorted: Vec<(usize, u64)> = blocks.iter().copied().enumerate().collect();
+        sorted.sort_unstable_by_key(|(_, block)| *block);
+
+        let mut results: Vec<_> = sorted
+            .into_iter()
+            .map(|(index, block)| (index, self.read(block)))
+            .collect();
+        results.sort_unstable_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Lazily iterates over every object (and its starting block) in block order, only one is in memory at a
+    /// time
+    ///
+    /// Empty blocks and continuations are skipped, unlike `filter` errors aren't ignored, they are yielded
+    /// and the iteration goes on
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test57.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test57.file", Some(10))?;
+    /// for i in 0..5 {
+    ///     cbd.write(&i)?;
+    /// }
+    /// cbd.remove(1)?;
+    ///
+    /// let mut sum = 0;
+    /// for res in cbd.iter() {
+    ///     let (block, obj) = res?;
+    ///     assert_eq!(block, obj as u64);
+    ///     sum += obj;
+    /// }
+    /// assert_eq!(sum, 9);
+    /// # std::fs::remove_file("test57.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn iter(&mut self) -> CabideIter<'_, T, B> {
+        let blocks = self.written_blocks();
+        CabideIter::new(self, blocks)
+    }
+
+    /// Iterates over every object (and its starting block) in the order they were written, no matter
+    /// where they were placed, if sequence numbers are enabled (otherwise in block order)
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test23.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::builder().sequence_numbers(true).open("test23.file")?;
+    ///
+    /// for i in 0..5 {
+    ///     cbd.write(&i)?;
+    /// }
+    /// cbd.remove(1)?;
+    /// cbd.write(&5)?;
+    ///
+    /// let objects: Result<Vec<_>, _> = cbd.iter_ordered()?.map(|res| res.map(|(_, obj)| obj)).collect();
+    /// assert_eq!(objects?, vec![0, 2, 3, 4, 5]);
+    /// # std::fs::remove_file("test23.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn iter_ordered(
+        &mut self,
+    ) -> Result<impl Iterator<Item = Result<(u64, T), Error>> + '_, Error> {
+        let mut sequences = vec![];
+        for block in self.object_blocks()? {
+            let sequence = match self.next_sequence {
+                Some(_) => self.read_sequence(block)?,
+                None => 0,
+            };
+            sequences.push((sequence, block));
+        }
+        sequences.sort_unstable();
+
+        Ok(sequences
+            .into_iter()
+            .map(move |(_, block)| self.read(block).map(|obj| (block, obj))))
+    }
+
+    /// Returns objects written after the one with specified sequence number (like a checkpoint), with their
+    /// starting blocks, in the order they were written, nothing if sequence numbers are disabled
+    ///
+    /// Replaced objects are written again, so they are returned, but removals can't be, since removed objects
+    /// leave nothing behind. Errors are ignored, just like `filter`
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test48.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::builder().sequence_numbers(true).open("test48.file")?;
+    /// for i in 0..5 {
+    ///     cbd.write(&i)?;
+    /// }
+    /// let checkpoint = cbd.sequence(4)?;
+    ///
+    /// cbd.write(&5)?;
+    /// let changes: Vec<u8> = cbd.changes_since(checkpoint).map(|(_, obj)| obj).collect();
+    /// assert_eq!(changes, vec![5]);
+    /// # std::fs::remove_file("test48.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn changes_since(&mut self, sequence: u64) -> impl Iterator<Item = (u64, T)> + '_ {
+        let mut changes: Vec<(u64, u64)> = match &self.sequence_index {
+            Some(index) => index
+                .range((Bound::Excluded(sequence), Bound::Unbounded))
+                .map(|(sequence, block)| (*sequence, *block))
+                .collect(),
+            None if self.next_sequence.is_some() => {
+                let mut changes = vec![];
+                for block in self.object_blocks().unwrap_or_default() {
+                    match self.read_sequence(block) {
+                        Ok(written) if written > sequence => changes.push((written, block)),
+                        _ => continue,
+                    }
+                }
+                changes
+            }
+            None => vec![],
+        };
+        changes.sort_unstable();
+
+        changes
+            .into_iter()
+            .filter_map(move |(_, block)| self.read(block).ok().map(|obj| (block, obj)))
+    }
+
+    /// Returns object with specified sequence number, no matter where it was placed, fails with
+    /// `Error::NotExistant` if there is none (always, if sequence numbers are disabled)
+    ///
+    /// Scans the file for it, unless sequence numbers are indexed (see [`CabideBuilder::sequence_index`])
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test34.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::builder().sequence_numbers(true).open("test34.file")?;
+    ///
+    /// for i in 0..5 {
+    ///     cbd.write(&i)?;
+    /// }
+    /// cbd.remove(1)?;
+    /// // Re-uses the freed block
+    /// assert_eq!(cbd.write(&5)?, 1);
+    ///
+    /// assert_eq!(cbd.read_seq(5)?, 5);
+    /// assert!(cbd.read_seq(1).is_err());
+    /// # std::fs::remove_file("test34.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_seq(&mut self, sequence: u64) -> Result<T, Error> {
+        let block = self.find_sequence(sequence)?.ok_or(Error::NotExistant)?;
+        self.read(block)
+    }
+
+    /// Returns sequence number of the object at specified block, its version since rewriting an object gives it
+    /// a new one, fails with `Error::NotExistant` if sequence numbers are disabled
+    #[inline]
+    pub fn sequence(&mut self, block: u64) -> Result<u64, Error> {
+        if self.next_sequence.is_none() {
+            return Err(Error::NotExistant);
+        }
+        self.read_sequence(block)
+    }
+
+    /// Returns stable id of the object at specified block, fails with `Error::NotExistant` if stable ids are
+    /// disabled, see [`CabideBuilder::stable_ids`]
+    #[inline]
+    pub fn id(&mut self, block: u64) -> Result<u64, Error> {
+        if self.next_id.is_none() {
+            return Err(Error::NotExistant);
+        }
+        self.read_id(block)
+    }
+
+    /// Returns object with specified stable id, no matter where it was moved, fails with
+    /// `Error::NotExistant` if there is none (always, if stable ids are disabled)
+    #[inline]
+    pub fn read_by_id(&mut self, id: u64) -> Result<T, Error> {
+        let block = *self.id_index.get(&id).ok_or(Error::NotExistant)?;
+        self.read(block)
+    }
+
+    /// Removes object with specified stable id, see [`Cabide::read_by_id`]
+    #[inline]
+    pub fn remove_by_id(&mut self, id: u64) -> Result<T, Error> {
+        let block = *self.id_index.get(&id).ok_or(Error::NotExistant)?;
+        self.remove(block)
+    }
+
+    /// Returns starting block of the object with specified sequence number, if any
+    fn find_sequence(&mut self, sequence: u64) -> Result<Option<u64>, Error> {
+        if self.next_sequence.is_none() {
+            return Ok(None);
+        }
+        if let Some(index) = &self.sequence_index {
+            return Ok(index.get(&sequence).copied());
+        }
+
+        for block in self.object_blocks()? {
+            if self.read_sequence(block)? == sequence {
+                return Ok(Some(block));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the last object of the file (and its starting block), if any
+    ///
+    /// Walks backwards from the last block, skipping empty and continuation blocks until an object start is found
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test11.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::new("test11.file", None)?;
+    /// assert_eq!(cbd.last()?, None);
+    ///
+    /// cbd.write(&"first".to_owned())?;
+    /// let block = cbd.write(&"last but spanning multiple blocks".repeat(3))?;
+    /// assert_eq!(cbd.last()?, Some((block, "last but spanning multiple blocks".repeat(3))));
+    /// # std::fs::remove_file("test11.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn last(&mut self) -> Result<Option<(u64, T)>, Error> {
+        for block in (0..self.next_block).rev() {
+            if self.read_metadata(block)?.is_some_and(Metadata::is_start) {
+                return Ok(Some((block, self.read(block)?)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns first element to be selected by the `filter` function
+    ///
+    /// Works in O(n), testing each block until the first is found
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use cabide::Cabide;
+    ///
+    /// #[derive(Debug, Serialize, Deserialize)]
+    /// struct Student {
+    ///     name: String,
+    ///     dre: u64,
+    ///     classes: Vec<u16>
+    /// }
+    ///
+    /// # use rand::{distributions::*, random, thread_rng};
+    /// # fn random_student(classes: Vec<u16>) -> Student {
+    /// #     // this is slow af since it doesn't cache thread_rng
+    /// #     let mut dre = random();
+    /// #     while dre == 10101010 {
+    /// #         dre = random();
+    /// #     }
+    /// #     Student {
+    /// #         name: (0..7)
+    /// #             .map(|_| Alphanumeric.sample(&mut thread_rng()))
+    /// #             .collect(),
+    /// #         dre,
+    /// #         classes: classes.clone(),
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test5.file")?;
+    /// // Opens file pre-filling it, creates it since it's first run
+    /// let mut cbd: Cabide<Student> = Cabide::new("test5.file", Some(1000))?;
+    /// assert_eq!(cbd.blocks()?, 1000);
+    ///
+    /// for i in 0..20 {
+    ///     cbd.write(&random_student(vec![1023, random(), random()]))?;
+    /// }
+    ///
+    /// cbd.write(&Student {
+    ///     name: "Mr Legit Student".to_owned(),
+    ///     dre: 10101010,
+    ///     classes: vec![3],
+    /// })?;
+    ///
+    /// let student = cbd.first(|student| student.dre == 10101010).unwrap();
+    /// assert_eq!(&student.name, "Mr Legit Student");
+    /// # std::fs::remove_file("test5.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn first(&mut self, mut filter: impl FnMut(&T) -> bool) -> Option<T> {
+        for block in 0..self.written_blocks() {
+            match self.read(block) {
+                Ok(data) => {
+                    if filter(&data) {
+                        return Some(data);
+                    }
+                }
+                Err(Error::EmptyBlock { .. }) => continue,
+                Err(Error::ContinuationBlock { .. }) => continue,
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Same as `first`, but also returns the starting block and doesn't ignore errors
+    pub(crate) fn position(
+        &mut self,
+        mut filter: impl FnMut(&T) -> bool,
+    ) -> Result<Option<(u64, T)>, Error> {
+        for block in 0..self.written_blocks() {
+            match self.read(block) {
+                Ok(data) => {
+                    if filter(&data) {
+                        return Ok(Some((block, data)));
+                    }
+                }
+                Err(Error::EmptyBlock { .. }) => continue,
+                Err(Error::ContinuationBlock { .. }) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the starting block of the first object that matches the filter, so it can be removed or replaced
+    ///
+    /// Unlike `first` errors aren't ignored
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test25.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::new("test25.file", None)?;
+    ///
+    /// cbd.write(&"first".to_owned())?;
+    /// let block = cbd.write(&"second".to_owned())?;
+    ///
+    /// assert_eq!(cbd.find_block(|name| name == "second")?, Some(block));
+    /// assert_eq!(cbd.find_block(|name| name == "third")?, None);
+    /// # std::fs::remove_file("test25.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn find_block(&mut self, filter: impl FnMut(&T) -> bool) -> Result<Option<u64>, Error> {
+        Ok(self.position(filter)?.map(|(block, _)| block))
+    }
+
+    /// Returns the `n`-th object (counting from 0) in block order, skipping empty blocks, or `None` if there
+    /// are fewer objects
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test42.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test42.file", None)?;
+    /// for i in 0..5 {
+    ///     cbd.write(&i)?;
+    /// }
+    /// cbd.remove(1)?;
+    ///
+    /// assert_eq!(cbd.nth(1)?, Some(2));
+    /// assert_eq!(cbd.nth(4)?, None);
+    /// # std::fs::remove_file("test42.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn nth(&mut self, mut n: u64) -> Result<Option<T>, Error> {
+        let (mut block, blocks) = (0, self.written_blocks());
+        while block < blocks {
+            match self.read(block) {
+                Ok(data) if n == 0 => return Ok(Some(data)),
+                // Extents of fragmented objects may have other objects between them, so blocks aren't skipped
+                Ok(_) => {
+                    n -= 1;
+                    block += 1;
+                }
+                Err(Error::EmptyBlock { .. }) | Err(Error::ContinuationBlock { .. }) => block += 1,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns list of element selected by the `filter` function
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use cabide::Cabide;
+    ///
+    /// #[derive(Debug, Serialize, Deserialize)]
+    /// struct Student {
+    ///     name: String,
+    ///     dre: u64,
+    ///     classes: Vec<u16>
+    /// }
+    ///
+    /// # use rand::{distributions::*, random, thread_rng};
+    /// # fn random_student(classes: Vec<u16>) -> Student {
+    /// #     // this is slow af since it doesn't cache thread_rng
+    /// #     Student {
+    /// #         name: (0..7)
+    /// #             .map(|_| Alphanumeric.sample(&mut thread_rng()))
+    /// #             .collect(),
+    /// #         dre: random(),
+    /// #         classes,
+    /// #     }
+    /// # }
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test6.file")?;
+    /// // Opens file pre-filling it, creates it since it's first run
+    /// let mut cbd: Cabide<Student> = Cabide::new("test6.file", Some(1000))?;
+    ///
+    /// cbd.write(&Student {
+    ///     name: "Mr Legit Student".to_owned(),
+    ///     dre: 10101010,
+    ///     classes: vec![3],
+    /// })?;
+    ///
+    /// for i in 0..20 {
+    ///     cbd.write(&random_student(vec![1023, random(), random()]))?;
+    /// }
+    ///
+    /// let students = cbd.filter(|student| student.classes.contains(&1023));
+    /// assert_eq!(students.len(), 20);
+    /// # std::fs::remove_file("test6.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn filter(&mut self, mut filter: impl FnMut(&T) -> bool) -> Vec<T> {
+        let mut vec = vec![];
+        for block in 0..self.written_blocks() {
+            match self.read(block) {
+                Ok(data) => {
+                    if filter(&data) {
+                        vec.push(data);
+                    }
+                }
+                Err(Error::EmptyBlock { .. }) => continue,
+                Err(Error::ContinuationBlock { .. }) => continue,
+                // We ignore IO errors, this may be a mistake (or not, only future will know)
+                Err(err) => self.quarantine(block, &err),
+            }
+        }
+        vec
+    }
+
+    /// Same as `filter`, but pairs every object with its starting block, so they can be removed or replaced
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test28.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test28.file", None)?;
+    ///
+    /// for i in 0..10 {
+    ///     cbd.write(&i)?;
+    /// }
+    ///
+    /// for (block, _) in cbd.filter_with_blocks(|i| i % 2 == 0) {
+    ///     cbd.remove(block)?;
+    /// }
+    /// assert_eq!(cbd.filter(|_| true), vec![1, 3, 5, 7, 9]);
+    /// # std::fs::remove_file("test28.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn filter_with_blocks(&mut self, mut filter: impl FnMut(&T) -> bool) -> Vec<(u64, T)> {
+        let mut vec = vec![];
+        for block in 0..self.written_blocks() {
+            match self.read(block) {
+                Ok(data) => {
+                    if filter(&data) {
+                        vec.push((block, data));
+                    }
+                }
+                // Errors are ignored, just like `filter`
+                Err(err) => self.quarantine(block, &err),
+            }
+        }
+        vec
+    }
+
+    /// Returns up to `limit` objects selected by `filter` (with their starting blocks), scanning from
+    /// `start_block`, and the block the next page starts at, `None` if the scan reached the end
+    ///
+    /// Pages are keyed by block, so nothing has to be kept between them, unlike `filter` errors aren't ignored
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test50.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test50.file", None)?;
+    /// for i in 0..10 {
+    ///     cbd.write(&i)?;
+    /// }
+    ///
+    /// let (page, next) = cbd.page(|i| i % 2 == 0, 0, 3)?;
+    /// assert_eq!(page, vec![(0, 0), (2, 2), (4, 4)]);
+    /// let (page, next) = cbd.page(|i| i % 2 == 0, next.unwrap(), 3)?;
+    /// assert_eq!(page, vec![(6, 6), (8, 8)]);
+    /// assert_eq!(next, None);
+    /// # std::fs::remove_file("test50.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn page(
+        &mut self,
+        mut filter: impl FnMut(&T) -> bool,
+        start_block: u64,
+        limit: usize,
+    ) -> Result<Page<T>, Error> {
+        let (mut vec, mut block, blocks) = (vec![], start_block, self.written_blocks());
+        while block < blocks && vec.len() < limit {
+            match self.read(block) {
+                // Extents of fragmented objects may have other objects between them, so blocks aren't skipped
+                Ok(data) => {
+                    if filter(&data) {
+                        vec.push((block, data));
+                    }
+                    block += 1;
+                }
+                Err(Error::EmptyBlock { .. }) | Err(Error::ContinuationBlock { .. }) => block += 1,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok((vec, Some(block).filter(|block| *block < blocks)))
+    }
+
+    /// Same as `filter`, but also returns the number of blocks that were read by the whole scan
+    pub fn filter_counted(&mut self, mut filter: impl FnMut(&T) -> bool) -> (Vec<T>, u64) {
+        let (mut vec, mut read_blocks) = (vec![], 0);
+        for block in 0..self.written_blocks() {
+            match self.read_counted(block) {
+                Ok((data, blocks)) => {
+                    read_blocks += blocks;
+                    if filter(&data) {
+                        vec.push(data);
+                    }
+                }
+                // Errors are ignored, just like `filter`
+                Err(err) => self.quarantine(block, &err),
+            }
+        }
+        (vec, read_blocks)
+    }
+
+    /// Writes every object selected by `filter` to `out` with `encode`, as they are read, returns how many
+    /// were written
+    ///
+    /// Only one object is in memory at a time, unlike `filter` errors aren't ignored
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    /// use std::io::Write;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test46.file")?;
+    /// let mut cbd: Cabide<(String, u8)> = Cabide::new("test46.file", None)?;
+    /// cbd.write(&("Alice".to_owned(), 30))?;
+    /// cbd.write(&("Bob".to_owned(), 17))?;
+    ///
+    /// let mut csv = vec![];
+    /// let written = cbd.filter_to(
+    ///     |(_, age)| *age >= 18,
+    ///     &mut csv,
+    ///     |(name, age), out| writeln!(out, "{},{}", name, age),
+    /// )?;
+    /// assert_eq!(written, 1);
+    /// assert_eq!(csv, b"Alice,30\n");
+    /// # std::fs::remove_file("test46.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn filter_to<W: Write>(
+        &mut self,
+        mut filter: impl FnMut(&T) -> bool,
+        mut out: W,
+        mut encode: impl FnMut(&T, &mut W) -> io::Result<()>,
+    ) -> Result<u64, Error> {
+        let mut written = 0;
+        for block in 0..self.written_blocks() {
+            match self.read(block) {
+                Ok(data) => {
+                    if filter(&data) {
+                        encode(&data, &mut out)?;
+                        written += 1;
+                    }
+                }
+                Err(Error::EmptyBlock { .. }) => continue,
+                Err(Error::ContinuationBlock { .. }) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        out.flush()?;
+        Ok(written)
+    }
+
+    /// Writes a line for every object, with its starting block, the number of blocks it uses (not always
+    /// contiguous, fragmented objects have extents apart) and its `Debug` representation, separated by tabs, so
+    /// the file can be inspected (or attached to a bug report)
+    ///
+    /// Unlike `filter` errors aren't ignored, see [`Cabide::restore`] for the inverse
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test53.file")?;
+    /// let mut cbd: Cabide<(u8, bool)> = Cabide::new("test53.file", None)?;
+    /// cbd.write(&(1, true))?;
+    /// cbd.write(&(2, false))?;
+    /// cbd.remove(0)?;
+    ///
+    /// let mut dump = vec![];
+    /// cbd.dump(&mut dump)?;
+    /// assert_eq!(dump, b"1\t1\t(2, false)\n");
+    /// # std::fs::remove_file("test53.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn dump<W: Write>(&mut self, mut out: W) -> Result<(), Error>
+    where
+        T: fmt::Debug,
+    {
+        let (mut block, blocks) = (0, self.written_blocks());
+        while block < blocks {
+            match self.read_counted(block) {
+                // Extents of fragmented objects may have other objects between them, so blocks aren't skipped
+                Ok((data, read)) => {
+                    writeln!(out, "{}\t{}\t{:?}", block, read, data)?;
+                    block += 1;
+                }
+                Err(Error::EmptyBlock { .. }) | Err(Error::ContinuationBlock { .. }) => block += 1,
+                Err(err) => return Err(err),
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Writes every object of a dump made by [`Cabide::dump`], in the order they were dumped, `decode` parses
+    /// them from their representation (like JSON, if that's how `Debug` formats them), returns how many were
+    /// written
+    ///
+    /// Objects get new blocks, fails with `Error::CorruptedBlock` if a line isn't part of a dump or `decode`
+    /// returns `None`
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test54.file")?;
+    /// let mut cbd: Cabide<u64> = Cabide::new("test54.file", None)?;
+    /// let restored = cbd.restore(&b"0\t1\t10\n3\t1\t20\n"[..], |debug| debug.parse().ok())?;
+    /// assert_eq!(restored, 2);
+    /// assert_eq!(cbd.filter(|_| true), vec![10, 20]);
+    /// # std::fs::remove_file("test54.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn restore<R: io::BufRead>(
+        &mut self,
+        dump: R,
+        mut decode: impl FnMut(&str) -> Option<T>,
+    ) -> Result<u64, Error>
+    where
+        T: Serialize,
+    {
+        let mut restored = 0;
+        for line in dump.lines() {
+            let line = line?;
+            let mut fields = line.splitn(3, '\t');
+            let (block, blocks, debug) = (fields.next(), fields.next(), fields.next());
+            let is_dump = [block, blocks]
+                .iter()
+                .all(|field| field.is_some_and(|field| field.parse::<u64>().is_ok()));
+            let obj = debug
+                .filter(|_| is_dump)
+                .and_then(&mut decode)
+                .ok_or(Error::CorruptedBlock)?;
+            self.write(&obj)?;
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    /// Sorry, docs are still on their way for this
+    ///
+    /// Fails with [`Error::ReadOnly`] if the database can't be changed, objects that fail to be removed are
+    /// kept (and not returned)
+    #[inline]
+    pub fn remove_with(&mut self, mut filter: impl FnMut(&T) -> bool) -> Result<Vec<T>, Error> {
+        self.file.writable()?;
+        let mut vec = vec![];
+        for block in 0..self.written_blocks() {
+            match self.read(block) {
+                Ok(data) => {
+                    if filter(&data) && self.remove(block).is_ok() {
+                        vec.push(data);
+                    }
+                }
+                Err(Error::EmptyBlock { .. }) => continue,
+                Err(Error::ContinuationBlock { .. }) => continue,
+                // We ignore IO errors, this may be a mistake (or not, only future will know)
+                Err(err) => self.quarantine(block, &err),
+            }
+        }
+        Ok(vec)
+    }
+
+    /// Same as `remove_with`, but objects are removed lazily, one per iteration, so only one is in memory
+    ///
+    /// Freed blocks are cached as each object is removed, dropping the iterator stops the removal, the
+    /// iteration also stops after yielding an error
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test31.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test31.file", None)?;
+    /// for i in 0..10 {
+    ///     cbd.write(&i)?;
+    /// }
+    ///
+    /// let mut removed = 0;
+    /// for obj in cbd.remove_matching_stream(|i| i % 3 == 0) {
+    ///     removed += obj?;
+    /// }
+    /// assert_eq!(removed, 18);
+    /// assert_eq!(cbd.filter(|_| true), vec![1, 2, 4, 5, 7, 8]);
+    /// # std::fs::remove_file("test31.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn remove_matching_stream<'a>(
+        &'a mut self,
+        mut filter: impl FnMut(&T) -> bool + 'a,
+    ) -> impl Iterator<Item = Result<T, Error>> + 'a {
+        let (mut block, end) = (0, self.written_blocks());
+        std::iter::from_fn(move || {
+            while block < end {
+                let current = block;
+                block += 1;
+                match self.read(current) {
+                    Ok(data) if filter(&data) => return Some(self.remove(current)),
+                    Ok(_)
+                    | Err(Error::EmptyBlock { .. })
+                    | Err(Error::ContinuationBlock { .. }) => {}
+                    Err(err) => {
+                        block = end;
+                        return Some(Err(err));
+                    }
+                }
+            }
+            None
+        })
+    }
+
+    /// Removes every object that has expired (according to the function set by `set_expiry`),
+    /// returns how many were removed
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test19.file")?;
+    /// // Objects are (value, expiration in seconds since UNIX_EPOCH)
+    /// let mut cbd: Cabide<(u8, Option<u64>)> = Cabide::new("test19.file", None)?;
+    /// cbd.set_expiry(|(_, expiration)| {
+    ///     expiration.map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    /// });
+    ///
+    /// cbd.write(&(1, Some(0)))?;
+    /// cbd.write(&(2, None))?;
+    /// cbd.write(&(3, Some(u32::MAX as u64)))?;
+    ///
+    /// assert_eq!(cbd.reap_expired()?, 1);
+    /// assert_eq!(cbd.filter(|_| true), vec![(2, None), (3, Some(u32::MAX as u64))]);
+    /// # std::fs::remove_file("test19.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reap_expired(&mut self) -> Result<u64, Error> {
+        let now = SystemTime::now();
+        let mut reaped = 0;
+        for block in 0..self.written_blocks() {
+            let expired = match (self.read(block), &self.expiry) {
+                (Ok(data), Some(Expiry(expires_at))) => {
+                    expires_at(&data).is_some_and(|at| at <= now)
+                }
+                (Ok(_), None) => return Ok(0),
+                (Err(Error::EmptyBlock { .. }), _) => continue,
+                (Err(Error::ContinuationBlock { .. }), _) => continue,
+                (Err(err), _) => return Err(err),
+            };
+
+            if expired {
+                self.remove(block)?;
+                reaped += 1;
+            }
+        }
+        Ok(reaped)
+    }
+
+    /// Empties the blocks of objects that can't be deserialized, like the ones left half written by a crash,
+    /// returns how many blocks were reclaimed
+    ///
+    /// Their starting block and every continuation block after it are marked as empty, IO errors
+    /// interrupt it
+    pub fn reclaim_orphans(&mut self) -> Result<u64, Error> {
+        let mut reclaimed = 0;
+        for block in 0..self.written_blocks() {
+            match self.read(block) {
+                Err(Error::CorruptedBlock)
+                | Err(Error::Serde(_))
+                | Err(Error::ChecksumMismatch { .. })
+                | Err(Error::ObjectTooLarge { .. })
+                | Err(Error::ObjectTooLong { .. }) => reclaimed += self.empty_object(block)?,
+                Ok(_) | Err(Error::EmptyBlock { .. }) | Err(Error::ContinuationBlock { .. }) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(reclaimed)
+    }
+
+    /// Removes every object, yielding them (and their starting blocks) as they are removed
+    ///
+    /// Objects that fail to be read are removed but not yielded, afterwards the file can be truncated, fails
+    /// with [`Error::ReadOnly`] before removing anything if the database can't be changed
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test18.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test18.file", None)?;
+    ///
+    /// for i in 0..10 {
+    ///     cbd.write(&i)?;
+    /// }
+    ///
+    /// assert_eq!(cbd.drain()?.map(|(_, obj)| obj).sum::<u8>(), 45);
+    /// assert!(cbd.object_blocks()?.is_empty());
+    /// cbd.truncate()?;
+    /// # std::fs::remove_file("test18.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn drain(&mut self) -> Result<impl Iterator<Item = (u64, T)> + '_, Error> {
+        self.file.writable()?;
+        let (mut block, blocks) = (0, self.written_blocks());
+        Ok(std::iter::from_fn(move || {
+            while block < blocks {
+                let current = block;
+                block += 1;
+                match self.remove(current) {
+                    Ok(data) => return Some((current, data)),
+                    Err(Error::EmptyBlock { .. }) => continue,
+                    Err(Error::ContinuationBlock { .. }) => continue,
+                    // We ignore IO errors, just like `filter`
+                    _ => continue,
+                }
+            }
+            None
+        }))
+    }
+}
+
+impl<T: Serialize, const B: usize> Cabide<T, B> {
+    /// Writes data to database, splitting data in multiple blocks if needed
+    ///
+    /// Re-uses removed blocks, doesn't fragment data (unless [`CabideBuilder::fragmentation`] is enabled),
+    /// which free blocks are chosen depends on the [`Allocation`] strategy
+    ///
+    /// ```
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test7.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::new("test7.file", None)?;
+    ///
+    /// // Each u8 will always fit in one block
+    /// for i in 0..1000 {
+    ///     assert_eq!(cbd.write(&rand::random())?, i);
+    /// }
+    ///
+    /// cbd.remove(30)?;
+    /// cbd.remove(58)?;
+    ///
+    /// // Since there are empty blocks in the middle of the file we re-use one of them
+    /// // (the last one to be available that fits the data)
+    /// assert_eq!(cbd.write(&rand::random())?, 58);
+    /// # std::fs::remove_file("test7.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn write(&mut self, obj: &T) -> Result<u64, Error> {
+        self.write_hinted(obj, 0)
+    }
+
+    /// Same as `write`, but only re-uses runs of at least `min_extent` free blocks (or as many as the object
+    /// needs, if more), otherwise the object is appended
+    ///
+    /// Keeps objects that will be replaced by bigger versions out of tight holes
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test33.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::new("test33.file", None)?;
+    ///
+    /// let hole = cbd.write(&"tight".to_owned())?;
+    /// cbd.write(&"kept".to_owned())?;
+    /// cbd.remove(hole)?;
+    ///
+    /// // The hole has a single block
+    /// assert_eq!(cbd.write_hinted(&"growable".to_owned(), 2)?, 2);
+    /// assert_eq!(cbd.write_hinted(&"growable".to_owned(), 1)?, hole);
+    /// # std::fs::remove_file("test33.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn write_hinted(&mut self, obj: &T, min_extent: u64) -> Result<u64, Error> {
+        let result = self.write_with_id(obj, min_extent, None);
+        self.synced(result)
+    }
+
+    /// Writes object starting at specified block, instead of letting the database choose it, like to rebuild
+    /// a database from an external index
+    ///
+    /// Fails with `Error::BlockInUse` if any of the blocks it needs belong to another object, an object
+    /// starting at the same block is replaced (keeping its stable id, like `update`). Blocks past the last
+    /// object are written to like appends, the ones skipped before it become free
+    ///
+    /// ```rust
+    /// use cabide::{Cabide, Error};
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test61.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::new("test61.file", None)?;
+    /// cbd.write_at(5, &"five".to_owned())?;
+    /// cbd.write_at(2, &"two".to_owned())?;
+    /// assert_eq!(cbd.object_blocks()?, vec![2, 5]);
+    ///
+    /// // Needs 2 blocks, but the one after block 4 is taken
+    /// let res = cbd.write_at(4, &"four".repeat(10));
+    /// assert!(matches!(res, Err(Error::BlockInUse { block: 5 })));
+    /// cbd.write_at(5, &"five".repeat(10))?;
+    /// assert_eq!(cbd.read(5)?, "five".repeat(10));
+    /// # std::fs::remove_file("test61.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_at(&mut self, block: u64, obj: &T) -> Result<(), Error> {
+        let (mut raw, mut framed) = (
+            std::mem::take(&mut self.content_buffer),
+            std::mem::take(&mut self.block_buffer),
+        );
+        let result = self.write_at_buffered(block, obj, &mut raw, &mut framed);
+        self.content_buffer = raw;
+        self.block_buffer = framed;
+        self.synced(result)
+    }
+
+    /// Same as `write_at`, but serializes into `raw` and frames the blocks into `framed`, like `write_buffered`
+    fn write_at_buffered(
+        &mut self,
+        block: u64,
+        obj: &T,
+        raw: &mut Vec<u8>,
+        framed: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        let replaced = self.read_metadata(block)?.is_some_and(Metadata::is_start);
+        let id = match self.next_id {
+            Some(_) if replaced => Some(self.read_id(block)?),
+            _ => None,
+        };
+        let written = self.serialize_buffered(obj, id, raw)?;
+        let needed = self.needed_blocks(raw);
+        let end = block
+            .checked_add(needed)
+            .ok_or_else(|| self.out_of_bounds(block))?;
+        if let Some(len) = self
+            .len
+            .filter(|len| end.saturating_mul(Self::BLOCK_SIZE) > *len)
+        {
+            return Err(Error::OutOfSpace {
+                needed,
+                available: (len / Self::BLOCK_SIZE).saturating_sub(block),
+            });
+        }
+        self.check_size_limit(end)?;
+
+        // Only the first blocks of the replaced object may be overwritten
+        let mut in_replaced = replaced;
+        for current in block..end {
+            match self.read_metadata(current)? {
+                Some(_) if current == block && in_replaced => {}
+                Some(metadata) if in_replaced && metadata == Metadata::Continuation as u8 => {}
+                Some(metadata) if metadata != Metadata::Empty as u8 => {
+                    return Err(Error::BlockInUse { block: current })
+                }
+                _ => in_replaced = false,
+            }
+        }
+
+        if replaced {
+            let mut header = vec![0; self.header_len()];
+            let mut reader = BlockReader::new(self, block, true)?;
+            let result = reader
+                .read_exact(&mut header)
+                .and_then(|_| io::copy(&mut reader, &mut io::sink()))
+                .map_err(Error::from);
+            reader.finish(result)?;
+            self.unindex(&header);
+        }
+        self.take_free_blocks(block, end);
+        self.write_extents(raw, &[(block, needed)], framed)?;
+        // Custom allocators can't give specific blocks away, so they learn about the free ones again
+        if self.allocator.is_some() {
+            self.scan()?;
+        }
+        self.index_written(block, written);
+        Ok(())
+    }
+
+    /// Replaces the object at specified block with `obj`, returns the block it was written at
+    ///
+    /// If it fits in the blocks of the old object it's written in place, keeping its block (unused blocks are
+    /// freed), otherwise `obj` is written elsewhere, like `write`, and only then the old object is removed, so
+    /// it's kept if writing fails
+    ///
+    /// It keeps its stable id, but gets a new sequence number, if they are enabled
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test56.file")?;
+    /// let mut cbd: Cabide<String> = Cabide::new("test56.file", None)?;
+    ///
+    /// let block = cbd.write(&"first".to_owned())?;
+    /// cbd.write(&"neighbour".to_owned())?;
+    ///
+    /// // Fits in the same block, so it's kept
+    /// assert_eq!(cbd.update(block, &"second".to_owned())?, block);
+    /// assert_eq!(cbd.read(block)?, "second");
+    ///
+    /// // Too big for it, so it's moved
+    /// let moved = cbd.update(block, &"third, but a lot longer".repeat(3))?;
+    /// assert_ne!(moved, block);
+    /// assert_eq!(cbd.read(moved)?, "third, but a lot longer".repeat(3));
+    /// # std::fs::remove_file("test56.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn update(&mut self, block: u64, obj: &T) -> Result<u64, Error> {
+        let (mut raw, mut framed) = (
+            std::mem::take(&mut self.content_buffer),
+            std::mem::take(&mut self.block_buffer),
+        );
+        let result = self.update_buffered(block, obj, &mut raw, &mut framed);
+        self.content_buffer = raw;
+        self.block_buffer = framed;
+        self.synced(result)
+    }
+
+    /// Same as `update`, but serializes into `raw` and frames the blocks into `framed`, like `write_buffered`
+    fn update_buffered(
+        &mut self,
+        block: u64,
+        obj: &T,
+        raw: &mut Vec<u8>,
+        framed: &mut Vec<u8>,
+    ) -> Result<u64, Error> {
+        // Old object is only measured, it isn't decoded
+        let mut header = vec![0; self.header_len()];
+        let mut reader = BlockReader::new(self, block, false)?;
+        let result = reader
+            .read_exact(&mut header)
+            .and_then(|_| io::copy(&mut reader, &mut io::sink()))
+            .map_err(Error::from);
+        let fragmented = reader.fragmented;
+        let (_, blocks) = reader.finish_counted(result)?;
+
+        let id = match self.next_id {
+            Some(_) => {
+                let (position, mut id) = (self.id_position(), [0; ID_SIZE as usize]);
+                id.copy_from_slice(&header[position..position + ID_SIZE as usize]);
+                Some(u64::from_le_bytes(id))
+            }
+            None => None,
+        };
+        let written = self.serialize_buffered(obj, id, raw)?;
+        let needed = self.needed_blocks(raw);
+
+        let starting_block = if fragmented || needed > blocks {
+            // Old object is only freed once the new copy is written, so it isn't lost if writing fails
+            let starting_block = self.write_raw(raw, 0, framed)?;
+            let mut reader = BlockReader::new(self, block, true)?;
+            let result = io::copy(&mut reader, &mut io::sink()).map_err(Error::from);
+            reader.finish(result)?;
+            self.unindex(&header);
+            starting_block
+        } else {
+            self.unindex(&header);
+            self.write_extents(raw, &[(block, needed)], framed)?;
+            for tail in block + needed..block + blocks {
+                self.seek_block(tail)?;
+                self.file.write_all(&[Metadata::Empty as u8])?;
+            }
+            if blocks > needed {
+                self.free_run(block + needed, blocks - needed);
+            }
+            block
+        };
+        self.index_written(starting_block, written);
+        Ok(starting_block)
+    }
+
+    /// Same as `write_hinted`, but the object keeps specified stable id instead of getting a new one
+    fn write_with_id(&mut self, obj: &T, min_extent: u64, id: Option<u64>) -> Result<u64, Error> {
+        // Buffers are moved out so `self` can be borrowed while they are filled, they're moved back even on errors
+        let (mut raw, mut framed) = (
+            std::mem::take(&mut self.content_buffer),
+            std::mem::take(&mut self.block_buffer),
+        );
+        let result = self.write_buffered(obj, min_extent, id, &mut raw, &mut framed);
+        self.content_buffer = raw;
+        self.block_buffer = framed;
+        result
+    }
+
+    /// Writes object serializing it into `raw` and framing its blocks into `framed`, both are cleared first
+    ///
+    /// Only re-uses runs of at least `min_extent` free blocks, it gets a new stable id unless `id` is set
+    fn write_buffered(
+        &mut self,
+        obj: &T,
+        min_extent: u64,
+        id: Option<u64>,
+        raw: &mut Vec<u8>,
+        framed: &mut Vec<u8>,
+    ) -> Result<u64, Error> {
+        let written = self.serialize_buffered(obj, id, raw)?;
+        let starting_block = self.write_raw(raw, min_extent, framed)?;
+        self.index_written(starting_block, written);
+        Ok(starting_block)
+    }
+
+    /// Serializes object into `raw` (cleared first) prefixed by its content header, and encodes it
+    ///
+    /// Returns the sequence number and stable id it got, if they are enabled, it gets a new stable id unless
+    /// `id` is set
+    fn serialize_buffered(
+        &mut self,
+        obj: &T,
+        id: Option<u64>,
+        raw: &mut Vec<u8>,
+    ) -> Result<Written, Error> {
+        // Fails before a sequence number or stable id is taken
+        self.file.writable()?;
+
+        // Header comes before the content, so it can be read without decoding the object
+        let header_len = self.header_len();
+        raw.clear();
+        raw.resize(header_len, 0);
+        bincode_options().serialize_into(&mut *raw, obj)?;
+
+        if self.tagged {
+            // bincode starts enums with their variant index as a 4 bytes integer
+            let index = raw
+                .get(header_len..header_len + 4)
+                .ok_or(Error::CorruptedBlock)?;
+            let index = u32::from_le_bytes([index[0], index[1], index[2], index[3]]);
+            raw[header_len - 1] = u8::try_from(index).map_err(|_| Error::CorruptedBlock)?;
+        }
+        let written_sequence = self.next_sequence;
+        if let Some(sequence) = &mut self.next_sequence {
+            raw[..SEQUENCE_SIZE as usize].copy_from_slice(&sequence.to_le_bytes());
+            *sequence += 1;
+        }
+        let written_id = match (&mut self.next_id, id) {
+            (Some(_), Some(id)) => Some(id),
+            (Some(next), None) => {
+                *next += 1;
+                Some(*next - 1)
+            }
+            (None, _) => None,
+        };
+        if let Some(id) = written_id {
+            let position = self.id_position();
+            raw[position..position + ID_SIZE as usize].copy_from_slice(&id.to_le_bytes());
+        }
+        let size = (raw.len() - header_len) as u64;
+        let encode = self
+            .codec_threshold
+            .map_or(true, |min_size| size >= min_size);
+        if self.codec_threshold.is_some() {
+            raw[self.codec_flag_position()] = encode as u8;
+        }
+        if let Some(codec) = self.codec.as_ref().filter(|_| encode) {
+            let encoded = codec.encode(raw[header_len..].to_vec());
+            raw.truncate(header_len);
+            raw.extend(encoded);
+        }
+        if self.checksums {
+            let (header, content) = raw.split_at(header_len);
+            let checksum = self.checksum(header, content);
+            let position = self.checksum_position();
+            raw[position..position + CHECKSUM_SIZE as usize]
+                .copy_from_slice(&checksum.to_le_bytes());
+        }
+        Ok(Written {
+            sequence: written_sequence,
+            id: written_id,
+        })
+    }
+
+    /// Indexes the sequence number and stable id of the object written at specified block
+    #[inline]
+    fn index_written(&mut self, block: u64, written: Written) {
+        if let (Some(index), Some(sequence)) = (&mut self.sequence_index, written.sequence) {
+            index.insert(sequence, block);
+        }
+        if let Some(id) = written.id {
+            self.id_index.insert(id, block);
+        }
+    }
+}
+
+impl<T, const B: usize> Cabide<T, B>
+where
+    for<'de> T: Serialize + Deserialize<'de>,
+{
+    /// Reads the object at specified block, modifies it with `f` and writes it back, returning its new block
+    ///
+    /// It's written back with `update`, so it keeps its block if it still fits, and isn't lost if it doesn't
+    /// and writing it elsewhere fails
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test71.file")?;
+    /// let mut cbd: Cabide<(String, u8)> = Cabide::new("test71.file", None)?;
+    ///
+    /// let block = cbd.write(&("counter".to_owned(), 1))?;
+    /// let block = cbd.patch(block, |(_, count)| *count += 1)?;
+    /// assert_eq!(cbd.read(block)?, ("counter".to_owned(), 2));
+    /// # std::fs::remove_file("test71.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn patch(&mut self, block: u64, f: impl FnOnce(&mut T)) -> Result<u64, Error> {
+        let mut obj = self.read(block)?;
+        f(&mut obj);
+        self.update(block, &obj)
+    }
+
+    /// Replaces the object at specified block with `obj` only if its version (see [`Cabide::sequence`]) is
+    /// still `expected_version`, returning its block, it's written like `update`, so it gets a new version but
+    /// keeps its stable id (and its block, if it still fits)
+    ///
+    /// Fails with `Error::VersionConflict` if it was rewritten since `expected_version` was read, sequence
+    /// numbers must be enabled
+    ///
+    /// ```rust
+    /// use cabide::{Cabide, Error};
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test44.file")?;
+    /// let mut cbd: Cabide<u8> = Cabide::builder().sequence_numbers(true).open("test44.file")?;
+    ///
+    /// let block = cbd.write(&1)?;
+    /// let version = cbd.sequence(block)?;
+    /// let block = cbd.update_if(block, version, &2)?;
+    ///
+    /// // Version read before the update is now stale
+    /// let res = cbd.update_if(block, version, &3);
+    /// assert!(matches!(res, Err(Error::VersionConflict { .. })));
+    /// assert_eq!(cbd.read(block)?, 2);
+    /// # std::fs::remove_file("test44.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn update_if(&mut self, block: u64, expected_version: u64, obj: &T) -> Result<u64, Error> {
+        let stored = self.sequence(block)?;
+        if stored != expected_version {
+            return Err(Error::VersionConflict {
+                expected: expected_version,
+                stored,
+            });
+        }
+        self.update(block, obj)
+    }
+
+    /// Splits the objects into `n` databases, each object is written to the one at `out(bucket(&obj) % n)`
+    ///
+    /// Shards are created if non existent (objects are appended to them otherwise) and use the same block size,
+    /// objects are kept in the source, see `into_partition` to move them
+    ///
+    /// ```rust
+    /// use cabide::Cabide;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test30.file")?;
+    /// # for i in 0..2 { let _ = std::fs::remove_file(format!("test30.{}.file", i)); }
+    /// let mut cbd: Cabide<u8> = Cabide::new("test30.file", None)?;
+    /// for i in 0..10 {
+    ///     cbd.write(&i)?;
+    /// }
+    ///
+    /// let mut shards = cbd.partition(2, |i| *i as usize, |shard| format!("test30.{}.file", shard).into())?;
+    /// assert_eq!(shards[0].filter(|_| true), vec![0, 2, 4, 6, 8]);
+    /// assert_eq!(shards[1].filter(|_| true), vec![1, 3, 5, 7, 9]);
+    /// # std::fs::remove_file("test30.file")?;
+    /// # for i in 0..2 { std::fs::remove_file(format!("test30.{}.file", i))?; }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn partition(
+        &mut self,
+        n: usize,
+        bucket: impl Fn(&T) -> usize,
+        out: impl Fn(usize) -> PathBuf,
+    ) -> Result<Vec<Self>, Error> {
+        if n == 0 {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidInput, "no shards to partition into").into(),
+            );
+        }
+
+        let mut shards = Vec::with_capacity(n);
+        for shard in 0..n {
+            shards.push(Cabide::builder().open(out(shard))?);
+        }
+
+        for block in self.object_blocks()? {
+            let obj = self.read(block)?;
+            shards[bucket(&obj) % n].write(&obj)?;
+        }
+        Ok(shards)
+    }
+
+    /// Same as `partition`, but the objects are moved, the source is truncated once every one was written
+    pub fn into_partition(
+        mut self,
+        n: usize,
+        bucket: impl Fn(&T) -> usize,
+        out: impl Fn(usize) -> PathBuf,
+    ) -> Result<Vec<Self>, Error> {
+        let shards = self.partition(n, bucket, out)?;
+        self.truncate()?;
+        Ok(shards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
+    use crate::protocol::{BLOCK_SIZE, CONTENT_SIZE};
+    use bincode::serialize;
     use rand::{distributions::*, random, thread_rng};
     use serde::{Deserialize, Serialize};
 
-    #[derive(Serialize, Deserialize, PartialEq, Debug)]
-    struct InnerData {
-        wow: Option<f32>,
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct InnerData {
+        wow: Option<f32>,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Data {
+        this: u8,
+        that: bool,
+        there: String,
+        those: u64,
+        inner: InnerData,
+    }
+
+    fn random_data() -> Data {
+        // this is slow af since it doesn't cache thread_rng
+        Data {
+            this: random(),
+            that: random(),
+            there: (0..40)
+                .map(|_| Alphanumeric.sample(&mut thread_rng()))
+                .collect(),
+            those: random(),
+            inner: InnerData {
+                wow: Some(random()).filter(|_| random()),
+            },
+        }
+    }
+
+    #[test]
+    fn persistance() {
+        std::fs::File::create("cabide.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide.test", None).unwrap();
+        cbd.file.set_len(0).unwrap();
+
+        let mut blocks = vec![];
+        for _ in 0..50 {
+            let data = random_data();
+            let block = cbd.write(&data).unwrap();
+            blocks.push((block, data));
+        }
+
+        // this drops the last cabide, therefore closes the file
+        cbd = Cabide::new("cabide.test", Some(10)).unwrap();
+
+        for (block, data) in &blocks {
+            let read: Data = cbd.read(*block).unwrap();
+            assert_eq!(&read, data);
+        }
+
+        assert_eq!(cbd.remove(blocks[8].0).unwrap(), blocks[8].1);
+
+        assert_eq!(cbd.remove(blocks[13].0).unwrap(), blocks[13].1);
+
+        // this drops the last cabide, therefore closes the file
+        cbd = Cabide::new("cabide.test", Some(10)).unwrap();
+
+        for i in &[8, 13] {
+            let data = random_data();
+            let block = cbd.write(&data).unwrap();
+            blocks[*i as usize] = (block, data);
+        }
+
+        cbd = Cabide::new("cabide.test", Some(10)).unwrap();
+
+        for (block, data) in blocks {
+            assert_eq!(cbd.read(block).unwrap(), data);
+        }
+        std::fs::remove_file("cabide.test").unwrap();
+    }
+
+    #[test]
+    fn last_multi_block() {
+        std::fs::File::create("cabide_last.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_last.test", None).unwrap();
+
+        for _ in 0..10 {
+            cbd.write(&random_data()).unwrap();
+        }
+
+        let data = random_data();
+        let block = cbd.write(&data).unwrap();
+        // Makes sure the last object spans multiple blocks, so we would land on a continuation
+        assert!(cbd.blocks().unwrap() - block > 1);
+
+        assert_eq!(cbd.last().unwrap(), Some((block, data)));
+        std::fs::remove_file("cabide_last.test").unwrap();
+    }
+
+    #[test]
+    fn no_free_block_reuse() {
+        std::fs::File::create("cabide_no_reuse.test").unwrap();
+        let mut cbd: Cabide<Data> =
+            Cabide::with_free_block_reuse("cabide_no_reuse.test", None, false).unwrap();
+
+        let mut blocks = vec![];
+        for _ in 0..10 {
+            blocks.push(cbd.write(&random_data()).unwrap());
+        }
+
+        let end = cbd.blocks().unwrap();
+        cbd.remove(blocks[3]).unwrap();
+
+        let data = random_data();
+        let block = cbd.write(&data).unwrap();
+        assert_ne!(block, blocks[3]);
+        assert_eq!(block, end);
+        assert_eq!(cbd.read(block).unwrap(), data);
+        std::fs::remove_file("cabide_no_reuse.test").unwrap();
+    }
+
+    #[test]
+    fn error_reports_block() {
+        std::fs::File::create("cabide_error_block.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_error_block.test", Some(20)).unwrap();
+
+        let block = cbd.write(&random_data()).unwrap();
+        match cbd.remove(block + 1) {
+            Err(Error::ContinuationBlock { block: b }) => assert_eq!(b, block + 1),
+            res => panic!("expected continuation block error, got {:?}", res),
+        }
+
+        match cbd.read(15) {
+            Err(err @ Error::EmptyBlock { block: 15 }) => {
+                assert_eq!(err.to_string(), "Block 15 is empty")
+            }
+            res => panic!("expected empty block error, got {:?}", res),
+        }
+        std::fs::remove_file("cabide_error_block.test").unwrap();
+    }
+
+    #[test]
+    fn offset_tables() {
+        std::fs::File::create("cabide_offset.test").unwrap();
+        let region = 20 * BLOCK_SIZE;
+        let mut first: Cabide<Data> =
+            Cabide::new_at("cabide_offset.test", 0, region, None).unwrap();
+        let mut second: Cabide<Data> =
+            Cabide::new_at("cabide_offset.test", region, region, None).unwrap();
+
+        let (mut firsts, mut seconds) = (vec![], vec![]);
+        loop {
+            let data = random_data();
+            match first.write(&data) {
+                Ok(block) => firsts.push((block, data)),
+                Err(Error::OutOfSpace { needed, available }) => {
+                    assert!(needed > available);
+                    break;
+                }
+                Err(err) => panic!("unexpected error {:?}", err),
+            }
+
+            let data = random_data();
+            seconds.push((second.write(&data).unwrap(), data));
+        }
+
+        assert!(first.blocks().unwrap() <= 20);
+        // this drops the last cabides, therefore closes the file
+        first = Cabide::new_at("cabide_offset.test", 0, region, None).unwrap();
+        second = Cabide::new_at("cabide_offset.test", region, region, None).unwrap();
+
+        for (block, data) in firsts {
+            assert_eq!(first.read(block).unwrap(), data);
+        }
+        for (block, data) in seconds {
+            assert_eq!(second.read(block).unwrap(), data);
+        }
+        drop((first, second));
+
+        // Ranges that aren't a multiple of the block size never touch the partial block at their end
+        std::fs::File::create("cabide_offset.test").unwrap();
+        let mut first: Cabide<u8> = Cabide::new_at("cabide_offset.test", 0, 100, None).unwrap();
+        let mut second: Cabide<u8> = Cabide::new_at("cabide_offset.test", 100, 100, None).unwrap();
+        assert_eq!(second.write(&2).unwrap(), 0);
+        for i in 0..3 {
+            assert_eq!(first.write(&i).unwrap(), i as u64);
+        }
+        assert!(matches!(first.write(&3), Err(Error::OutOfSpace { .. })));
+        assert_eq!(first.blocks().unwrap(), 3);
+        first.truncate().unwrap();
+        assert_eq!(second.read(0).unwrap(), 2);
+        first.set_prefill(3).unwrap();
+        assert!(matches!(
+            first.set_prefill(4),
+            Err(Error::OutOfSpace { .. })
+        ));
+        assert_eq!(second.read(0).unwrap(), 2);
+        std::fs::remove_file("cabide_offset.test").unwrap();
+    }
+
+    #[test]
+    fn object_too_large() {
+        std::fs::File::create("cabide_too_large.test").unwrap();
+        let mut cbd: Cabide<String> = Cabide::new("cabide_too_large.test", None).unwrap();
+        cbd.set_max_object_size(1000);
+
+        // Hand-crafted object that claims to have 2^40 bytes, but only has a few
+        cbd.file
+            .write_all(&[
+                Metadata::Start as u8,
+                0,
+                0,
+                0,
+                0,
+                0,
+                1,
+                0,
+                0,
+                b'a',
+                b'b',
+                END_BYTE,
+            ])
+            .unwrap();
+        cbd.next_block = 1;
+        match cbd.read(0) {
+            // Objects without a stored length only have the decoder's length field, the limit is hit before
+            // the blocks are read
+            Err(Error::ObjectTooLarge {
+                claimed: None,
+                max: 1000,
+            }) => {}
+            res => panic!("expected object too large error, got {:?}", res),
+        }
+
+        // Objects that are really bigger than the limit can't be read either, their stored length is reported
+        let block = cbd.write(&"a".repeat(2000)).unwrap();
+        match cbd.read(block) {
+            Err(Error::ObjectTooLarge {
+                claimed: Some(2008),
+                max: 1000,
+            }) => {}
+            res => panic!("expected object too large error, got {:?}", res),
+        }
+
+        cbd.set_max_object_size(DEFAULT_MAX_OBJECT_SIZE);
+        assert_eq!(cbd.read(block).unwrap(), "a".repeat(2000));
+        std::fs::remove_file("cabide_too_large.test").unwrap();
+    }
+
+    #[test]
+    fn iter_ordered() {
+        std::fs::File::create("cabide_iter_ordered.test").unwrap();
+        let builder = || Cabide::<Data>::builder().sequence_numbers(true);
+        let mut cbd = builder().open("cabide_iter_ordered.test").unwrap();
+
+        let mut objects = vec![];
+        for _ in 0..10 {
+            let data = random_data();
+            objects.push((cbd.write(&data).unwrap(), data));
+        }
+        for (block, _) in objects.drain(..4) {
+            cbd.remove(block).unwrap();
+        }
+
+        // New objects re-use the free blocks at the start of the file
+        for _ in 0..3 {
+            let data = random_data();
+            objects.push((cbd.write(&data).unwrap(), data));
+        }
+        assert!(objects.last().unwrap().0 < objects[0].0);
+
+        // this drops the last cabide, therefore closes the file
+        cbd = builder().open("cabide_iter_ordered.test").unwrap();
+        let data = random_data();
+        objects.push((cbd.write(&data).unwrap(), data));
+
+        let ordered: Vec<(u64, Data)> = cbd.iter_ordered().unwrap().map(Result::unwrap).collect();
+        assert_eq!(ordered, objects);
+        std::fs::remove_file("cabide_iter_ordered.test").unwrap();
+    }
+
+    #[test]
+    fn update_if() {
+        let filename = "cabide_update_if.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Data> = Cabide::builder()
+            .sequence_numbers(true)
+            .open(filename)
+            .unwrap();
+        let (first, second) = (random_data(), random_data());
+        let block = cbd.write(&first).unwrap();
+        cbd.write(&random_data()).unwrap();
+        let stale = cbd.sequence(block).unwrap();
+
+        // Another writer updates it first
+        let block = cbd.update_if(block, stale, &second).unwrap();
+        let current = cbd.sequence(block).unwrap();
+        assert!(current > stale);
+
+        let res = cbd.update_if(block, stale, &first);
+        assert!(matches!(
+            res,
+            Err(Error::VersionConflict { expected, stored }) if expected == stale && stored == current
+        ));
+        assert_eq!(cbd.read(block).unwrap(), second);
+
+        let block = cbd.update_if(block, current, &first).unwrap();
+        assert_eq!(cbd.read(block).unwrap(), first);
+        assert!(cbd.sequence(block).unwrap() > current);
+        drop(cbd);
+
+        // Versions can't be checked without sequence numbers
+        let mut cbd: Cabide<Data> = Cabide::new(filename, None).unwrap();
+        assert!(matches!(cbd.sequence(0), Err(Error::NotExistant)));
+        drop(cbd);
+
+        // Updated in place keeping its stable id, and kept as it was if it can't be moved
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<String> = Cabide::builder()
+            .sequence_numbers(true)
+            .stable_ids(true)
+            .range(0, 300)
+            .open(filename)
+            .unwrap();
+        let block = cbd.write(&"hello".to_owned()).unwrap();
+        let id = cbd.id(block).unwrap();
+        let version = cbd.sequence(block).unwrap();
+        assert_eq!(
+            cbd.update_if(block, version, &"world".to_owned()).unwrap(),
+            block
+        );
+        assert_eq!(cbd.id(block).unwrap(), id);
+        let version = cbd.sequence(block).unwrap();
+        assert!(matches!(
+            cbd.update_if(block, version, &"x".repeat(300)),
+            Err(Error::OutOfSpace { .. })
+        ));
+        assert_eq!(cbd.read_by_id(id).unwrap(), "world");
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn update() {
+        let filename = "cabide_update.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::builder()
+            .sequence_numbers(true)
+            .sequence_index(true)
+            .stable_ids(true)
+            .open(filename)
+            .unwrap();
+        // Objects have 16 bytes of header, 8 of the vector's length and 8 of the content's length
+        let content_size = cbd.content_size() as usize;
+        let block = cbd.write(&vec![1; 2 * content_size]).unwrap();
+        let neighbour = cbd.write(&vec![2; 2]).unwrap();
+        let (id, sequence) = (cbd.id(block).unwrap(), cbd.sequence(block).unwrap());
+        assert_eq!(neighbour, block + 4);
+
+        // Same number of blocks, in place
+        assert_eq!(
+            cbd.update(block, &vec![3; 2 * content_size]).unwrap(),
+            block
+        );
+        assert_eq!(cbd.read(block).unwrap(), vec![3; 2 * content_size]);
+        assert_eq!(cbd.id(block).unwrap(), id);
+        let current = cbd.sequence(block).unwrap();
+        assert!(current > sequence);
+        assert!(cbd.read_seq(sequence).is_err());
+        assert_eq!(cbd.read_seq(current).unwrap(), vec![3; 2 * content_size]);
+
+        // Fewer blocks, in place, the rest is freed
+        assert_eq!(cbd.update(block, &vec![4; 2]).unwrap(), block);
+        assert_eq!(cbd.read(block).unwrap(), vec![4; 2]);
+        assert!(matches!(
+            cbd.read(block + 2),
+            Err(Error::EmptyBlock { block: empty }) if empty == block + 2
+        ));
+        assert_eq!(cbd.empty_blocks.get(&2), Some(&vec![block + 2]));
+        assert_eq!(cbd.read(neighbour).unwrap(), vec![2; 2]);
+
+        // More blocks than it has, so it's moved and its blocks are freed
+        let moved = cbd.update(block, &vec![5; 3 * content_size]).unwrap();
+        assert_eq!(moved, neighbour + 2);
+        assert_eq!(cbd.read(moved).unwrap(), vec![5; 3 * content_size]);
+        assert_eq!(cbd.read_by_id(id).unwrap(), vec![5; 3 * content_size]);
+        assert_eq!(cbd.empty_blocks.get(&4), Some(&vec![block]));
+        assert_eq!(cbd.read(neighbour).unwrap(), vec![2; 2]);
+
+        // Only starting blocks can be updated
+        assert!(matches!(
+            cbd.update(block, &vec![]),
+            Err(Error::EmptyBlock { .. })
+        ));
+        assert!(matches!(
+            cbd.update(moved + 1, &vec![]),
+            Err(Error::ContinuationBlock { .. })
+        ));
+        assert_eq!(cbd.read(moved).unwrap(), vec![5; 3 * content_size]);
+
+        // Persisted as written
+        drop(cbd);
+        let mut cbd: Cabide<Vec<u8>> = Cabide::builder()
+            .sequence_numbers(true)
+            .stable_ids(true)
+            .open(filename)
+            .unwrap();
+        assert_eq!(cbd.empty_blocks.get(&4), Some(&vec![block]));
+        assert_eq!(cbd.read(neighbour).unwrap(), vec![2; 2]);
+        drop(cbd);
+
+        // Objects that can't be moved are kept as they were
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<String> = Cabide::new_at(filename, 0, 90, None).unwrap();
+        let block = cbd.write(&"hello".to_owned()).unwrap();
+        assert!(matches!(
+            cbd.update(block, &"x".repeat(200)),
+            Err(Error::OutOfSpace { .. })
+        ));
+        assert_eq!(cbd.read(block).unwrap(), "hello");
+        assert_eq!(cbd.write(&"next".to_owned()).unwrap(), block + 1);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn stable_ids() {
+        let filename = "cabide_stable_ids.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Data> = Cabide::builder()
+            .sequence_numbers(true)
+            .stable_ids(true)
+            .open(filename)
+            .unwrap();
+        let objects: Vec<Data> = (0..20).map(|_| random_data()).collect();
+        let blocks: Vec<u64> = objects.iter().map(|obj| cbd.write(obj).unwrap()).collect();
+        let ids: Vec<u64> = blocks.iter().map(|block| cbd.id(*block).unwrap()).collect();
+        assert_eq!(ids, (0..20).collect::<Vec<_>>());
+
+        // Replaced objects keep their id
+        let moved = cbd
+            .patch(blocks[10], |data| data.this = data.this.wrapping_add(1))
+            .unwrap();
+        assert_eq!(cbd.id(moved).unwrap(), 10);
+        let patched = cbd.read(moved).unwrap();
+        assert_eq!(cbd.remove_by_id(3).unwrap(), objects[3]);
+        assert!(matches!(cbd.read_by_id(3), Err(Error::NotExistant)));
+        for block in &blocks[..3] {
+            cbd.remove(*block).unwrap();
+        }
+
+        // Compaction moves every object, but ids still find them
+        cbd.compact_resumable(7).unwrap();
+        assert!(cbd.id_index[&19] < blocks[19]);
+        assert_eq!(cbd.read_by_id(19).unwrap(), objects[19]);
+        assert_eq!(cbd.read_by_id(10).unwrap(), patched);
+        let block = cbd.write(&random_data()).unwrap();
+        assert_eq!(cbd.id(block).unwrap(), 20);
+        assert_eq!(cbd.read_seq(19).unwrap(), objects[19]);
+        drop(cbd);
+
+        // Index is built when opening
+        let mut cbd: Cabide<Data> = Cabide::builder()
+            .sequence_numbers(true)
+            .stable_ids(true)
+            .open(filename)
+            .unwrap();
+        for id in (4..20).filter(|id| *id != 10) {
+            assert_eq!(cbd.read_by_id(id).unwrap(), objects[id as usize]);
+        }
+        assert!(matches!(cbd.read_by_id(0), Err(Error::NotExistant)));
+        let block = cbd.write(&random_data()).unwrap();
+        assert_eq!(cbd.id(block).unwrap(), 21);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn changes_since() {
+        for indexed in [false, true] {
+            let filename = "cabide_changes_since.test";
+            std::fs::File::create(filename).unwrap();
+            let mut cbd: Cabide<Data> = Cabide::builder()
+                .sequence_numbers(true)
+                .sequence_index(indexed)
+                .open(filename)
+                .unwrap();
+            let mut blocks = vec![];
+            for _ in 0..10 {
+                blocks.push(cbd.write(&random_data()).unwrap());
+            }
+            let checkpoint = cbd.sequence(blocks[9]).unwrap();
+
+            // Newer objects re-use freed blocks, so block order isn't write order
+            cbd.remove(blocks[2]).unwrap();
+            cbd.remove(blocks[7]).unwrap();
+            let newer: Vec<Data> = (0..3).map(|_| random_data()).collect();
+            let written: Vec<u64> = newer.iter().map(|obj| cbd.write(obj).unwrap()).collect();
+            assert!(written.contains(&blocks[2]));
+            let changes: Vec<(u64, Data)> = cbd.changes_since(checkpoint).collect();
+            let (changed, objects): (Vec<u64>, Vec<Data>) = changes.into_iter().unzip();
+            assert_eq!((changed, objects), (written, newer));
+            assert_eq!(cbd.changes_since(checkpoint + 3).count(), 0);
+            std::fs::remove_file(filename).unwrap();
+        }
+
+        // Without sequence numbers nothing is known to have changed
+        std::fs::File::create("cabide_changes_since.test").unwrap();
+        let mut cbd: Cabide<u8> = Cabide::new("cabide_changes_since.test", None).unwrap();
+        cbd.write(&1).unwrap();
+        assert_eq!(cbd.changes_since(0).count(), 0);
+        std::fs::remove_file("cabide_changes_since.test").unwrap();
+    }
+
+    #[test]
+    fn read_seq() {
+        for indexed in &[false, true] {
+            std::fs::File::create("cabide_read_seq.test").unwrap();
+            let builder = || {
+                Cabide::<Data>::builder()
+                    .sequence_numbers(true)
+                    .sequence_index(*indexed)
+            };
+            let mut cbd = builder().open("cabide_read_seq.test").unwrap();
+
+            let mut objects: Vec<(u64, Data)> = vec![];
+            for _ in 0..10 {
+                let data = random_data();
+                cbd.write(&data).unwrap();
+                objects.push((objects.len() as u64, data));
+            }
+
+            // Re-use shuffles where the newer objects are placed
+            let first = cbd.remove(0).unwrap();
+            let block = cbd.object_blocks().unwrap()[3];
+            let removed = cbd.remove(block).unwrap();
+            objects.retain(|(_, data)| data != &first && data != &removed);
+            for sequence in 10..13 {
+                let data = random_data();
+                cbd.write(&data).unwrap();
+                objects.push((sequence, data));
+            }
+            assert!(matches!(cbd.read_seq(0), Err(Error::NotExistant)));
+            for (sequence, data) in &objects {
+                assert_eq!(&cbd.read_seq(*sequence).unwrap(), data);
+            }
+
+            // this drops the last cabide, therefore closes the file
+            cbd = builder().open("cabide_read_seq.test").unwrap();
+            for (sequence, data) in &objects {
+                assert_eq!(&cbd.read_seq(*sequence).unwrap(), data);
+            }
+            assert!(matches!(cbd.read_seq(13), Err(Error::NotExistant)));
+        }
+        std::fs::remove_file("cabide_read_seq.test").unwrap();
+    }
+
+    #[test]
+    fn read_as() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Flags {
+            this: u8,
+            that: bool,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Named {
+            this: u8,
+            that: bool,
+            there: String,
+        }
+
+        std::fs::File::create("cabide_read_as.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_read_as.test", None).unwrap();
+        let data = random_data();
+        let block = cbd.write(&data).unwrap();
+
+        let flags: Flags = cbd.read_as(block).unwrap();
+        assert_eq!((flags.this, flags.that), (data.this, data.that));
+        let named: Named = cbd.read_as(block).unwrap();
+        assert_eq!(named.there, data.there);
+
+        // Projections don't change the object
+        assert_eq!(cbd.read(block).unwrap(), data);
+        assert!(matches!(
+            cbd.read_as::<Flags>(block + 1),
+            Err(Error::ContinuationBlock { .. }) | Err(Error::BlockOutOfBounds { .. })
+        ));
+        std::fs::remove_file("cabide_read_as.test").unwrap();
+    }
+
+    #[test]
+    fn quarantine_on_corruption() {
+        for quarantine in &[false, true] {
+            std::fs::File::create("cabide_quarantine.test").unwrap();
+            let mut cbd: Cabide<Data> = Cabide::builder()
+                .quarantine_on_corruption(*quarantine)
+                .open("cabide_quarantine.test")
+                .unwrap();
+            let mut objects = vec![];
+            for _ in 0..5 {
+                let data = random_data();
+                objects.push((cbd.write(&data).unwrap(), data));
+            }
+
+            // `that` is a bool, but 7 isn't a valid one
+            let (corrupted, _) = objects.remove(2);
+            let start = cbd.block_start(corrupted).unwrap();
+            cbd.file
+                .seek(SeekFrom::Start(start + 2 + LENGTH_SIZE))
+                .unwrap();
+            cbd.file.write_all(&[7]).unwrap();
+            assert!(matches!(cbd.read(corrupted), Err(Error::Serde(_))));
+
+            let all = cbd.filter_with_blocks(|_| true);
+            assert_eq!(all, objects);
+            if !quarantine {
+                assert!(cbd.quarantined_blocks().is_empty());
+                assert!(matches!(cbd.read(corrupted), Err(Error::Serde(_))));
+                continue;
+            }
+
+            // Its blocks are empty now, so the next scan doesn't find it again
+            assert_eq!(cbd.quarantined_blocks(), &[corrupted]);
+            assert!(matches!(cbd.read(corrupted), Err(Error::EmptyBlock { .. })));
+            assert_eq!(cbd.filter_with_blocks(|_| true), objects);
+            assert_eq!(cbd.quarantined_blocks(), &[corrupted]);
+            assert_eq!(cbd.write(&objects[0].1).unwrap(), corrupted);
+        }
+        std::fs::remove_file("cabide_quarantine.test").unwrap();
+    }
+
+    #[test]
+    fn exact_multiple_of_content_size() {
+        std::fs::File::create("cabide_exact_multiple.test").unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new("cabide_exact_multiple.test", None).unwrap();
+
+        // Length prefix and the elements fill exactly 2 blocks
+        let exact = vec![7; 2 * CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+        assert_eq!(cbd.write(&exact).unwrap(), 0);
+        assert_eq!(cbd.next_block, 2);
+        assert_eq!(cbd.blocks().unwrap(), 2);
+        assert_eq!(cbd.write(&vec![1]).unwrap(), 2);
+        assert_eq!(cbd.read(0).unwrap(), exact);
+        assert_eq!(cbd.read(2).unwrap(), vec![1]);
+
+        // Objects with empty content still use a block
+        let mut unit: Cabide<()> = Cabide::new("cabide_exact_multiple.test", None).unwrap();
+        assert_eq!(unit.write(&()).unwrap(), 3);
+        assert_eq!(unit.blocks().unwrap(), 4);
+        unit.read(3).unwrap();
+        std::fs::remove_file("cabide_exact_multiple.test").unwrap();
+    }
+
+    #[test]
+    fn write_buffers() {
+        std::fs::File::create("cabide_write_buffers.test").unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new("cabide_write_buffers.test", None).unwrap();
+
+        // Smaller objects after bigger ones can't keep any stale byte from the buffers
+        let objs: Vec<Vec<u8>> = (0..10).rev().map(|i| vec![i as u8; i * 17]).collect();
+        let mut expected = vec![];
+        for obj in &objs {
+            let serialized = serialize(obj).unwrap();
+            let mut raw = (serialized.len() as u64).to_le_bytes().to_vec();
+            raw.extend(serialized);
+            let needed = raw.len().div_ceil(CONTENT_SIZE as usize);
+            let start = expected.len();
+            let mut chunks = raw.chunks(CONTENT_SIZE as usize);
+            for i in 0..needed {
+                let metadata = if i == 0 {
+                    Metadata::SizedStart
+                } else {
+                    Metadata::Continuation
+                };
+                expected.push(metadata as u8);
+                expected.extend(chunks.next().unwrap_or(&[]));
+                expected.push(END_BYTE);
+            }
+            expected.resize(start + needed * BLOCK_SIZE as usize, PAD_BYTE);
+
+            assert_eq!(cbd.write(obj).unwrap() * BLOCK_SIZE, start as u64);
+        }
+        assert!(cbd.content_buffer.capacity() >= serialize(&objs[0]).unwrap().len());
+
+        assert_eq!(
+            std::fs::read("cabide_write_buffers.test").unwrap(),
+            expected
+        );
+        for (block, obj) in cbd.object_blocks().unwrap().into_iter().zip(&objs) {
+            assert_eq!(&cbd.read(block).unwrap(), obj);
+        }
+        std::fs::remove_file("cabide_write_buffers.test").unwrap();
+    }
+
+    #[test]
+    fn find_block() {
+        std::fs::File::create("cabide_find_block.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_find_block.test", None).unwrap();
+
+        for i in 0..30 {
+            cbd.write(&Data {
+                this: i,
+                that: i % 7 == 6,
+                there: "a".repeat(i as usize),
+                those: i as u64,
+                inner: InnerData { wow: None },
+            })
+            .unwrap();
+        }
+        let block = cbd.find_block(|data| data.that).unwrap().unwrap();
+        assert_eq!(cbd.remove(block).unwrap().this, 6);
+
+        let block = cbd.find_block(|data| data.that).unwrap().unwrap();
+        let data = cbd.read(block).unwrap();
+        assert!(data.that);
+        assert_eq!(data.this, 13);
+        assert_eq!(cbd.find_block(|data| data.this > 30).unwrap(), None);
+        std::fs::remove_file("cabide_find_block.test").unwrap();
+    }
+
+    #[test]
+    fn nth() {
+        let filename = "cabide_nth.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new(filename, None).unwrap();
+        let objects: Vec<Vec<u8>> = (0..20u8)
+            .map(|i| vec![i; (i as usize % 4) * CONTENT_SIZE as usize])
+            .collect();
+        let blocks: Vec<u64> = objects.iter().map(|obj| cbd.write(obj).unwrap()).collect();
+        for i in (0..20).filter(|i| i % 3 == 1) {
+            cbd.remove(blocks[i]).unwrap();
+        }
+
+        let survivors: Vec<_> = (0..20)
+            .filter(|i| i % 3 != 1)
+            .map(|i| &objects[i])
+            .collect();
+        for (n, obj) in survivors.iter().enumerate() {
+            assert_eq!(cbd.nth(n as u64).unwrap().as_ref(), Some(*obj));
+        }
+        assert_eq!(cbd.nth(survivors.len() as u64).unwrap(), None);
+        drop(cbd);
+
+        // Objects between the extents of a fragmented one aren't skipped
+        let mut cbd = fragmented(filename);
+        let objects = cbd.filter(|_| true);
+        assert_eq!(objects.len(), 7);
+        for (n, obj) in objects.iter().enumerate() {
+            assert_eq!(cbd.nth(n as u64).unwrap().as_ref(), Some(obj));
+        }
+        assert_eq!(cbd.nth(7).unwrap(), None);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    /// Opens a fresh database with an object at block 1 fragmented over blocks 1, 3 and 5, with objects
+    /// between its extents
+    fn fragmented(filename: &str) -> Cabide<Vec<u8>> {
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::builder()
+            .fragmentation(true)
+            .allocation(Allocation::FirstFit)
+            .open(filename)
+            .unwrap();
+        for i in 0..10 {
+            cbd.write(&vec![i; CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize])
+                .unwrap();
+        }
+        for block in [1, 3, 5, 7] {
+            cbd.remove(block).unwrap();
+        }
+        assert_eq!(
+            cbd.write(&vec![10; 2 * CONTENT_SIZE as usize - LENGTH_SIZE as usize])
+                .unwrap(),
+            1
+        );
+        cbd
+    }
+
+    #[test]
+    fn read_counted() {
+        std::fs::File::create("cabide_read_counted.test").unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new("cabide_read_counted.test", None).unwrap();
+
+        let small = cbd.write(&vec![1; 3]).unwrap();
+        // Length prefix and the elements need a bit more than 4 blocks
+        let big = cbd.write(&vec![2; 4 * CONTENT_SIZE as usize]).unwrap();
+        assert_eq!(cbd.read_counted(small).unwrap(), (vec![1; 3], 1));
+        assert_eq!(
+            cbd.read_counted(big).unwrap(),
+            (vec![2; 4 * CONTENT_SIZE as usize], 5)
+        );
+
+        let (found, blocks) = cbd.filter_counted(|obj| obj.len() > 3);
+        assert_eq!(found, vec![vec![2; 4 * CONTENT_SIZE as usize]]);
+        assert_eq!(blocks, 6);
+        std::fs::remove_file("cabide_read_counted.test").unwrap();
+    }
+
+    #[test]
+    fn reload() {
+        std::fs::File::create("cabide_reload.test").unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::builder()
+            .sequence_numbers(true)
+            .open("cabide_reload.test")
+            .unwrap();
+        // Sequence number and length prefix take 16 bytes, so each object needs 2 blocks
+        for i in 0..5 {
+            assert_eq!(cbd.write(&vec![i; 13]).unwrap(), 2 * i as u64);
+        }
+        cbd.remove(0).unwrap();
+
+        // Another handle appends an object and empties the blocks next to the already empty ones
+        let mut other: Cabide<Vec<u8>> = Cabide::builder()
+            .sequence_numbers(true)
+            .open("cabide_reload.test")
+            .unwrap();
+        let appended = other.write(&vec![9; 100]).unwrap();
+        assert_eq!(appended, 10);
+        other.remove(2).unwrap();
+
+        cbd.reload().unwrap();
+        assert_eq!(cbd.next_block, other.next_block);
+        assert_eq!(cbd.empty_blocks.get(&4), Some(&vec![0]));
+        assert_eq!(cbd.next_sequence, Some(6));
+
+        // Exactly fills the emptied blocks, then the next write is appended after the other handle's object
+        let fill = vec![1; 4 * CONTENT_SIZE as usize - 16 - LENGTH_SIZE as usize];
+        assert_eq!(cbd.write(&fill).unwrap(), 0);
+        assert_eq!(cbd.write(&vec![2; 12]).unwrap(), other.next_block);
+        assert_eq!(cbd.read(appended).unwrap(), vec![9; 100]);
+        assert_eq!(cbd.read(0).unwrap(), fill);
+        std::fs::remove_file("cabide_reload.test").unwrap();
+    }
+
+    #[test]
+    fn golden_little_endian() {
+        type Row = (u16, u64, String, Vec<u32>);
+        // Written on x86_64 Linux, integers in every platform must be stored exactly like this
+        const GOLDEN: &[u8] = include_bytes!("../data/golden_le_sized.cabide");
+        // Written before the content's length was stored, objects end at the END_BYTE of their last block
+        const UNSIZED: &[u8] = include_bytes!("../data/golden_le.cabide");
+        let rows: [Row; 3] = [
+            (
+                1,
+                0x0102_0304_0506_0708,
+                "golden".to_owned(),
+                vec![1, 0xDEAD_BEEF],
+            ),
+            (2, 0, "removed".to_owned(), vec![]),
+            (
+                u16::MAX,
+                u64::MAX - 1,
+                "little endian".repeat(3),
+                (0..10).collect(),
+            ),
+        ];
+
+        for golden in [GOLDEN, UNSIZED] {
+            std::fs::write("cabide_golden.test", golden).unwrap();
+            let mut cbd: Cabide<Row> = Cabide::builder()
+                .sequence_numbers(true)
+                .open("cabide_golden.test")
+                .unwrap();
+            assert_eq!(cbd.object_blocks().unwrap(), vec![0, 4]);
+            assert_eq!(cbd.read(0).unwrap(), rows[0]);
+            assert_eq!(cbd.read(4).unwrap(), rows[2]);
+            assert_eq!(cbd.read_sequence(4).unwrap(), 2);
+            assert_eq!(cbd.next_sequence, Some(3));
+        }
+
+        // Writing the same objects produces the same bytes
+        std::fs::File::create("cabide_golden.test").unwrap();
+        let mut cbd: Cabide<Row> = Cabide::builder()
+            .sequence_numbers(true)
+            .open("cabide_golden.test")
+            .unwrap();
+        for row in &rows {
+            cbd.write(row).unwrap();
+        }
+        cbd.remove(2).unwrap();
+        assert_eq!(std::fs::read("cabide_golden.test").unwrap(), GOLDEN);
+        std::fs::remove_file("cabide_golden.test").unwrap();
+    }
+
+    #[test]
+    fn filter_with_blocks() {
+        std::fs::File::create("cabide_filter_with_blocks.test").unwrap();
+        let mut cbd: Cabide<String> = Cabide::new("cabide_filter_with_blocks.test", None).unwrap();
+
+        for i in 0..40 {
+            cbd.write(&"x".repeat(i)).unwrap();
+        }
+        cbd.remove(0).unwrap();
+
+        let found = cbd.filter_with_blocks(|value| value.len() % 3 == 0);
+        assert_eq!(found.len(), 13);
+        for (block, value) in found {
+            assert_eq!(value.len() % 3, 0);
+            assert_eq!(cbd.read(block).unwrap(), value);
+        }
+        std::fs::remove_file("cabide_filter_with_blocks.test").unwrap();
+    }
+
+    #[test]
+    fn scan_chunk_size() {
+        std::fs::File::create("cabide_scan_chunk.test").unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new("cabide_scan_chunk.test", None).unwrap();
+        for i in 0..200 {
+            cbd.write(&vec![i as u8; i % 70]).unwrap();
+        }
+        for block in cbd.object_blocks().unwrap().into_iter().step_by(3) {
+            cbd.remove(block).unwrap();
+        }
+        // Scanning block by block is the reference
+        cbd = Cabide::builder()
+            .scan_chunk_size(BLOCK_SIZE)
+            .open("cabide_scan_chunk.test")
+            .unwrap();
+        let (next_block, empty_blocks) = (cbd.next_block, cbd.empty_blocks.clone());
+        assert!(!empty_blocks.is_empty());
+
+        // Chunks that split the file in the middle of objects and of empty runs find the same blocks
+        for chunk_size in &[0, 1, 7 * BLOCK_SIZE + 3, 1 << 20] {
+            let cbd: Cabide<Vec<u8>> = Cabide::builder()
+                .scan_chunk_size(*chunk_size)
+                .open("cabide_scan_chunk.test")
+                .unwrap();
+            assert_eq!(cbd.next_block, next_block);
+            assert_eq!(cbd.empty_blocks, empty_blocks);
+        }
+        std::fs::remove_file("cabide_scan_chunk.test").unwrap();
+    }
+
+    #[test]
+    fn partition() {
+        std::fs::File::create("cabide_partition.test").unwrap();
+        let shard_path = |shard| PathBuf::from(format!("cabide_partition.{}.test", shard));
+        for shard in 0..4 {
+            let _ = std::fs::remove_file(shard_path(shard));
+        }
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_partition.test", None).unwrap();
+
+        let data = |i: u64| Data {
+            this: (i % 7) as u8,
+            that: i > 50,
+            there: "x".repeat(i as usize % 40),
+            those: i,
+            inner: InnerData { wow: None },
+        };
+        for i in 0..100 {
+            cbd.write(&data(i)).unwrap();
+        }
+
+        let shards = cbd
+            .into_partition(4, |data| data.this as usize, shard_path)
+            .unwrap();
+        let mut union = vec![];
+        for (shard, mut cabide) in shards.into_iter().enumerate() {
+            let objs = cabide.filter(|_| true);
+            assert!(objs.iter().all(|data| data.this as usize % 4 == shard));
+            union.extend(objs);
+            std::fs::remove_file(shard_path(shard)).unwrap();
+        }
+        union.sort_by_key(|data| data.those);
+        assert_eq!(union, (0..100).map(data).collect::<Vec<_>>());
+
+        // Objects were moved out of the source
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_partition.test", None).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 0);
+        assert!(cbd.partition(0, |_| 0, shard_path).is_err());
+        std::fs::remove_file("cabide_partition.test").unwrap();
+    }
+
+    #[test]
+    fn out_of_space() {
+        std::fs::File::create("cabide_out_of_space.test").unwrap();
+        let region = 10 * BLOCK_SIZE;
+        let mut neighbor: Cabide<Vec<u8>> =
+            Cabide::new_at("cabide_out_of_space.test", region, region, None).unwrap();
+        neighbor.write(&vec![7; 200]).unwrap();
+        let before = std::fs::read("cabide_out_of_space.test").unwrap();
+
+        let mut cbd: Cabide<Vec<u8>> =
+            Cabide::new_at("cabide_out_of_space.test", 0, region, Some(10)).unwrap();
+        // Fills 9 of the 10 blocks
+        cbd.write(&vec![
+            1;
+            9 * CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize
+        ])
+        .unwrap();
+        match cbd.write(&vec![2; CONTENT_SIZE as usize]) {
+            Err(Error::OutOfSpace {
+                needed: 2,
+                available: 1,
+            }) => {}
+            res => panic!("expected out of space error, got {:?}", res),
+        }
+        assert_eq!(cbd.write(&vec![3; 5]).unwrap(), 9);
+        assert!(matches!(
+            cbd.write(&vec![]),
+            Err(Error::OutOfSpace {
+                needed: 1,
+                available: 0
+            })
+        ));
+        assert!(matches!(
+            cbd.set_prefill(11),
+            Err(Error::OutOfSpace {
+                needed: 11,
+                available: 10
+            })
+        ));
+
+        let after = std::fs::read("cabide_out_of_space.test").unwrap();
+        assert_eq!(&after[region as usize..], &before[region as usize..]);
+        assert_eq!(neighbor.read(0).unwrap(), vec![7; 200]);
+        std::fs::remove_file("cabide_out_of_space.test").unwrap();
+    }
+
+    #[test]
+    fn remove_matching_stream() {
+        std::fs::File::create("cabide_remove_stream.test").unwrap();
+        let mut cbd: Cabide<u64> = Cabide::new("cabide_remove_stream.test", None).unwrap();
+        for i in 0..5000 {
+            cbd.write(&i).unwrap();
+        }
+
+        // Blocks are freed as the iterator advances, not when it's done
+        let first: Vec<u64> = cbd
+            .remove_matching_stream(|i| i % 2 == 1)
+            .take(1000)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(first, (0..1000).map(|i| i * 2 + 1).collect::<Vec<_>>());
+        assert_eq!(cbd.empty_blocks.get(&1).map(Vec::len), Some(1000));
+
+        let mut removed = 1000;
+        for (i, obj) in cbd.remove_matching_stream(|i| i % 2 == 1).enumerate() {
+            assert_eq!(obj.unwrap(), 2001 + 2 * i as u64);
+            removed += 1;
+        }
+        assert_eq!(removed, 2500);
+        assert_eq!(cbd.empty_blocks.get(&1).map(Vec::len), Some(2500));
+        assert_eq!(
+            cbd.filter(|_| true),
+            (0..2500).map(|i| i * 2).collect::<Vec<_>>()
+        );
+        std::fs::remove_file("cabide_remove_stream.test").unwrap();
+    }
+
+    #[test]
+    fn geometry() {
+        std::fs::File::create("cabide_geometry.test").unwrap();
+        let cbd: Cabide<u8> = Cabide::new("cabide_geometry.test", None).unwrap();
+        assert_eq!(
+            (cbd.block_size(), cbd.content_size()),
+            (BLOCK_SIZE, CONTENT_SIZE)
+        );
+        drop(cbd);
+        std::fs::File::create("cabide_geometry.test").unwrap();
+
+        let mut cbd: Cabide<Vec<u8>, 100> = Cabide::new("cabide_geometry.test", None).unwrap();
+        assert_eq!((cbd.block_size(), cbd.content_size()), (100, 98));
+        // Length prefix and the elements fill exactly a block
+        cbd.write(&vec![
+            1;
+            cbd.content_size() as usize - 8 - LENGTH_SIZE as usize
+        ])
+        .unwrap();
+        assert_eq!(
+            std::fs::metadata("cabide_geometry.test").unwrap().len(),
+            header::HEADER_SIZE + cbd.block_size()
+        );
+        std::fs::remove_file("cabide_geometry.test").unwrap();
+    }
+
+    #[test]
+    fn write_hinted() {
+        std::fs::File::create("cabide_write_hinted.test").unwrap();
+
+        for allocation in &[Allocation::BestFit, Allocation::FirstFit] {
+            let mut cbd: Cabide<Vec<u8>> = Cabide::builder()
+                .allocation(*allocation)
+                .open("cabide_write_hinted.test")
+                .unwrap();
+            cbd.truncate().unwrap();
+
+            // Holes of 1, 2 and 4 blocks, separated by objects
+            let mut holes = vec![];
+            for blocks in &[1, 2, 4] {
+                holes.push(
+                    cbd.write(&vec![
+                        0;
+                        blocks * CONTENT_SIZE as usize
+                            - 8
+                            - LENGTH_SIZE as usize
+                    ])
+                    .unwrap(),
+                );
+                cbd.write(&vec![]).unwrap();
+            }
+            for hole in &holes {
+                cbd.remove(*hole).unwrap();
+            }
+
+            // Without the hint the object would go to the 1 block hole
+            let small = vec![1; CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+            assert_eq!(cbd.write_hinted(&small, 3).unwrap(), holes[2]);
+            assert_eq!(cbd.write_hinted(&small, 2).unwrap(), holes[1]);
+            // No hole is big enough anymore, so it's appended
+            assert_eq!(cbd.write_hinted(&small, 4).unwrap(), 10);
+            let block = cbd.write_hinted(&small, 0).unwrap();
+            assert!(block == holes[0] || block == holes[1] + 1);
+            assert_eq!(cbd.read(holes[2]).unwrap(), small);
+        }
+        std::fs::remove_file("cabide_write_hinted.test").unwrap();
+    }
+
+    #[test]
+    fn allocation() {
+        std::fs::File::create("cabide_allocation.test").unwrap();
+
+        for (allocation, expected) in &[
+            (Allocation::BestFit, [9, 12]),
+            (Allocation::FirstFit, [1, 1]),
+        ] {
+            let mut cbd: Cabide<Vec<u8>> = Cabide::builder()
+                .prefill(20)
+                .allocation(*allocation)
+                .open("cabide_allocation.test")
+                .unwrap();
+
+            // Object of a single block, then an object of two blocks
+            for (len, expected) in [10, 30].iter().zip(expected) {
+                cbd.next_block = 20;
+                cbd.empty_blocks = vec![(1, vec![9]), (2, vec![5, 12]), (3, vec![1])]
+                    .into_iter()
+                    .collect();
+                assert_eq!(cbd.write(&vec![0; *len]).unwrap(), *expected);
+            }
+        }
+        std::fs::remove_file("cabide_allocation.test").unwrap();
+    }
+
+    #[test]
+    fn coalesce_free_runs() {
+        std::fs::File::create("cabide_coalesce.test").unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new("cabide_coalesce.test", None).unwrap();
+        let single = vec![1; CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+        let blocks: Vec<u64> = (0..6).map(|_| cbd.write(&single).unwrap()).collect();
+        assert_eq!(blocks, vec![0, 1, 2, 3, 4, 5]);
+
+        cbd.remove(2).unwrap();
+        cbd.remove(3).unwrap();
+        assert_eq!(cbd.empty_blocks.get(&1), None);
+        assert_eq!(cbd.empty_blocks.get(&2), Some(&vec![2]));
+
+        // Runs on both sides are merged
+        cbd.remove(0).unwrap();
+        cbd.remove(1).unwrap();
+        assert_eq!(cbd.empty_blocks.len(), 1);
+        assert_eq!(cbd.empty_blocks.get(&4), Some(&vec![0]));
+
+        let double = vec![2; 2 * CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+        let quadruple = vec![4; 4 * CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+        cbd.remove(4).unwrap();
+        assert_eq!(cbd.write(&quadruple).unwrap(), 0);
+        assert_eq!(cbd.empty_blocks.get(&1), Some(&vec![4]));
+        cbd.remove(0).unwrap();
+        cbd.write(&double).unwrap();
+        assert_eq!(cbd.read(5).unwrap(), single);
+        std::fs::remove_file("cabide_coalesce.test").unwrap();
+    }
+
+    /// Always picks the free run with the lowest starting block
+    #[derive(Debug, Default)]
+    struct LowestFirst {
+        runs: BTreeMap<u64, u64>,
+    }
+
+    impl Allocator for LowestFirst {
+        fn allocate(&mut self, blocks: u64) -> Option<u64> {
+            let (start, len) = self
+                .runs
+                .iter()
+                .map(|(start, len)| (*start, *len))
+                .find(|(_, len)| *len >= blocks)?;
+            self.runs.remove(&start);
+            if len > blocks {
+                self.runs.insert(start + blocks, len - blocks);
+            }
+            Some(start)
+        }
+
+        fn free(&mut self, mut start: u64, mut len: u64) {
+            if let Some(next) = self.runs.remove(&(start + len)) {
+                len += next;
+            }
+            let previous = self.runs.range(..start).next_back().map(|(s, l)| (*s, *l));
+            if let Some((previous, previous_len)) = previous.filter(|(s, l)| s + l == start) {
+                start = previous;
+                len += previous_len;
+            }
+            self.runs.insert(start, len);
+        }
+
+        fn clear(&mut self) {
+            self.runs.clear();
+        }
+    }
+
+    #[test]
+    fn custom_allocator() {
+        let filename = "cabide_custom_allocator.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = CabideBuilder::default()
+            .allocator(LowestFirst::default())
+            .open(filename)
+            .unwrap();
+
+        let single = vec![1; CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+        for _ in 0..10 {
+            cbd.write(&single).unwrap();
+        }
+        // Built-in free list isn't used
+        for block in [7, 2, 5] {
+            cbd.remove(block).unwrap();
+        }
+        assert!(cbd.empty_blocks.is_empty());
+        assert_eq!(cbd.write(&single).unwrap(), 2);
+        assert_eq!(cbd.write(&single).unwrap(), 5);
+
+        // Runs found when opening are given to the allocator too
+        drop(cbd);
+        let mut cbd: Cabide<Vec<u8>> = CabideBuilder::default()
+            .allocator(LowestFirst::default())
+            .open(filename)
+            .unwrap();
+        cbd.remove(3).unwrap();
+        cbd.remove(4).unwrap();
+        let double = vec![2; 2 * CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+        assert_eq!(cbd.write(&double).unwrap(), 3);
+        assert_eq!(cbd.write(&single).unwrap(), 7);
+        assert_eq!(cbd.write(&single).unwrap(), 10);
+        assert_eq!(cbd.read(3).unwrap(), double);
+        assert_eq!(cbd.read(7).unwrap(), single);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn reclaim_orphans() {
+        let filename = "cabide_reclaim_orphans.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new(filename, None).unwrap();
+        let single = vec![1; CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+        let long = vec![2; 4 * CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+        cbd.write(&single).unwrap();
+        let first = cbd.write(&long).unwrap();
+        cbd.write(&single).unwrap();
+        let second = cbd.write(&long).unwrap();
+        assert_eq!(cbd.reclaim_orphans().unwrap(), 0);
+
+        // Crash while writing the continuations of the last object
+        let file = OpenOptions::new().write(true).open(filename).unwrap();
+        file.set_len((second + 2) * BLOCK_SIZE).unwrap();
+        drop(file);
+
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new(filename, None).unwrap();
+        assert!(matches!(cbd.read(second), Err(Error::CorruptedBlock)));
+        assert_eq!(cbd.reclaim_orphans().unwrap(), 2);
+        assert!(matches!(cbd.read(second), Err(Error::EmptyBlock { .. })));
+        assert_eq!(cbd.empty_blocks.get(&2), Some(&vec![second]));
+        assert_eq!(cbd.read(first).unwrap(), long);
+        assert_eq!(cbd.filter(|_| true), vec![single.clone(), long, single]);
+        assert_eq!(cbd.reclaim_orphans().unwrap(), 0);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn filter_mut() {
+        let filename = "cabide_filter_mut.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<u64> = Cabide::new(filename, None).unwrap();
+        for i in 0..20 {
+            cbd.write(&i).unwrap();
+        }
+
+        // Aggregates are computed while filtering
+        let (mut seen, mut sum) = (0, 0);
+        let even = cbd.filter(|i| {
+            seen += 1;
+            sum += i;
+            i % 2 == 0
+        });
+        assert_eq!(even, (0..20).step_by(2).collect::<Vec<_>>());
+        assert_eq!((seen, sum), (20, 190));
+
+        let mut skipped = 0;
+        let first = cbd.first(|i| {
+            skipped += 1;
+            *i > 4
+        });
+        assert_eq!((first, skipped), (Some(5), 6));
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn fragmentation() {
+        let filename = "cabide_fragmentation.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::builder()
+            .fragmentation(true)
+            .allocation(Allocation::FirstFit)
+            .open(filename)
+            .unwrap();
+        let single = vec![1; CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+        for _ in 0..10 {
+            cbd.write(&single).unwrap();
+        }
+        for block in [1, 3, 5, 7] {
+            cbd.remove(block).unwrap();
+        }
+
+        // Needs 3 blocks once two of them link to the next one, but no free blocks are next to each other
+        let large = vec![2; 2 * CONTENT_SIZE as usize - LENGTH_SIZE as usize];
+        assert_eq!(cbd.write(&large).unwrap(), 1);
+        assert_eq!(cbd.blocks().unwrap(), 10);
+        assert_eq!(cbd.read(1).unwrap(), large);
+        assert!(matches!(
+            cbd.read(3),
+            Err(Error::ContinuationBlock { block: 3 })
+        ));
+        assert_eq!(cbd.empty_blocks.get(&1), Some(&vec![7]));
+        let mut objects = vec![single.clone(); 6];
+        objects.insert(1, large.clone());
+        assert_eq!(cbd.filter(|_| true), objects);
+        assert_eq!(cbd.reclaim_orphans().unwrap(), 0);
+
+        // Every part is freed
+        assert_eq!(cbd.remove(1).unwrap(), large);
+        let mut free = cbd.empty_blocks.get(&1).cloned().unwrap();
+        free.sort_unstable();
+        assert_eq!(free, vec![1, 3, 5, 7]);
+
+        // Objects are only fragmented if the free blocks fit them
+        let huge = vec![3; 5 * CONTENT_SIZE as usize];
+        assert_eq!(cbd.write(&huge).unwrap(), 10);
+        assert_eq!(cbd.empty_blocks.get(&1).map(Vec::len), Some(4));
+        assert_eq!(cbd.write(&large).unwrap(), 1);
+        drop(cbd);
+
+        // Fragmented objects are read without the option, compaction makes them continuous
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new(filename, None).unwrap();
+        assert_eq!(cbd.empty_blocks.get(&1), Some(&vec![7]));
+        assert_eq!(cbd.read(1).unwrap(), large);
+        let objects = cbd.filter(|_| true);
+        cbd.compact_resumable(4).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 6 + 3 + 6);
+        let mut compacted = cbd.filter(|_| true);
+        compacted.sort();
+        let mut objects = objects;
+        objects.sort();
+        assert_eq!(compacted, objects);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn filter_to() {
+        let filename = "cabide_filter_to.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new(filename, None).unwrap();
+        let objects: Vec<Data> = (0..100).map(|_| random_data()).collect();
+        for obj in &objects {
+            cbd.write(obj).unwrap();
+        }
+
+        let mut jsonl = vec![];
+        let written = cbd
+            .filter_to(
+                |data| data.that,
+                &mut jsonl,
+                |data, out| writeln!(out, r#"{{"there":"{}","this":{}}}"#, data.there, data.this),
+            )
+            .unwrap();
+
+        let expected: Vec<String> = objects
+            .iter()
+            .filter(|data| data.that)
+            .map(|data| format!(r#"{{"there":"{}","this":{}}}"#, data.there, data.this))
+            .collect();
+        assert_eq!(written, expected.len() as u64);
+        assert_eq!(
+            String::from_utf8(jsonl)
+                .unwrap()
+                .lines()
+                .collect::<Vec<_>>(),
+            expected
+        );
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn create_or_open() {
+        let filename = "cabide_create_or_open.test";
+        let _ = std::fs::remove_file(filename);
+
+        let mut cbd: Cabide<Data> = Cabide::create_or_open(filename, 20).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 20);
+        let data = random_data();
+        assert_eq!(cbd.write(&data).unwrap(), 0);
+        cbd.truncate().unwrap();
+        let block = cbd.write(&data).unwrap();
+        let blocks = cbd.blocks().unwrap();
+        assert!(blocks < 20);
+
+        // this drops the last cabide, therefore closes the file
+        cbd = Cabide::create_or_open(filename, 20).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), blocks);
+        assert_eq!(cbd.read(block).unwrap(), data);
+        drop(cbd);
+
+        // Even empty files already exist, so they aren't pre-filled either
+        std::fs::File::create(filename).unwrap();
+        let cbd: Cabide<Data> = Cabide::create_or_open(filename, 20).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 0);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn set_prefill() {
+        std::fs::File::create("cabide_set_prefill.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_set_prefill.test", Some(2)).unwrap();
+
+        let first = random_data();
+        assert_eq!(cbd.write(&first).unwrap(), 0);
+        cbd.set_prefill(30).unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 30);
+
+        // New empty blocks are used right after the existing objects, without growing the file
+        let mut objects = vec![(0, first)];
+        for _ in 0..5 {
+            let data = random_data();
+            objects.push((cbd.write(&data).unwrap(), data));
+        }
+        assert_eq!(objects[1].0, 3);
+        assert_eq!(cbd.blocks().unwrap(), 30);
+
+        // this drops the last cabide, therefore closes the file
+        cbd = Cabide::new("cabide_set_prefill.test", None).unwrap();
+        for (block, data) in &objects {
+            assert_eq!(&cbd.read(*block).unwrap(), data);
+        }
+        std::fs::remove_file("cabide_set_prefill.test").unwrap();
+    }
+
+    #[test]
+    fn block_out_of_bounds() {
+        std::fs::File::create("cabide_out_of_bounds.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_out_of_bounds.test", Some(10)).unwrap();
+        let data = random_data();
+        let block = cbd.write(&data).unwrap();
+        let blocks = cbd.blocks().unwrap();
+        assert_eq!(blocks, 10);
+
+        // Last prefilled block exists, it's just empty
+        assert!(matches!(
+            cbd.read(blocks - 1),
+            Err(Error::EmptyBlock { block: 9 })
+        ));
+        match cbd.read(blocks) {
+            Err(
+                err @ Error::BlockOutOfBounds {
+                    block: 10,
+                    blocks: 10,
+                },
+            ) => assert_eq!(
+                err.to_string(),
+                "Block 10 is out of bounds, database has 10 blocks"
+            ),
+            res => panic!("expected out of bounds error, got {:?}", res),
+        }
+        assert!(matches!(
+            cbd.remove(blocks + 5),
+            Err(Error::BlockOutOfBounds { block: 15, .. })
+        ));
+
+        // Nothing was changed by the failed reads
+        assert_eq!(cbd.blocks().unwrap(), 10);
+        assert_eq!(cbd.read(block).unwrap(), data);
+        std::fs::remove_file("cabide_out_of_bounds.test").unwrap();
+    }
+
+    #[test]
+    fn huge_blocks() {
+        std::fs::File::create("cabide_huge_blocks.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_huge_blocks.test", None).unwrap();
+        let data = random_data();
+        cbd.write(&data).unwrap();
+
+        // `u64::MAX * BLOCK_SIZE` would wrap around to a block before the end of the file
+        for block in &[u64::MAX, u64::MAX / BLOCK_SIZE + 1] {
+            match cbd.read(*block) {
+                Err(Error::BlockOutOfBounds { block: b, blocks }) => {
+                    assert_eq!((b, blocks), (*block, cbd.blocks().unwrap()))
+                }
+                res => panic!("expected out of bounds error, got {:?}", res),
+            }
+            assert!(matches!(
+                cbd.remove(*block),
+                Err(Error::BlockOutOfBounds { .. })
+            ));
+        }
+        assert_eq!(cbd.read(0).unwrap(), data);
+
+        // Appending past the last representable block fails before anything is written
+        cbd.next_block = u64::MAX - 1;
+        assert!(matches!(
+            cbd.write(&data),
+            Err(Error::BlockOutOfBounds { .. })
+        ));
+        std::fs::remove_file("cabide_huge_blocks.test").unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn with_open_options() {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        let filename = "cabide_open_options.test";
+        let _ = std::fs::remove_file(filename);
+        let mut options = OpenOptions::new();
+        options.mode(0o640).append(true);
+        let mut cbd: Cabide<Data> = Cabide::with_open_options(filename, None, options).unwrap();
+        let mode = std::fs::metadata(filename).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        // Existing files are opened with them too, appending is disabled since it breaks writes to free blocks
+        let data = random_data();
+        cbd.write(&random_data()).unwrap();
+        let block = cbd.write(&data).unwrap();
+        cbd.remove(0).unwrap();
+        drop(cbd);
+        let mut options = OpenOptions::new();
+        options.mode(0o600);
+        let mut cbd: Cabide<Data> = Cabide::with_open_options(filename, None, options).unwrap();
+        assert_eq!(cbd.read(block).unwrap(), data);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn prefetch() {
+        std::fs::File::create("cabide_prefetch.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_prefetch.test", None).unwrap();
+        let objects: Vec<Data> = (0..50).map(|_| random_data()).collect();
+        for data in &objects {
+            cbd.write(data).unwrap();
+        }
+
+        let blocks = cbd.blocks().unwrap();
+        cbd.prefetch(0..blocks).unwrap();
+        // Ranges past the end of the file are fine, there is just nothing to read ahead
+        cbd.prefetch(blocks..blocks * 2).unwrap();
+        cbd.prefetch(3..3).unwrap();
+        assert!(matches!(
+            cbd.prefetch(0..u64::MAX),
+            Err(Error::BlockOutOfBounds { .. })
+        ));
+        assert_eq!(cbd.filter(|_| true), objects);
+        std::fs::remove_file("cabide_prefetch.test").unwrap();
+    }
+
+    #[test]
+    fn invalid_block_size() {
+        std::fs::File::create("cabide_block_size.test").unwrap();
+        let res = Cabide::<Data, 2>::new("cabide_block_size.test", None);
+        match res {
+            Err(err @ Error::InvalidBlockSize { block_size: 2 }) => assert_eq!(
+                err.to_string(),
+                "Block size 2 is invalid, blocks need at least 3 bytes"
+            ),
+            res => panic!(
+                "expected invalid block size error, got {:?}",
+                res.map(|_| ())
+            ),
+        }
+
+        let mut cbd: Cabide<Data, { protocol::MIN_BLOCK_SIZE as usize }> =
+            Cabide::new("cabide_block_size.test", None).unwrap();
+        protocol::debug_assert_layout(cbd.block_size());
+        let data = random_data();
+        let block = cbd.write(&data).unwrap();
+        assert_eq!(cbd.read(block).unwrap(), data);
+        std::fs::remove_file("cabide_block_size.test").unwrap();
+    }
+
+    #[test]
+    fn object_too_long() {
+        std::fs::File::create("cabide_too_long.test").unwrap();
+        let mut cbd: Cabide<String> = Cabide::new("cabide_too_long.test", None).unwrap();
+        cbd.set_max_object_blocks(10);
+
+        // Hand-crafted string that claims a million bytes, followed by a never-ending chain of continuations
+        let mut block = vec![Metadata::Start as u8];
+        block.extend(&1_000_000u64.to_le_bytes());
+        block.resize(BLOCK_SIZE as usize - 1, b'a');
+        block.push(END_BYTE);
+        for i in 0..50 {
+            if i > 0 {
+                block[0] = Metadata::Continuation as u8;
+                block[1..9].copy_from_slice(b"aaaaaaaa");
+            }
+            cbd.file.write_all(&block).unwrap();
+        }
+        cbd.next_block = 50;
+
+        for result in &[cbd.read(0), cbd.remove(0)] {
+            match result {
+                Err(err @ Error::ObjectTooLong { block: 0 }) => assert_eq!(
+                    err.to_string(),
+                    "Object at block 0 spans more blocks than allowed"
+                ),
+                res => panic!("expected object too long error, got {:?}", res),
+            }
+        }
+
+        // Real objects under the limit can be read
+        cbd.truncate().unwrap();
+        let block = cbd.write(&"a".repeat(200)).unwrap();
+        assert_eq!(cbd.read(block).unwrap(), "a".repeat(200));
+        std::fs::remove_file("cabide_too_long.test").unwrap();
     }
 
-    #[derive(Serialize, Deserialize, PartialEq, Debug)]
-    struct Data {
-        this: u8,
-        that: bool,
-        there: String,
-        those: u64,
-        inner: InnerData,
+    #[test]
+    fn object_blocks() {
+        std::fs::File::create("cabide_object_blocks.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_object_blocks.test", None).unwrap();
+
+        let mut blocks = vec![];
+        for _ in 0..30 {
+            blocks.push(cbd.write(&random_data()).unwrap());
+        }
+        for block in blocks.drain(10..15) {
+            cbd.remove(block).unwrap();
+        }
+
+        assert_eq!(cbd.object_blocks().unwrap(), blocks);
+        std::fs::remove_file("cabide_object_blocks.test").unwrap();
     }
 
-    fn random_data() -> Data {
-        // this is slow af since it doesn't cache thread_rng
-        Data {
-            this: random(),
-            that: random(),
-            there: (0..40)
-                .map(|_| Alphanumeric.sample(&mut thread_rng()))
-                .collect(),
-            those: random(),
-            inner: InnerData {
-                wow: Some(random()).filter(|_| random()),
-            },
+    #[test]
+    fn read_batch() {
+        std::fs::File::create("cabide_read_batch.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_read_batch.test", None).unwrap();
+
+        let mut objects = vec![];
+        for _ in 0..20 {
+            let data = random_data();
+            objects.push((cbd.write(&data).unwrap(), data));
+        }
+
+        let indexes = [13, 2, 19, 0, 7, 2];
+        let blocks: Vec<u64> = indexes.iter().map(|i| objects[*i].0).collect();
+        let mut batch = cbd.read_batch(&blocks);
+        for (result, index) in batch.iter().zip(&indexes) {
+            assert_eq!(result.as_ref().unwrap(), &objects[*index].1);
         }
+
+        // Failures are reported in their own position
+        batch = cbd.read_batch(&[objects[5].0, objects[5].0 + 1]);
+        assert_eq!(batch[0].as_ref().unwrap(), &objects[5].1);
+        assert!(matches!(batch[1], Err(Error::ContinuationBlock { .. })));
+        std::fs::remove_file("cabide_read_batch.test").unwrap();
     }
 
     #[test]
-    fn persistance() {
-        std::fs::File::create("cabide.test").unwrap();
-        let mut cbd: Cabide<Data> = Cabide::new("cabide.test", None).unwrap();
-        cbd.file.set_len(0).unwrap();
+    fn patch() {
+        std::fs::File::create("cabide_patch.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_patch.test", None).unwrap();
 
-        let mut blocks = vec![];
-        for _ in 0..50 {
+        let mut objects = vec![];
+        for _ in 0..10 {
             let data = random_data();
-            let block = cbd.write(&data).unwrap();
-            blocks.push((block, data));
+            objects.push((cbd.write(&data).unwrap(), data));
         }
 
-        // this drops the last cabide, therefore closes the file
-        cbd = Cabide::new("cabide.test", Some(10)).unwrap();
+        let (block, mut expected) = objects.remove(4);
+        let patched = cbd
+            .patch(block, |data| data.those = data.those.wrapping_add(1))
+            .unwrap();
+        expected.those = expected.those.wrapping_add(1);
+        assert_eq!(cbd.read(patched).unwrap(), expected);
 
-        for (block, data) in &blocks {
-            let read: Data = cbd.read(*block).unwrap();
-            assert_eq!(&read, data);
+        for (block, data) in &objects {
+            assert_eq!(&cbd.read(*block).unwrap(), data);
         }
+        assert!(matches!(
+            cbd.patch(objects[0].0 + 1, |_| {}),
+            Err(Error::ContinuationBlock { .. })
+        ));
 
-        assert_eq!(cbd.remove(blocks[8].0).unwrap(), blocks[8].1);
+        // Objects that still fit keep their block, the ones that can't be moved are kept as they were
+        std::fs::File::create("cabide_patch.test").unwrap();
+        let mut cbd: Cabide<String> = Cabide::new_at("cabide_patch.test", 0, 90, None).unwrap();
+        let block = cbd.write(&"hello".to_owned()).unwrap();
+        cbd.write(&"next".to_owned()).unwrap();
+        assert_eq!(cbd.patch(block, |obj| obj.push('!')).unwrap(), block);
+        assert!(matches!(
+            cbd.patch(block, |obj| *obj = obj.repeat(50)),
+            Err(Error::OutOfSpace { .. })
+        ));
+        assert_eq!(cbd.read(block).unwrap(), "hello!");
+        std::fs::remove_file("cabide_patch.test").unwrap();
+    }
 
-        assert_eq!(cbd.remove(blocks[13].0).unwrap(), blocks[13].1);
+    #[test]
+    fn snapshot() {
+        std::fs::File::create("cabide_snapshot.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new_at("cabide_snapshot.test", 60, 3000, None).unwrap();
+
+        let mut objects = vec![];
+        for _ in 0..10 {
+            let data = random_data();
+            objects.push((cbd.write(&data).unwrap(), data));
+        }
+        cbd.snapshot("cabide_snapshot.snapshot.test").unwrap();
+
+        cbd.remove(objects[3].0).unwrap();
+        cbd.write(&random_data()).unwrap();
+
+        let mut snapshot: Cabide<Data> =
+            Cabide::new("cabide_snapshot.snapshot.test", None).unwrap();
+        let (blocks, objects): (Vec<u64>, Vec<Data>) = objects.into_iter().unzip();
+        assert_eq!(snapshot.object_blocks().unwrap(), blocks);
+        assert_eq!(snapshot.filter(|_| true), objects);
+        assert!(!Path::new("cabide_snapshot.snapshot.test.tmp").exists());
+        std::fs::remove_file("cabide_snapshot.test").unwrap();
+        std::fs::remove_file("cabide_snapshot.snapshot.test").unwrap();
+
+        // Free blocks sidecar is copied with the snapshot
+        let (filename, snapshot) = (
+            "cabide_snapshot_idx.test",
+            "cabide_snapshot_idx.snapshot.test",
+        );
+        std::fs::File::create(filename).unwrap();
+        let open = |filename| -> Cabide<u8> {
+            Cabide::builder()
+                .persist_free_blocks(true)
+                .open(filename)
+                .unwrap()
+        };
+        let mut cbd = open(filename);
+        for i in 0..1000 {
+            cbd.write(&(i as u8)).unwrap();
+        }
+        for block in (0..990).step_by(3) {
+            cbd.remove(block).unwrap();
+        }
+        cbd.snapshot(snapshot).unwrap();
+        assert!(Path::new("cabide_snapshot_idx.snapshot.test.idx").is_file());
+        assert!(!Path::new("cabide_snapshot_idx.snapshot.test.idx.tmp").exists());
+
+        let copy = open(snapshot);
+        assert!(copy.file.read_bytes < 1000, "{}", copy.file.read_bytes);
+        assert_eq!(copy.empty_blocks, cbd.empty_blocks);
+        assert_eq!(copy.next_block, cbd.next_block);
+        drop(copy);
+        drop(cbd);
+        for file in [filename, snapshot] {
+            std::fs::remove_file(file).unwrap();
+            std::fs::remove_file(format!("{}.idx", file)).unwrap();
+        }
+    }
+
+    #[test]
+    fn fingerprint() {
+        std::fs::File::create("cabide_fingerprint.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_fingerprint.test", None).unwrap();
+
+        let mut blocks = vec![];
+        for _ in 0..30 {
+            blocks.push(cbd.write(&random_data()).unwrap());
+        }
+        for block in blocks.iter().step_by(4) {
+            cbd.remove(*block).unwrap();
+        }
+
+        let fingerprint = cbd.fingerprint().unwrap();
+        std::fs::copy(
+            "cabide_fingerprint.test",
+            "cabide_fingerprint_compacted.test",
+        )
+        .unwrap();
+        let mut compacted: Cabide<Data> =
+            Cabide::new("cabide_fingerprint_compacted.test", None).unwrap();
+        compacted.compact_resumable(5).unwrap();
+        assert!(compacted.blocks().unwrap() < cbd.blocks().unwrap());
+        assert_eq!(compacted.fingerprint().unwrap(), fingerprint);
+
+        cbd.remove(blocks[1]).unwrap();
+        assert_ne!(cbd.fingerprint().unwrap(), fingerprint);
+        std::fs::remove_file("cabide_fingerprint.test").unwrap();
+        std::fs::remove_file("cabide_fingerprint_compacted.test").unwrap();
+    }
+
+    #[test]
+    fn padding_is_not_empty() {
+        std::fs::File::create("cabide_padding.test").unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new("cabide_padding.test", None).unwrap();
+
+        let mut objects = vec![];
+        for len in 0..70 {
+            // Contents that end in framing bytes
+            let mut obj = vec![len as u8; len];
+            obj.extend(&[PAD_BYTE, END_BYTE, Metadata::Empty as u8][..len % 4]);
+            objects.push((cbd.write(&obj).unwrap(), obj));
+        }
+
+        let mut block = vec![0; BLOCK_SIZE as usize];
+        cbd.seek_block(0).unwrap();
+        cbd.file.read_exact(&mut block).unwrap();
+        // `Vec` with no elements only has its length, after the 8 bytes of the content's length
+        let mut expected = vec![Metadata::SizedStart as u8, 8, 0, 0, 0, 0, 0, 0, 0];
+        expected.extend(&[0, 0, 0, 0, 0, 0, 0, 0, END_BYTE]);
+        assert_eq!(&block[..18], &expected[..]);
+        assert!(block[18..].iter().all(|byte| *byte == PAD_BYTE));
+
+        // Padding starts right after the END_BYTE that follows the recorded length of content
+        for (start, obj) in &objects {
+            let length = serialize(obj).unwrap().len();
+            let mut block = vec![0; 1 + LENGTH_SIZE as usize];
+            cbd.seek_block(*start).unwrap();
+            cbd.file.read_exact(&mut block).unwrap();
+            assert_eq!(block[1..], (length as u64).to_le_bytes());
+
+            let blocks = cbd.needed_blocks(&serialize(obj).unwrap());
+            let used =
+                LENGTH_SIZE as usize + length - (blocks as usize - 1) * CONTENT_SIZE as usize;
+            let mut block = vec![0; BLOCK_SIZE as usize];
+            cbd.seek_block(start + blocks - 1).unwrap();
+            cbd.file.read_exact(&mut block).unwrap();
+            assert_eq!(block[used + 1], END_BYTE);
+            assert!(block[used + 2..].iter().all(|byte| *byte == PAD_BYTE));
+        }
 
         // this drops the last cabide, therefore closes the file
-        cbd = Cabide::new("cabide.test", Some(10)).unwrap();
+        cbd = Cabide::new("cabide_padding.test", None).unwrap();
+        assert!(cbd.empty_blocks.is_empty());
+        for (block, obj) in objects {
+            assert_eq!(cbd.read(block).unwrap(), obj);
+        }
+        std::fs::remove_file("cabide_padding.test").unwrap();
+    }
 
-        for i in &[8, 13] {
+    #[test]
+    fn content_ends_in_framing_bytes() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Tail {
+            data: Data,
+            last: Option<u8>,
+        }
+
+        std::fs::File::create("cabide_tail.test").unwrap();
+        let mut cbd: Cabide<Tail> = Cabide::new("cabide_tail.test", None).unwrap();
+
+        let mut objects = vec![];
+        // Lengths that make the content end anywhere in its last block, including right before its END_BYTE
+        for len in 0..2 * BLOCK_SIZE as usize {
+            for (last, byte) in [(None, Metadata::Empty as u8), (Some(END_BYTE), END_BYTE)] {
+                let mut data = random_data();
+                data.there = "a".repeat(len);
+                data.inner.wow = None;
+                let obj = Tail { data, last };
+                assert_eq!(serialize(&obj).unwrap().last(), Some(&byte));
+                objects.push((cbd.write(&obj).unwrap(), obj));
+            }
+        }
+
+        for (block, obj) in &objects {
+            assert_eq!(&cbd.read(*block).unwrap(), obj);
+        }
+
+        // Content's length is stored right after the metadata of its first block
+        let (block, length) = (objects[2].0, serialize(&objects[2].1).unwrap().len() as u64);
+        let mut start = [0; 1 + LENGTH_SIZE as usize];
+        cbd.seek_block(block).unwrap();
+        cbd.file.read_exact(&mut start).unwrap();
+        assert_eq!(start[0], Metadata::SizedStart as u8);
+        assert_eq!(start[1..], length.to_le_bytes());
+
+        // Content can't end past its last block, or anywhere but right before its END_BYTE
+        for length in [length - 1, length + 1, 1_000] {
+            cbd.seek_block(block).unwrap();
+            cbd.file.write_all(&start[..1]).unwrap();
+            cbd.file.write_all(&length.to_le_bytes()).unwrap();
+            assert!(matches!(cbd.read(block), Err(Error::CorruptedBlock)));
+        }
+        cbd.seek_block(block).unwrap();
+        cbd.file.write_all(&start).unwrap();
+
+        cbd = Cabide::new("cabide_tail.test", None).unwrap();
+        for (block, obj) in objects {
+            assert_eq!(cbd.remove(block).unwrap(), obj);
+        }
+        std::fs::remove_file("cabide_tail.test").unwrap();
+    }
+
+    #[test]
+    fn reuse_keeps_neighbours() {
+        std::fs::File::create("cabide_neighbours.test").unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new("cabide_neighbours.test", None).unwrap();
+        // Contents (with their length prefixes) that aren't a multiple of the content size
+        let (one, two, three) = (vec![1; 5], vec![2; 38], vec![3; 60]);
+        let stored = LENGTH_SIZE as usize + serialize(&two).unwrap().len();
+        assert_ne!(stored % CONTENT_SIZE as usize, 0);
+
+        let first = cbd.write(&two).unwrap();
+        let neighbour = cbd.write(&two).unwrap();
+        assert_eq!(neighbour, first + 2);
+        cbd.remove(first).unwrap();
+        assert_eq!(cbd.write(&vec![4; 30]).unwrap(), first);
+        assert!(cbd.empty_blocks.values().all(Vec::is_empty));
+        assert_eq!(cbd.read(neighbour).unwrap(), two);
+
+        // Only the blocks really written are taken from a bigger run, the rest stays free
+        let longer = cbd.write(&three).unwrap();
+        let neighbour = cbd.write(&three).unwrap();
+        assert_eq!(neighbour, longer + 3);
+        cbd.remove(longer).unwrap();
+        assert_eq!(cbd.write(&two).unwrap(), longer);
+        assert_eq!(cbd.empty_blocks.get(&1), Some(&vec![longer + 2]));
+        assert_eq!(cbd.write(&one).unwrap(), longer + 2);
+        assert!(cbd.empty_blocks.values().all(Vec::is_empty));
+        assert_eq!(cbd.read(longer).unwrap(), two);
+        assert_eq!(cbd.read(longer + 2).unwrap(), one);
+        assert_eq!(cbd.read(neighbour).unwrap(), three);
+        std::fs::remove_file("cabide_neighbours.test").unwrap();
+    }
+
+    #[test]
+    fn drain() {
+        std::fs::File::create("cabide_drain.test").unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new("cabide_drain.test", Some(100)).unwrap();
+
+        let mut objects = vec![];
+        for _ in 0..20 {
             let data = random_data();
-            let block = cbd.write(&data).unwrap();
-            blocks[*i as usize] = (block, data);
+            objects.push((cbd.write(&data).unwrap(), data));
         }
+        let removed = objects.remove(4);
+        cbd.remove(removed.0).unwrap();
 
-        cbd = Cabide::new("cabide.test", Some(10)).unwrap();
+        assert_eq!(cbd.drain().unwrap().collect::<Vec<_>>(), objects);
+        assert!(cbd.object_blocks().unwrap().is_empty());
+        assert!(cbd.filter(|_| true).is_empty());
+        std::fs::remove_file("cabide_drain.test").unwrap();
+    }
 
-        for (block, data) in blocks {
-            assert_eq!(cbd.read(block).unwrap(), data);
+    #[test]
+    fn reap_expired() {
+        use std::time::Duration;
+
+        std::fs::File::create("cabide_reap.test").unwrap();
+        let mut cbd: Cabide<(Data, Option<SystemTime>)> =
+            Cabide::new("cabide_reap.test", None).unwrap();
+        cbd.set_expiry(|(_, expires_at)| *expires_at);
+
+        let (now, mut alive) = (SystemTime::now(), vec![]);
+        for i in 0..30 {
+            let expires_at = match i % 3 {
+                0 => Some(now - Duration::from_secs(60)),
+                1 => Some(now + Duration::from_secs(3600)),
+                _ => None,
+            };
+            let obj = (random_data(), expires_at);
+            cbd.write(&obj).unwrap();
+            if i % 3 != 0 {
+                alive.push(obj);
+            }
         }
-        std::fs::remove_file("cabide.test").unwrap();
+
+        assert_eq!(cbd.reap_expired().unwrap(), 10);
+        assert_eq!(cbd.filter(|_| true), alive);
+        assert_eq!(cbd.reap_expired().unwrap(), 0);
+        std::fs::remove_file("cabide_reap.test").unwrap();
+    }
+
+    #[test]
+    fn prefilled_scan() {
+        let filename = "cabide_prefilled_scan.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<u8> = Cabide::new(filename, Some(100_000)).unwrap();
+        for i in 0..10 {
+            cbd.write(&i).unwrap();
+        }
+        assert_eq!(cbd.blocks().unwrap(), 100_000);
+        assert_eq!(cbd.written_blocks(), 10);
+
+        // Scans stop at the last object, so they never reach this block
+        cbd.seek_block(50_000).unwrap();
+        cbd.file.write_all(&[Metadata::Start as u8]).unwrap();
+        assert_eq!(cbd.object_blocks().unwrap(), (0..10).collect::<Vec<_>>());
+        assert_eq!(cbd.filter(|_| true), (0..10).collect::<Vec<_>>());
+        assert_eq!(cbd.reclaim_orphans().unwrap(), 0);
+
+        // After reloading it's part of the file, so it's scanned
+        cbd.reload().unwrap();
+        assert_eq!(cbd.written_blocks(), 50_001);
+        assert_eq!(cbd.object_blocks().unwrap().len(), 11);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn in_memory() {
+        let filename = "cabide_in_memory.test";
+        std::fs::File::create(filename).unwrap();
+        let mut file: Cabide<Data> = Cabide::new(filename, None).unwrap();
+        let mut cbd: Cabide<Data> = Cabide::from_bytes(vec![]).unwrap();
+
+        let mut blocks = vec![];
+        for _ in 0..30 {
+            let data = random_data();
+            blocks.push(cbd.write(&data).unwrap());
+            assert_eq!(file.write(&data).unwrap(), *blocks.last().unwrap());
+        }
+        for block in blocks.iter().step_by(3) {
+            cbd.remove(*block).unwrap();
+            file.remove(*block).unwrap();
+        }
+        let objects = cbd.filter(|_| true);
+        assert!(file.into_bytes().is_none());
+
+        // Same layout as the file, so the bytes can be opened either way
+        let bytes = cbd.into_bytes().unwrap();
+        assert_eq!(bytes, std::fs::read(filename).unwrap());
+        let mut cbd: Cabide<Data> = Cabide::from_bytes(bytes).unwrap();
+        assert_eq!(cbd.filter(|_| true), objects);
+
+        let before = cbd.blocks().unwrap();
+        cbd.compact_resumable(4).unwrap();
+        assert!(cbd.blocks().unwrap() < before);
+        assert_eq!(cbd.filter(|_| true), objects);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn max_bytes() {
+        let filename = "cabide_max_bytes.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::builder()
+            .max_bytes(10 * BLOCK_SIZE)
+            .open(filename)
+            .unwrap();
+        cbd.write(&vec![
+            1;
+            3 * CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize
+        ])
+        .unwrap();
+        cbd.write(&vec![
+            2;
+            2 * CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize
+        ])
+        .unwrap();
+        let bytes = std::fs::read(filename).unwrap();
+
+        let obj = vec![3; 6 * CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize];
+        assert!(matches!(
+            cbd.write(&obj),
+            Err(Error::SizeLimitExceeded {
+                needed,
+                max
+            }) if needed == 11 * BLOCK_SIZE && max == 10 * BLOCK_SIZE
+        ));
+        assert!(matches!(
+            cbd.set_prefill(11),
+            Err(Error::SizeLimitExceeded { .. })
+        ));
+        assert_eq!(std::fs::read(filename).unwrap(), bytes);
+
+        // Pre-filled blocks are within the limit, so writing to them doesn't grow the file
+        cbd.set_prefill(10).unwrap();
+        let block = cbd
+            .write(&vec![
+                4;
+                5 * CONTENT_SIZE as usize - 8 - LENGTH_SIZE as usize
+            ])
+            .unwrap();
+        assert_eq!(cbd.blocks().unwrap(), 10);
+        assert!(matches!(
+            cbd.write(&vec![]),
+            Err(Error::SizeLimitExceeded { .. })
+        ));
+        cbd.remove(block).unwrap();
+        assert_eq!(cbd.write(&vec![]).unwrap(), block);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn page() {
+        let filename = "cabide_page.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new(filename, Some(200)).unwrap();
+        let mut blocks = vec![];
+        for _ in 0..40 {
+            blocks.push(cbd.write(&random_data()).unwrap());
+        }
+        for block in blocks.iter().step_by(5) {
+            cbd.remove(*block).unwrap();
+        }
+
+        let filter = |data: &Data| data.that;
+        let (mut pages, mut next) = (vec![], Some(0));
+        while let Some(start) = next {
+            let (page, resume) = cbd.page(filter, start, 4).unwrap();
+            assert!(page.len() <= 4);
+            assert!(resume.map_or(true, |resume| resume > start));
+            pages.extend(page);
+            next = resume;
+        }
+        assert_eq!(pages, cbd.filter_with_blocks(filter));
+        assert_eq!(cbd.page(filter, 0, 0).unwrap(), (vec![], Some(0)));
+        drop(cbd);
+
+        let mut cbd = fragmented(filename);
+        let (mut pages, mut next) = (vec![], Some(0));
+        while let Some(start) = next {
+            let (page, resume) = cbd.page(|_| true, start, 2).unwrap();
+            pages.extend(page);
+            next = resume;
+        }
+        assert_eq!(pages, cbd.filter_with_blocks(|_| true));
+        assert_eq!(cbd.page(|_| true, 0, 100).unwrap().0, pages);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn dump() {
+        let (filename, restored) = ("cabide_dump.test", "cabide_dump_restored.test");
+        std::fs::File::create(filename).unwrap();
+        std::fs::File::create(restored).unwrap();
+        let mut cbd: Cabide<String> = Cabide::new(filename, None).unwrap();
+        let first = cbd.write(&"a".repeat(3 * CONTENT_SIZE as usize)).unwrap();
+        let second = cbd.write(&"b".to_owned()).unwrap();
+        let third = cbd.write(&"c".repeat(CONTENT_SIZE as usize)).unwrap();
+        cbd.remove(second).unwrap();
+
+        let mut dump = vec![];
+        cbd.dump(&mut dump).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        let lines: Vec<_> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(&format!("{}\t4\t\"aaa", first)));
+        assert!(lines[1].starts_with(&format!("{}\t2\t\"ccc", third)));
+
+        let unquote = |debug: &str| {
+            let debug = debug.strip_prefix('"')?.strip_suffix('"')?;
+            Some(debug.to_owned())
+        };
+        let mut other: Cabide<String> = Cabide::new(restored, None).unwrap();
+        assert_eq!(other.restore(dump.as_bytes(), unquote).unwrap(), 2);
+        assert_eq!(other.filter(|_| true), cbd.filter(|_| true));
+
+        assert!(matches!(
+            other.restore(&b"not a dump\n"[..], unquote),
+            Err(Error::CorruptedBlock)
+        ));
+        assert!(matches!(
+            other.restore(&b"0\t1\tunquoted\n"[..], unquote),
+            Err(Error::CorruptedBlock)
+        ));
+        drop(cbd);
+
+        // Objects between the extents of a fragmented one are dumped too
+        let mut cbd = fragmented(filename);
+        let mut dump = vec![];
+        cbd.dump(&mut dump).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        let starts: Vec<(u64, u64)> = dump
+            .lines()
+            .map(|line| {
+                let mut fields = line.split('\t');
+                let block = fields.next().unwrap().parse().unwrap();
+                (block, fields.next().unwrap().parse().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            starts,
+            vec![(0, 1), (1, 3), (2, 1), (4, 1), (6, 1), (8, 1), (9, 1)]
+        );
+        std::fs::remove_file(filename).unwrap();
+        std::fs::remove_file(restored).unwrap();
+    }
+
+    #[test]
+    fn persist_free_blocks() {
+        let (filename, sidecar) = ("cabide_free_blocks.test", "cabide_free_blocks.test.idx");
+        std::fs::File::create(filename).unwrap();
+        let open = || -> Cabide<u8> {
+            Cabide::builder()
+                .persist_free_blocks(true)
+                .open(filename)
+                .unwrap()
+        };
+
+        let mut cbd = open();
+        for i in 0..10_000 {
+            cbd.write(&(i as u8)).unwrap();
+        }
+        for block in (0..9_990).filter(|block| block % 7 < 2 || block % 1000 > 980) {
+            cbd.remove(block).unwrap();
+        }
+        let (empty_blocks, next_block) = (cbd.empty_blocks.clone(), cbd.next_block);
+        drop(cbd);
+        assert!(Path::new(sidecar).is_file());
+
+        // Only the header and the sidecar are read
+        let mut cbd = open();
+        assert!(cbd.file.read_bytes < 1000, "{}", cbd.file.read_bytes);
+        assert_eq!(cbd.empty_blocks, empty_blocks);
+        assert_eq!(cbd.next_block, next_block);
+
+        // First change removes the sidecar, it would be stale if the database isn't dropped
+        let block = cbd.write(&1).unwrap();
+        assert!(!Path::new(sidecar).exists());
+        cbd.flush_index().unwrap();
+        assert!(Path::new(sidecar).is_file());
+        drop(cbd);
+
+        // Handles that don't store the free blocks still remove the sidecar, so the file is scanned
+        let mut other: Cabide<u8> = Cabide::new(filename, None).unwrap();
+        other.remove(block).unwrap();
+        assert!(!Path::new(sidecar).exists());
+        drop(other);
+        let cbd = open();
+        assert!(cbd.file.read_bytes > 10_000 * BLOCK_SIZE / 2);
+        assert_eq!(cbd.empty_blocks, empty_blocks);
+        drop(cbd);
+
+        // Corrupted sidecars are ignored too
+        std::fs::write(sidecar, b"garbage").unwrap();
+        let cbd = open();
+        assert_eq!(cbd.empty_blocks, empty_blocks);
+        drop(cbd);
+        std::fs::remove_file(filename).unwrap();
+        std::fs::remove_file(sidecar).unwrap();
+    }
+
+    #[test]
+    fn iter() {
+        let filename = "cabide_iter.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new(filename, Some(100)).unwrap();
+        let mut objects = vec![];
+        for i in 0..30 {
+            let mut data = random_data();
+            // Some objects span multiple blocks
+            data.there = "a".repeat(i * 3);
+            objects.push((cbd.write(&data).unwrap(), data));
+        }
+        for (block, _) in objects.iter().step_by(4) {
+            cbd.remove(*block).unwrap();
+        }
+        let objects: Vec<_> = objects
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| i % 4 != 0)
+            .map(|(_, obj)| obj)
+            .collect();
+
+        let iterated: Result<Vec<_>, _> = cbd.iter().collect();
+        assert_eq!(iterated.unwrap(), objects);
+        assert_eq!(cbd.iter().count(), objects.len());
+
+        // Errors are yielded, the rest is still iterated
+        let (corrupted, _) = objects[3];
+        cbd.seek_block(corrupted).unwrap();
+        // `that` is a bool, so 0xFF is invalid
+        cbd.file
+            .write_all(&[Metadata::Start as u8, 0, 0xFF])
+            .unwrap();
+        let results: Vec<_> = cbd.iter().collect();
+        assert_eq!(results.len(), objects.len());
+        assert!(matches!(results[3], Err(Error::Serde(_))));
+        assert_eq!(results[4].as_ref().unwrap(), &objects[4]);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn count() {
+        let filename = "cabide_count.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new(filename, Some(500)).unwrap();
+        assert_eq!(cbd.count().unwrap(), 0);
+
+        let mut blocks = vec![];
+        for i in 0..50 {
+            // Up to 4 blocks each
+            blocks.push(
+                cbd.write(&vec![i; i as usize % 4 * CONTENT_SIZE as usize])
+                    .unwrap(),
+            );
+        }
+        assert_eq!(cbd.count().unwrap(), 50);
+        for block in blocks.iter().step_by(3) {
+            cbd.remove(*block).unwrap();
+        }
+        assert_eq!(cbd.count().unwrap(), 33);
+        assert_eq!(cbd.count().unwrap(), cbd.filter(|_| true).len() as u64);
+
+        // Freed blocks are re-used, a smaller object leaves some of them empty
+        cbd.write(&vec![]).unwrap();
+        assert_eq!(cbd.count().unwrap(), 34);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn get() {
+        let filename = "cabide_get.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Data> = Cabide::new(filename, Some(20)).unwrap();
+        let mut data = random_data();
+        data.there = "a".repeat(2 * CONTENT_SIZE as usize);
+        let block = cbd.write(&data).unwrap();
+        let removed = cbd.write(&random_data()).unwrap();
+        cbd.remove(removed).unwrap();
+
+        assert_eq!(cbd.get(block).unwrap(), Some(data));
+        assert_eq!(cbd.get(block + 1).unwrap(), None);
+        assert_eq!(cbd.get(removed).unwrap(), None);
+        // Pre-filled blocks are empty, past them there are no blocks
+        assert_eq!(cbd.get(19).unwrap(), None);
+        assert_eq!(cbd.get(20).unwrap(), None);
+        assert_eq!(cbd.get(u64::MAX).unwrap(), None);
+
+        // Corrupted objects are still errors, `that` is a bool so 0xFF is invalid
+        cbd.seek_block(block).unwrap();
+        cbd.file
+            .write_all(&[Metadata::Start as u8, 0, 0xFF])
+            .unwrap();
+        assert!(matches!(cbd.get(block), Err(Error::Serde(_))));
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn serde_error() {
+        let filename = "cabide_serde_error.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new(filename, None).unwrap();
+        let block = cbd.write(&vec![0xFF, 0xFE]).unwrap();
+        drop(cbd);
+
+        // Same layout as a `String`, but not valid UTF-8
+        let mut cbd: Cabide<String> = Cabide::new(filename, None).unwrap();
+        let err = cbd.read(block).unwrap_err();
+        let message = err.to_string();
+        match err {
+            Error::Serde(ref inner) => {
+                assert!(matches!(
+                    **inner,
+                    bincode::ErrorKind::InvalidUtf8Encoding(_)
+                ));
+                assert!(message.contains(&inner.to_string()));
+            }
+            err => panic!("expected a serde error, got {:?}", err),
+        }
+        assert!(message.contains("utf8"), "{}", message);
+        assert!(std::error::Error::source(&err).is_some());
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn read_raw() {
+        let filename = "cabide_read_raw.test";
+        for sequence_numbers in [false, true] {
+            std::fs::File::create(filename).unwrap();
+            let mut cbd: Cabide<Data> = Cabide::builder()
+                .sequence_numbers(sequence_numbers)
+                .stable_ids(sequence_numbers)
+                .open(filename)
+                .unwrap();
+            let mut blocks = vec![];
+            for i in 0..20 {
+                let mut data = random_data();
+                data.there = "a".repeat(i * 5);
+                blocks.push(cbd.write(&data).unwrap());
+            }
+
+            let last = blocks[19];
+            for block in blocks {
+                let raw = cbd.read_raw(block).unwrap();
+                assert_eq!(raw, serialize(&cbd.read(block).unwrap()).unwrap());
+                let decoded: Data = bincode::deserialize(&raw).unwrap();
+                assert_eq!(decoded, cbd.read(block).unwrap());
+            }
+            assert!(matches!(
+                cbd.read_raw(last + 1),
+                Err(Error::ContinuationBlock { .. })
+            ));
+        }
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn write_at() {
+        let filename = "cabide_write_at.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::builder()
+            .prefill(100)
+            .stable_ids(true)
+            .open(filename)
+            .unwrap();
+        let free_blocks = |cbd: &Cabide<Vec<u8>>| {
+            let mut free: Vec<u64> = cbd
+                .empty_blocks
+                .iter()
+                .flat_map(|(size, starts)| starts.iter().flat_map(move |s| *s..*s + *size as u64))
+                .collect();
+            free.sort_unstable();
+            (free, cbd.next_block)
+        };
+        // Cached state must be the same as the one scanned from the file
+        let assert_consistent = |cbd: &mut Cabide<Vec<u8>>| {
+            let cached = free_blocks(cbd);
+            cbd.reload().unwrap();
+            assert_eq!(free_blocks(cbd), cached);
+        };
+        let two_blocks = vec![1; CONTENT_SIZE as usize];
+
+        // Pre-filled region, the blocks skipped before it become free
+        cbd.write_at(10, &two_blocks).unwrap();
+        assert_eq!(cbd.next_block, 12);
+        assert_eq!(cbd.empty_blocks.get(&10), Some(&vec![0]));
+        assert_consistent(&mut cbd);
+
+        // Middle of a free run, it's split around the object
+        cbd.write_at(4, &two_blocks).unwrap();
+        assert_eq!(free_blocks(&cbd).0, vec![0, 1, 2, 3, 6, 7, 8, 9]);
+        assert_consistent(&mut cbd);
+        cbd.write_at(0, &vec![]).unwrap();
+        cbd.write_at(9, &vec![]).unwrap();
+        assert_eq!(free_blocks(&cbd).0, vec![1, 2, 3, 6, 7, 8]);
+        assert_consistent(&mut cbd);
+        assert_eq!(cbd.write(&two_blocks).unwrap(), 6);
+        assert_eq!(cbd.write(&two_blocks).unwrap(), 1);
+
+        // Blocks of other objects are never overwritten, failing writes change nothing
+        let bytes = std::fs::read(filename).unwrap();
+        for block in [2, 5, 7, 11] {
+            assert!(matches!(
+                cbd.write_at(block, &vec![]),
+                Err(Error::BlockInUse { block: used }) if used == block
+            ));
+        }
+        assert!(matches!(
+            cbd.write_at(3, &two_blocks),
+            Err(Error::BlockInUse { block: 4 })
+        ));
+        assert!(matches!(
+            cbd.write_at(4, &vec![2; 3 * CONTENT_SIZE as usize]),
+            Err(Error::BlockInUse { block: 6 })
+        ));
+        assert_eq!(std::fs::read(filename).unwrap(), bytes);
+
+        // Objects starting at the block are replaced, keeping their stable id
+        let id = cbd.id(4).unwrap();
+        cbd.write_at(4, &vec![3]).unwrap();
+        assert_eq!(cbd.read(4).unwrap(), vec![3]);
+        assert_eq!(cbd.read_by_id(id).unwrap(), vec![3]);
+        assert_eq!(free_blocks(&cbd).0, vec![3, 5, 8]);
+        assert_consistent(&mut cbd);
+        assert_eq!(cbd.count().unwrap(), 6);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let filename = "cabide_shrink_to_fit.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new(filename, Some(1000)).unwrap();
+        let mut blocks = vec![];
+        for i in 0..5 {
+            blocks.push(cbd.write(&vec![i; CONTENT_SIZE as usize]).unwrap());
+        }
+        assert_eq!(cbd.shrink_to_fit().unwrap(), 10);
+        assert_eq!(std::fs::metadata(filename).unwrap().len(), 10 * BLOCK_SIZE);
+
+        // Only the empty blocks after the last object are dropped
+        cbd.remove(blocks[1]).unwrap();
+        cbd.remove(blocks[3]).unwrap();
+        cbd.remove(blocks[4]).unwrap();
+        assert_eq!(cbd.shrink_to_fit().unwrap(), 6);
+        assert_eq!(std::fs::metadata(filename).unwrap().len(), 6 * BLOCK_SIZE);
+        assert_eq!(cbd.empty_blocks.get(&2), Some(&vec![blocks[1]]));
+        assert_eq!(cbd.empty_blocks.len(), 1);
+        assert_eq!(cbd.read(blocks[2]).unwrap(), vec![2; CONTENT_SIZE as usize]);
+        assert_eq!(cbd.write(&vec![5; 100]).unwrap(), 6);
+
+        cbd.truncate().unwrap();
+        assert_eq!(cbd.shrink_to_fit().unwrap(), 0);
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn read_only() {
+        let filename = "cabide_read_only.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new(filename, None).unwrap();
+        let mut blocks = vec![];
+        for i in 0..5 {
+            blocks.push(cbd.write(&vec![i; CONTENT_SIZE as usize]).unwrap());
+        }
+        cbd.remove(blocks[1]).unwrap();
+        let (next_block, empty_blocks) = (cbd.next_block, cbd.empty_blocks.clone());
+        drop(cbd);
+        let len = std::fs::metadata(filename).unwrap().len();
+
+        let mut permissions = std::fs::metadata(filename).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(filename, permissions.clone()).unwrap();
+
+        let mut cbd: Cabide<Vec<u8>> = Cabide::open_read_only(filename).unwrap();
+        assert_eq!(cbd.next_block, next_block);
+        assert_eq!(cbd.empty_blocks, empty_blocks);
+        assert_eq!(cbd.read(blocks[2]).unwrap(), vec![2; CONTENT_SIZE as usize]);
+        assert_eq!(cbd.filter(|_| true).len(), 4);
+
+        let obj = vec![9; 1];
+        assert!(matches!(cbd.write(&obj), Err(Error::ReadOnly)));
+        assert!(matches!(
+            cbd.write_at(blocks[1], &obj),
+            Err(Error::ReadOnly)
+        ));
+        assert!(matches!(cbd.remove(blocks[0]), Err(Error::ReadOnly)));
+        assert!(matches!(cbd.update(blocks[2], &obj), Err(Error::ReadOnly)));
+        assert!(matches!(cbd.truncate(), Err(Error::ReadOnly)));
+        assert!(matches!(cbd.set_prefill(100), Err(Error::ReadOnly)));
+        assert!(matches!(cbd.remove_with(|_| true), Err(Error::ReadOnly)));
+        assert!(matches!(cbd.drain().map(|_| ()), Err(Error::ReadOnly)));
+
+        // Failed changes left the state and the file as they were
+        assert_eq!(cbd.empty_blocks, empty_blocks);
+        assert_eq!(cbd.read(blocks[0]).unwrap(), vec![0; CONTENT_SIZE as usize]);
+        assert_eq!(cbd.read(blocks[2]).unwrap(), vec![2; CONTENT_SIZE as usize]);
+        drop(cbd);
+        assert_eq!(std::fs::metadata(filename).unwrap().len(), len);
+
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(filename, permissions).unwrap();
+        std::fs::remove_file(filename).unwrap();
+
+        let res = Cabide::<u8>::open_read_only(filename);
+        assert!(matches!(res, Err(Error::NotExistant)));
+        assert!(std::fs::metadata(filename).is_err());
+    }
+
+    #[test]
+    fn read_cache() {
+        let filename = "cabide_read_cache.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: Cabide<String> = Cabide::builder().read_cache(2).open(filename).unwrap();
+        let blocks: Vec<u64> = (0..3).map(|i| cbd.write(&i.to_string()).unwrap()).collect();
+
+        let first = cbd.read_shared(blocks[0]).unwrap();
+        let (read_bytes, read_calls) = (cbd.file.read_bytes, cbd.file.read_calls);
+        let again = cbd.read_shared(blocks[0]).unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        assert_eq!(
+            (cbd.file.read_bytes, cbd.file.read_calls),
+            (read_bytes, read_calls)
+        );
+
+        // Changes drop the object, even if it stays in the same block
+        assert_eq!(
+            cbd.update(blocks[0], &"updated".to_owned()).unwrap(),
+            blocks[0]
+        );
+        assert_eq!(*cbd.read_shared(blocks[0]).unwrap(), "updated");
+        assert_eq!(*first, "0");
+        cbd.read_shared(blocks[1]).unwrap();
+        cbd.remove(blocks[1]).unwrap();
+        assert!(matches!(
+            cbd.read_shared(blocks[1]),
+            Err(Error::EmptyBlock { .. })
+        ));
+        assert_eq!(cbd.write(&"reused".to_owned()).unwrap(), blocks[1]);
+        assert_eq!(*cbd.read_shared(blocks[1]).unwrap(), "reused");
+
+        // Least recently read object is evicted, `blocks[0]` was read before `blocks[1]`
+        cbd.read_shared(blocks[2]).unwrap();
+        let read_bytes = cbd.file.read_bytes;
+        cbd.read_shared(blocks[1]).unwrap();
+        cbd.read_shared(blocks[2]).unwrap();
+        assert_eq!(cbd.file.read_bytes, read_bytes);
+        cbd.read_shared(blocks[0]).unwrap();
+        assert!(cbd.file.read_bytes > read_bytes);
+
+        cbd.truncate().unwrap();
+        assert!(cbd.read_shared(blocks[0]).is_err());
+        std::fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn crc32() {
+        assert_eq!(super::crc32(0, b""), 0);
+        assert_eq!(super::crc32(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(
+            super::crc32(super::crc32(0, b"1234"), b"56789"),
+            0xCBF4_3926
+        );
+    }
+
+    #[test]
+    fn checksums() {
+        let filename = "cabide_checksums.test";
+        for checksums in [false, true] {
+            std::fs::File::create(filename).unwrap();
+            let mut cbd: Cabide<Data> = Cabide::builder()
+                .checksums(checksums)
+                .sequence_numbers(true)
+                .open(filename)
+                .unwrap();
+            let mut objects = vec![];
+            for _ in 0..10 {
+                let data = random_data();
+                objects.push((cbd.write(&data).unwrap(), data));
+            }
+            drop(cbd);
+
+            // Flips a byte of `there` in a continuation block
+            let (block, data) = &objects[4];
+            let position = (*block + 1) * BLOCK_SIZE + 10;
+            let mut bytes = std::fs::read(filename).unwrap();
+            bytes[position as usize] ^= 0b10;
+            std::fs::write(filename, bytes).unwrap();
+
+            let mut cbd: Cabide<Data> = Cabide::builder()
+                .checksums(checksums)
+                .sequence_numbers(true)
+                .quarantine_on_corruption(true)
+                .open(filename)
+                .unwrap();
+            if checksums {
+                assert!(matches!(
+                    cbd.read(*block),
+                    Err(Error::ChecksumMismatch { block: corrupted }) if corrupted == *block
+                ));
+                assert!(matches!(
+                    cbd.read_raw(*block),
+                    Err(Error::ChecksumMismatch { .. })
+                ));
+                assert_eq!(cbd.filter(|_| true).len(), 9);
+                assert_eq!(cbd.quarantined_blocks(), &[*block]);
+            } else {
+                // Without checksums a wrong object is returned
+                let read = cbd.read(*block).unwrap();
+                assert_ne!(&read, data);
+                assert_eq!(read.those, data.those);
+            }
+            for (block, data) in objects.iter().filter(|(other, _)| other != block) {
+                assert_eq!(&cbd.read(*block).unwrap(), data);
+            }
+        }
+        std::fs::remove_file(filename).unwrap();
     }
 }
@@ -42,6 +42,38 @@ where
     pub fn blocks(&self) -> Result<u64, Error> {
         Ok(self.unordered_buffer.blocks()? + self.main.0.blocks()?)
     }
+
+    /// Returns number of blocks of the sorted file, objects that are still buffered aren't there
+    #[inline]
+    pub fn main_blocks(&self) -> Result<u64, Error> {
+        self.main.0.blocks()
+    }
+
+    /// Waits for the contents of the buffer, the sorted file and the temporary file to reach the disk, see
+    /// [`Cabide::sync_data`]
+    #[inline]
+    pub fn sync_data(&mut self) -> Result<(), Error> {
+        self.unordered_buffer.sync_data()?;
+        self.main.0.sync_data()?;
+        self.sort_temp.0.sync_data()
+    }
+
+    /// Same as `sync_data`, but also waits for the metadata of the files, see [`Cabide::sync_all`]
+    #[inline]
+    pub fn sync_all(&mut self) -> Result<(), Error> {
+        self.unordered_buffer.sync_all()?;
+        self.main.0.sync_all()?;
+        self.sort_temp.0.sync_all()
+    }
+
+    /// Waits for every change to the buffer, the sorted file and the temporary file to reach the disk, see
+    /// [`Cabide::flush`]
+    #[inline]
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.unordered_buffer.flush()?;
+        self.main.0.flush()?;
+        self.sort_temp.0.flush()
+    }
 }
 
 impl<T, F, G, OrderField> OrderCabide<T, F, G, OrderField>
@@ -50,36 +82,139 @@ where
     F: Fn(&T) -> OrderField,
     G: Fn(&OrderField, &OrderField) -> Ordering,
 {
+    /// Creates an ordered database with every object of `existing`, the objects are read once, sorted and
+    /// written straight to the sorted file, instead of being merged buffer by buffer
+    ///
+    /// The sorted file is overwritten, objects in the buffer file are kept there, fails without touching
+    /// it if any object of `existing` can't be read
+    pub fn from_cabide(
+        mut existing: Cabide<T>,
+        buffer: impl AsRef<Path>,
+        main: impl Into<PathBuf>,
+        sort_temp: impl Into<PathBuf>,
+        extract_order_field: F,
+        order_function: G,
+    ) -> Result<Self, Error> {
+        let mut cbd = Self::new(buffer, main, sort_temp, extract_order_field, order_function)?;
+        let objs = existing
+            .iter()
+            .map(|res| res.map(|(_, obj)| obj))
+            .collect::<Result<Vec<T>, Error>>()?;
+        drop(existing);
+        let objs = cbd.sort(objs)?;
+
+        cbd.main.0.truncate()?;
+        for obj in objs {
+            cbd.main.0.write(&obj)?;
+        }
+        Ok(cbd)
+    }
+
     #[inline]
     pub fn write(&mut self, obj: &T) -> Result<(), Error> {
         self.unordered_buffer.write(obj)?;
 
         if self.unordered_buffer.blocks()? >= BUFFER_MAX_BLOCKS {
-            let mut main = self.main.0.filter(|_| true);
-            main.extend(self.unordered_buffer.filter(|_| true));
-            main.sort_by(|t1, t2| {
-                let f1 = (self.extract_order_field)(t1);
-                let f2 = (self.extract_order_field)(t2);
-                (self.order_function)(&f1, &f2)
-            });
-
-            self.sort_temp.0.truncate()?;
-            for obj in main {
-                self.sort_temp.0.write(&obj)?;
+            self.flush_buffer()?;
+        }
+        Ok(())
+    }
+
+    /// Merges the unordered buffer into the sorted file, regardless of the buffer's size
+    ///
+    /// Queries binary search the sorted file, but scan the buffer, so flushing it before a batch of them
+    /// makes them faster
+    pub fn flush_buffer(&mut self) -> Result<(), Error> {
+        let mut main = self.main.0.filter(|_| true);
+        main.extend(self.unordered_buffer.filter(|_| true));
+        let main = self.sort(main)?;
+
+        self.sort_temp.0.truncate()?;
+        for obj in main {
+            self.sort_temp.0.write(&obj)?;
+        }
+
+        fs::copy(&self.sort_temp.1, &self.main.1)?;
+        // Main file was replaced behind its handle
+        self.main.0.reload()?;
+        self.unordered_buffer.truncate()?;
+        self.sort_temp.0.truncate()?;
+        Ok(())
+    }
+
+    /// Sorts objects by their order field, failing if the order function couldn't sort them
+    fn sort(&self, objs: Vec<T>) -> Result<Vec<T>, Error> {
+        let sorted = merge_sort(objs, &|t1: &T, t2: &T| {
+            let f1 = (self.extract_order_field)(t1);
+            let f2 = (self.extract_order_field)(t2);
+            (self.order_function)(&f1, &f2)
+        });
+        self.check_sorted(&sorted)?;
+        Ok(sorted)
+    }
+
+    /// Makes sure sorted data is actually in order, since comparators that aren't total (like floats
+    /// with NaN) leave it in an order that can't be binary searched
+    ///
+    /// A field equal to its neighbours could be equal to everything (like a NaN), so runs of equal fields
+    /// must compare the same as their first field against the next one, and stay after the previous run
+    fn check_sorted(&self, sorted: &[T]) -> Result<(), Error> {
+        let field = |index: usize| (self.extract_order_field)(&sorted[index]);
+        let (mut run_start, mut previous_run) = (0, None);
+        for index in 0..sorted.len() {
+            let current = field(index);
+            if (self.order_function)(&current, &current) != Ordering::Equal {
+                return Err(Error::InvalidOrdering);
+            }
+            if index == 0 {
+                continue;
             }
 
-            fs::copy(&self.sort_temp.1, &self.main.1)?;
-            self.unordered_buffer.truncate()?;
-            self.sort_temp.0.truncate()?;
+            let ordering = (self.order_function)(&field(index - 1), &current);
+            if ordering != (self.order_function)(&field(run_start), &current) {
+                return Err(Error::InvalidOrdering);
+            }
+            match ordering {
+                Ordering::Greater => return Err(Error::InvalidOrdering),
+                Ordering::Less => {
+                    previous_run = Some(run_start);
+                    run_start = index;
+                }
+                Ordering::Equal => {
+                    let after_previous = previous_run.map_or(true, |start| {
+                        (self.order_function)(&field(start), &current) == Ordering::Less
+                    });
+                    if !after_previous {
+                        return Err(Error::InvalidOrdering);
+                    }
+                }
+            }
         }
         Ok(())
     }
 }
 
-#[derive(PartialEq)]
-enum Going {
-    Left,
-    Right,
+/// Stable merge sort, unlike `slice::sort_by` it doesn't panic if `compare` isn't a total order, it only
+/// leaves the objects out of order, so `check_sorted` can report it
+fn merge_sort<T>(mut objs: Vec<T>, compare: &impl Fn(&T, &T) -> Ordering) -> Vec<T> {
+    if objs.len() <= 1 {
+        return objs;
+    }
+    let right = objs.split_off(objs.len() / 2);
+    let (left, right) = (merge_sort(objs, compare), merge_sort(right, compare));
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        let next = match compare(r, l) {
+            Ordering::Less => right.next(),
+            Ordering::Equal | Ordering::Greater => left.next(),
+        };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    merged
 }
 
 impl<T, F, G, OrderField> OrderCabide<T, F, G, OrderField>
@@ -88,6 +223,11 @@ where
     F: Fn(&T) -> OrderField,
     G: Fn(&OrderField, &OrderField) -> Ordering,
 {
+    /// Returns an object with a field that `order_by` considers `Ordering::Equal`, if any
+    ///
+    /// The sorted file is binary searched, so `order_by` must agree with `order_function`, returning
+    /// `Ordering::Less` for fields sorted before the wanted ones (any lexicographic compare of a composite
+    /// field, like a tuple, works)
     pub fn first(&mut self, order_by: impl Fn(&OrderField) -> Ordering) -> Option<T> {
         let (unordered_buffer, extract_order_field) =
             (&mut self.unordered_buffer, &self.extract_order_field);
@@ -95,54 +235,108 @@ where
             .first(|data| order_by(&(extract_order_field)(data)) == Ordering::Equal)
             .or_else(|| {
                 let blocks = self.main.0.blocks().ok()?;
-                let mut block = blocks / 2;
-                let mut has_found_something = false;
-                let mut going = Going::Right;
-                loop {
-                    if let Ok(data) = self.main.0.read(block) {
-                        has_found_something = true;
-                        match order_by(&(self.extract_order_field)(&data)) {
-                            Ordering::Equal => return Some(data),
-                            Ordering::Less => {
-                                going = Going::Right;
-                                if block == blocks {
-                                    return None;
-                                } else {
-                                    let missing = blocks - block;
-                                    block = block.saturating_add(missing / 2);
-                                }
-                            }
-                            Ordering::Greater => {
-                                going = Going::Left;
-                                if block == 0 {
-                                    return None;
-                                } else {
-                                    block = block.saturating_sub(block / 2);
-                                }
-                            }
-                        }
-                    } else if going == Going::Left {
-                        if block == 0 {
-                            return None;
-                        } else {
-                            block = block.saturating_sub(1);
-                        }
-                    } else {
-                        if block == blocks {
-                            if has_found_something {
-                                return None;
-                            } else {
-                                going = Going::Left;
-                                block = blocks / 2;
-                            }
-                        } else {
-                            block = block.saturating_add(1);
-                        }
-                    }
-                }
+                let block = self.lower_bound(&order_by, blocks);
+                self.next_sorted(block, blocks)
+                    .map(|(_, data)| data)
+                    .filter(|data| order_by(&(self.extract_order_field)(data)) == Ordering::Equal)
             })
     }
 
+    /// Returns object starting at specified block of the sorted file, see [`Cabide::read`]
+    ///
+    /// Objects are sorted by their block, so neighbors of an object can be read directly
+    #[inline]
+    pub fn main_read(&mut self, block: u64) -> Result<T, Error> {
+        self.main.0.read(block)
+    }
+
+    /// Returns first object (and its starting block) of the sorted file that starts at or after `block`
+    fn next_sorted(&mut self, mut block: u64, blocks: u64) -> Option<(u64, T)> {
+        while block < blocks {
+            if let Ok(data) = self.main.0.read(block) {
+                return Some((block, data));
+            }
+            block += 1;
+        }
+        None
+    }
+
+    /// Returns the first block of the sorted file that may hold an object not before `order_by`'s range
+    fn lower_bound(&mut self, order_by: impl Fn(&OrderField) -> Ordering, blocks: u64) -> u64 {
+        let (mut low, mut high) = (0, blocks);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.next_sorted(middle, high) {
+                Some((block, data))
+                    if order_by(&(self.extract_order_field)(&data)) == Ordering::Less =>
+                {
+                    low = block + 1
+                }
+                _ => high = middle,
+            }
+        }
+        low
+    }
+
+    /// Returns every object in the range described by `order_by`, which must return `Ordering::Less` for
+    /// fields before the range, `Ordering::Equal` inside of it and `Ordering::Greater` after it
+    ///
+    /// Binary searches the sorted file for the start of the range, then scans forward while inside it,
+    /// so it's useful for prefix queries (the unordered buffer is still scanned linearly)
+    ///
+    /// ```rust
+    /// use cabide::OrderCabide;
+    /// use std::cmp::Ordering;
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # for file in &["test16.buffer", "test16.main", "test16.temp"] {
+    /// #     std::fs::File::create(file)?;
+    /// # }
+    /// let mut cbd = OrderCabide::new(
+    ///     "test16.buffer",
+    ///     "test16.main",
+    ///     "test16.temp",
+    ///     |name: &String| name.clone(),
+    ///     Ord::cmp,
+    /// )?;
+    ///
+    /// for name in &["Bob", "Arthur", "Alice", "Ariel", "Armando"] {
+    ///     cbd.write(&name.to_string())?;
+    /// }
+    ///
+    /// let mut names = cbd.prefix(|name| {
+    ///     if name.starts_with("Ar") {
+    ///         Ordering::Equal
+    ///     } else {
+    ///         name.as_str().cmp("Ar")
+    ///     }
+    /// });
+    /// names.sort();
+    /// assert_eq!(names, vec!["Ariel", "Armando", "Arthur"]);
+    /// # for file in &["test16.buffer", "test16.main", "test16.temp"] {
+    /// #     std::fs::remove_file(file)?;
+    /// # }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prefix(&mut self, order_by: impl Fn(&OrderField) -> Ordering) -> Vec<T> {
+        let (unordered_buffer, extract_order_field) =
+            (&mut self.unordered_buffer, &self.extract_order_field);
+        let mut vec = unordered_buffer
+            .filter(|data| order_by(&extract_order_field(data)) == Ordering::Equal);
+
+        let blocks = self.main.0.blocks().unwrap_or(0);
+        let mut low = self.lower_bound(&order_by, blocks);
+        while let Some((block, data)) = self.next_sorted(low, blocks) {
+            if order_by(&(self.extract_order_field)(&data)) != Ordering::Equal {
+                break;
+            }
+            vec.push(data);
+            low = block + 1;
+        }
+        vec
+    }
+
     pub fn filter(&mut self, order_by: impl Fn(&OrderField) -> Ordering) -> Vec<T> {
         let (unordered_buffer, extract_order_field) =
             (&mut self.unordered_buffer, &self.extract_order_field);
@@ -152,15 +346,336 @@ where
         vec
     }
 
-    pub fn filter_any(&mut self, filter: impl Fn(&T) -> bool) -> Vec<T> {
-        let mut vec = self.unordered_buffer.filter(&filter);
+    pub fn filter_any(&mut self, mut filter: impl FnMut(&T) -> bool) -> Vec<T> {
+        let mut vec = self.unordered_buffer.filter(&mut filter);
         vec.extend(self.main.0.filter(filter));
         vec
     }
 
-    pub fn remove(&mut self, filter: impl Fn(&T) -> bool) -> Vec<T> {
-        let mut vec = self.unordered_buffer.remove_with(&filter);
-        vec.extend(self.main.0.remove_with(filter));
-        vec
+    pub fn remove(&mut self, mut filter: impl FnMut(&T) -> bool) -> Result<Vec<T>, Error> {
+        let mut vec = self.unordered_buffer.remove_with(&mut filter)?;
+        vec.extend(self.main.0.remove_with(filter)?);
+        Ok(vec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+    use serde::{Deserialize, Serialize};
+    use std::io::{Seek, SeekFrom, Write};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Student {
+        name: String,
+        dre: u64,
+    }
+
+    const NAMES: &[&str] = &[
+        "Arthur", "Bianca", "Ariel", "Carlos", "Armando", "Alice", "Aaron", "Artur", "Beatriz",
+        "Ar", "Zoe", "Amanda", "Arnaldo", "B",
+    ];
+
+    fn students() -> Vec<Student> {
+        (0..290)
+            .map(|dre| Student {
+                name: NAMES[(dre * 7) as usize % NAMES.len()].to_owned(),
+                dre,
+            })
+            .collect()
+    }
+
+    fn files(name: &str) -> (String, String, String) {
+        let files = (
+            format!("order_{}.buffer.test", name),
+            format!("order_{}.main.test", name),
+            format!("order_{}.temp.test", name),
+        );
+        for file in &[&files.0, &files.1, &files.2] {
+            fs::File::create(file).unwrap();
+        }
+        files
+    }
+
+    fn remove_files((buffer, main, temp): (String, String, String)) {
+        for file in &[buffer, main, temp] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn prefix() {
+        let (buffer, main, temp) = files("prefix");
+        let mut cbd = OrderCabide::new(
+            &buffer,
+            &main,
+            &temp,
+            |student: &Student| student.name.clone(),
+            Ord::cmp,
+        )
+        .unwrap();
+
+        let students = students();
+        for student in &students {
+            cbd.write(student).unwrap();
+        }
+        // Makes sure most of the data was sorted
+        assert!(cbd.main.0.blocks().unwrap() > 0);
+
+        let mut found = cbd.prefix(|name| {
+            if name.starts_with("Ar") {
+                Ordering::Equal
+            } else {
+                name.as_str().cmp("Ar")
+            }
+        });
+        found.sort_by_key(|student| student.dre);
+
+        let expected: Vec<Student> = students
+            .into_iter()
+            .filter(|student| student.name.starts_with("Ar"))
+            .collect();
+        assert_eq!(found, expected);
+        remove_files((buffer, main, temp));
+    }
+
+    #[test]
+    fn main_read() {
+        let (buffer, main, temp) = files("main_read");
+        let mut cbd = OrderCabide::new(
+            &buffer,
+            &main,
+            &temp,
+            |student: &Student| student.name.clone(),
+            Ord::cmp,
+        )
+        .unwrap();
+
+        for student in &students() {
+            cbd.write(student).unwrap();
+        }
+        let blocks = cbd.main_blocks().unwrap();
+        assert!(blocks > 0);
+        assert!(blocks < cbd.blocks().unwrap());
+
+        // Blocks in the middle of an object can't be read, the rest must be in order
+        let mut sorted: Vec<Student> = vec![];
+        for block in 0..blocks {
+            match cbd.main_read(block) {
+                Ok(student) => sorted.push(student),
+                Err(Error::ContinuationBlock { .. }) => continue,
+                Err(err) => panic!("{}", err),
+            }
+        }
+        assert!(sorted.windows(2).all(|pair| pair[0].name <= pair[1].name));
+        let buffered = cbd.unordered_buffer.filter(|_| true);
+        assert_eq!(sorted.len() + buffered.len(), students().len());
+        remove_files((buffer, main, temp));
+    }
+
+    #[test]
+    fn flush_buffer() {
+        let (buffer, main, temp) = files("flush_buffer");
+        let mut cbd = OrderCabide::new(
+            &buffer,
+            &main,
+            &temp,
+            |student: &Student| student.dre,
+            Ord::cmp,
+        )
+        .unwrap();
+
+        for dre in &[5, 3, 9, 1] {
+            cbd.write(&Student {
+                name: NAMES[*dre as usize].to_owned(),
+                dre: *dre,
+            })
+            .unwrap();
+        }
+        assert_eq!(cbd.main_blocks().unwrap(), 0);
+
+        cbd.flush_buffer().unwrap();
+        assert_eq!(cbd.unordered_buffer.blocks().unwrap(), 0);
+        let dres: Vec<u64> = cbd.main.0.filter(|_| true).iter().map(|s| s.dre).collect();
+        assert_eq!(dres, vec![1, 3, 5, 9]);
+        assert_eq!(cbd.first(|dre| dre.cmp(&9)).unwrap().name, NAMES[9]);
+        remove_files((buffer, main, temp));
+    }
+
+    #[test]
+    fn sync() {
+        let (buffer, main, temp) = files("sync");
+        let order = |student: &Student| student.dre;
+        let mut cbd = OrderCabide::new(&buffer, &main, &temp, order, Ord::cmp).unwrap();
+        let students = students();
+        for student in &students {
+            cbd.write(student).unwrap();
+        }
+        // Some objects are sorted, the rest are still buffered
+        assert!(cbd.main_blocks().unwrap() > 0);
+        assert!(cbd.main_blocks().unwrap() < cbd.blocks().unwrap());
+        cbd.sync_all().unwrap();
+        cbd.sync_data().unwrap();
+        cbd.flush().unwrap();
+
+        // this drops the last OrderCabide, therefore closes the files
+        cbd = OrderCabide::new(&buffer, &main, &temp, order, Ord::cmp).unwrap();
+        let mut found = cbd.filter_any(|_| true);
+        found.sort_by_key(|student| student.dre);
+        assert_eq!(found, students);
+        remove_files((buffer, main, temp));
+    }
+
+    #[test]
+    fn from_cabide() {
+        let (buffer, main, temp) = files("from_cabide");
+        let heap_file = "order_from_cabide.heap.test";
+        fs::File::create(heap_file).unwrap();
+        let mut heap = Cabide::new(heap_file, None).unwrap();
+        let students: Vec<Student> = (0..1000)
+            .map(|dre| Student {
+                name: NAMES[(dre * 7) as usize % NAMES.len()].to_owned(),
+                dre: (dre * 389) % 1000,
+            })
+            .collect();
+        for student in &students {
+            heap.write(student).unwrap();
+        }
+
+        let mut cbd = OrderCabide::from_cabide(
+            heap,
+            &buffer,
+            &main,
+            &temp,
+            |student: &Student| student.dre,
+            Ord::cmp,
+        )
+        .unwrap();
+        // Nothing went through the buffer
+        assert_eq!(cbd.unordered_buffer.blocks().unwrap(), 0);
+        assert_eq!(cbd.blocks().unwrap(), cbd.main_blocks().unwrap());
+
+        let sorted = cbd.main.0.filter(|_| true);
+        assert_eq!(sorted.len(), 1000);
+        assert!(sorted.windows(2).all(|pair| pair[0].dre < pair[1].dre));
+        assert_eq!(cbd.first(|dre| dre.cmp(&389)).unwrap(), students[1]);
+        let expected: Vec<Student> = students.into_iter().filter(|student| student.dre == 500).collect();
+        assert_eq!(cbd.filter(|dre| dre.cmp(&500)), expected);
+
+        // Objects that can't be read aren't silently dropped
+        fs::File::create(heap_file).unwrap();
+        let mut heap = Cabide::new(heap_file, None).unwrap();
+        for dre in 0..300 {
+            heap.write(&Student {
+                name: "Ar".to_owned(),
+                dre,
+            })
+            .unwrap();
+        }
+        let start = heap.block_start(10).unwrap();
+        heap.file.seek(SeekFrom::Start(start)).unwrap();
+        // The name's length doesn't fit in the file
+        heap.file.write_all(&[Metadata::Start as u8]).unwrap();
+        heap.file.write_all(&[0xFF; 8]).unwrap();
+        let result = OrderCabide::from_cabide(
+            heap,
+            &buffer,
+            &main,
+            &temp,
+            |student: &Student| student.dre,
+            Ord::cmp,
+        );
+        assert!(result.is_err());
+
+        fs::remove_file(heap_file).unwrap();
+        remove_files((buffer, main, temp));
+    }
+
+    #[test]
+    fn composite_ordering() {
+        let (buffer, main, temp) = files("composite");
+        let mut cbd = OrderCabide::new(
+            &buffer,
+            &main,
+            &temp,
+            |student: &Student| (student.name.clone(), student.dre),
+            Ord::cmp,
+        )
+        .unwrap();
+
+        let students = students();
+        for student in &students {
+            cbd.write(student).unwrap();
+        }
+        assert!(cbd.main.0.blocks().unwrap() > 0);
+
+        for student in &students {
+            let found = cbd
+                .first(|(name, dre)| name.as_str().cmp(&student.name).then(dre.cmp(&student.dre)));
+            assert_eq!(found.as_ref(), Some(student));
+        }
+        assert_eq!(cbd.first(|(name, _)| name.as_str().cmp("Bob")), None);
+        assert_eq!(
+            cbd.first(|(name, dre)| name.as_str().cmp("Zoe").then(dre.cmp(&1))),
+            None
+        );
+
+        let mut arthurs = cbd.prefix(|(name, _)| name.as_str().cmp("Arthur"));
+        arthurs.sort_by_key(|student| student.dre);
+        let expected: Vec<Student> = students
+            .into_iter()
+            .filter(|student| student.name == "Arthur")
+            .collect();
+        assert_eq!(arthurs, expected);
+        remove_files((buffer, main, temp));
+    }
+
+    #[test]
+    fn nan_ordering() {
+        let (buffer, main, temp) = files("nan");
+        let mut cbd = OrderCabide::new(
+            &buffer,
+            &main,
+            &temp,
+            |value: &f64| *value,
+            |a: &f64, b: &f64| a.partial_cmp(b).unwrap_or(Ordering::Equal),
+        )
+        .unwrap();
+
+        let mut result = Ok(());
+        for i in 0..BUFFER_MAX_BLOCKS {
+            let value = if i % 10 == 0 { f64::NAN } else { (i * 37 % 101) as f64 };
+            result = cbd.write(&value);
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(matches!(result, Err(Error::InvalidOrdering)));
+        // Nothing was sorted, so the sorted file can't be corrupted
+        assert_eq!(cbd.main.0.blocks().unwrap(), 0);
+        drop(cbd);
+
+        // A single NaN is equal to every neighbour, wherever it's written
+        for position in [0, 57, BUFFER_MAX_BLOCKS - 1] {
+            let (buffer, main, temp) = files("nan");
+            let mut cbd = OrderCabide::new(
+                &buffer,
+                &main,
+                &temp,
+                |value: &f64| *value,
+                |a: &f64, b: &f64| a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            )
+            .unwrap();
+
+            for i in 0..BUFFER_MAX_BLOCKS - 1 {
+                let value = if i == position { f64::NAN } else { (i * 37 % 101) as f64 };
+                cbd.write(&value).unwrap();
+            }
+            let value = if position == BUFFER_MAX_BLOCKS - 1 { f64::NAN } else { 1.0 };
+            assert!(matches!(cbd.write(&value), Err(Error::InvalidOrdering)));
+            assert_eq!(cbd.main.0.blocks().unwrap(), 0);
+        }
+        remove_files((buffer, main, temp));
     }
 }
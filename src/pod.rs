@@ -0,0 +1,214 @@
+use crate::protocol::Metadata;
+use crate::{Error, READ_BLOCKS_COUNT};
+use bytemuck::Pod;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::atomic::Ordering;
+use std::{fs::File, fs::OpenOptions, marker::PhantomData, mem::size_of, path::Path};
+
+/// Typed database of fixed size plain old data, binded to a specific file
+///
+/// Each object is stored in its own slot, a metadata byte followed by the object's bytes, so they are read
+/// straight into memory without (de)serializing them, slots are never split or merged
+///
+/// The object's bytes depend on the platform's endianness, so the file isn't portable
+///
+/// ```rust
+/// use bytemuck::{Pod, Zeroable};
+/// use cabide::PodCabide;
+///
+/// #[repr(C)]
+/// #[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+/// struct Point {
+///     x: f32,
+///     y: f32,
+/// }
+///
+/// # fn main() -> Result<(), cabide::Error> {
+/// # std::fs::File::create("test20.file")?;
+/// let mut cbd: PodCabide<Point> = PodCabide::new("test20.file", None)?;
+///
+/// let slot = cbd.write(&Point { x: 1., y: 2. })?;
+/// assert_eq!(cbd.read(slot)?, Point { x: 1., y: 2. });
+///
+/// cbd.remove(slot)?;
+/// assert_eq!(cbd.write(&Point { x: 3., y: 4. })?, slot);
+/// # std::fs::remove_file("test20.file")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PodCabide<T: Pod> {
+    /// File which typed database is binded to
+    file: File,
+    /// Caches number of next slot after the last used one
+    next_slot: u64,
+    /// Empty slots before `next_slot`
+    empty_slots: Vec<u64>,
+    /// Marks that database must contain a single type
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> PodCabide<T> {
+    /// Size in bytes of each slot, the object and its metadata byte
+    const SLOT_SIZE: u64 = 1 + size_of::<T>() as u64;
+
+    /// Binds database to specified file, creating it if non existent
+    ///
+    /// Pads file to have specified number of slots, pre-filling it
+    pub fn new<P>(filename: P, slots: Option<u64>) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .open(&filename)?;
+        let mut cabide = Self {
+            file,
+            next_slot: 0,
+            empty_slots: vec![],
+            _marker: PhantomData,
+        };
+
+        let current = cabide.slots()?;
+        if let Some(slots) = slots.filter(|slots| *slots > current) {
+            // Zeroed slots are empty, since `Metadata::Empty` is 0
+            cabide.file.set_len(slots * Self::SLOT_SIZE)?;
+        }
+
+        // Finds empty slots, the ones after the last object are written to by appending
+        let mut metadata = [0];
+        for slot in 0..current {
+            cabide.seek_slot(slot)?;
+            cabide.file.read_exact(&mut metadata)?;
+            if metadata[0] == Metadata::Start as u8 {
+                cabide.empty_slots.extend(cabide.next_slot..slot);
+                cabide.next_slot = slot + 1;
+            }
+        }
+        Ok(cabide)
+    }
+
+    /// Returns number of slots in the file (some may be empty)
+    #[inline]
+    pub fn slots(&self) -> Result<u64, Error> {
+        Ok(self.file.metadata()?.len() / Self::SLOT_SIZE)
+    }
+
+    /// Moves the file cursor to the start of specified slot
+    #[inline]
+    fn seek_slot(&mut self, slot: u64) -> Result<(), Error> {
+        self.file.seek(SeekFrom::Start(slot * Self::SLOT_SIZE))?;
+        Ok(())
+    }
+
+    /// Writes object to database, re-using empty slots, returns its slot
+    pub fn write(&mut self, obj: &T) -> Result<u64, Error> {
+        let slot = match self.empty_slots.pop() {
+            Some(slot) => slot,
+            None => {
+                self.next_slot += 1;
+                self.next_slot - 1
+            }
+        };
+
+        self.seek_slot(slot)?;
+        self.file.write_all(&[Metadata::Start as u8])?;
+        self.file.write_all(bytemuck::bytes_of(obj))?;
+        Ok(slot)
+    }
+
+    /// Returns object stored in specified slot
+    pub fn read(&mut self, slot: u64) -> Result<T, Error> {
+        if slot >= self.slots()? {
+            return Err(Error::NotExistant);
+        }
+        READ_BLOCKS_COUNT.fetch_add(1, Ordering::SeqCst);
+
+        let mut metadata = [0];
+        self.seek_slot(slot)?;
+        self.file.read_exact(&mut metadata)?;
+        if metadata[0] != Metadata::Start as u8 {
+            return Err(Error::EmptyBlock { block: slot });
+        }
+
+        // Reads the bytes straight into the object
+        let mut obj = T::zeroed();
+        self.file.read_exact(bytemuck::bytes_of_mut(&mut obj))?;
+        Ok(obj)
+    }
+
+    /// Marks slot as empty, returns removed object
+    pub fn remove(&mut self, slot: u64) -> Result<T, Error> {
+        let obj = self.read(slot)?;
+        self.seek_slot(slot)?;
+        self.file.write_all(&[Metadata::Empty as u8])?;
+        self.empty_slots.push(slot);
+        Ok(obj)
+    }
+
+    /// Returns all objects that match the filter, in slot order
+    pub fn filter(&mut self, mut filter: impl FnMut(&T) -> bool) -> Result<Vec<T>, Error> {
+        let mut vec = vec![];
+        for slot in 0..self.next_slot {
+            match self.read(slot) {
+                Ok(obj) if filter(&obj) => vec.push(obj),
+                Ok(_) | Err(Error::EmptyBlock { .. }) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(vec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytemuck::Zeroable;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+    struct Record {
+        id: u64,
+        score: f64,
+        flags: [u8; 8],
+    }
+
+    fn record(id: u64) -> Record {
+        Record {
+            id,
+            score: id as f64 / 3.,
+            flags: [id as u8; 8],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let filename = "pod_round_trip.test";
+        std::fs::File::create(filename).unwrap();
+        let mut cbd: PodCabide<Record> = PodCabide::new(filename, Some(5)).unwrap();
+        assert_eq!(cbd.slots().unwrap(), 5);
+
+        for id in 0..20 {
+            assert_eq!(cbd.write(&record(id)).unwrap(), id);
+        }
+        cbd.remove(3).unwrap();
+        cbd.remove(7).unwrap();
+
+        // this drops the last cabide, therefore closes the file
+        cbd = PodCabide::new(filename, None).unwrap();
+        for id in (0..20).filter(|id| *id != 3 && *id != 7) {
+            assert_eq!(cbd.read(id).unwrap(), record(id));
+        }
+        assert!(matches!(cbd.read(3), Err(Error::EmptyBlock { block: 3 })));
+        assert!(matches!(cbd.read(20), Err(Error::NotExistant)));
+
+        assert_eq!(cbd.write(&record(30)).unwrap(), 7);
+        assert_eq!(cbd.write(&record(31)).unwrap(), 3);
+        assert_eq!(cbd.write(&record(32)).unwrap(), 20);
+        assert_eq!(cbd.filter(|record| record.id >= 30).unwrap().len(), 3);
+        std::fs::remove_file(filename).unwrap();
+    }
+}
@@ -1,6 +1,22 @@
-/// Each block has a END_BYTE to identify where the optional padding starts
+use crate::Error;
+
+/// Each block has a END_BYTE right after its content, the last block of an object may be padded after it
 pub const END_BYTE: u8 = 8;
 
+/// Fills the end of the last block of an object, after its END_BYTE
+///
+/// Distinct from `Metadata::Empty` and END_BYTE so padding is never mistaken for either, the content's
+/// length is stored before it (see `LENGTH_SIZE`), so the padding is skipped without looking at it
+pub const PAD_BYTE: u8 = 0xFF;
+
+/// Ends the last block of an extent of a fragmented object, right after the block where its next extent starts
+///
+/// Other blocks end in END_BYTE, PAD_BYTE or `Metadata::Empty`, so it's never mistaken for them
+pub const LINK_BYTE: u8 = 9;
+
+/// Size of the block number that precedes a LINK_BYTE, little endian like every integer
+pub const LINK_SIZE: u64 = 8;
+
 /// Size of binary block that database deals with
 ///
 /// Smaller blocks mean more metadata per object, since each block needs 2 bytes of metadata, making objects need more blocks
@@ -11,12 +27,37 @@ pub const BLOCK_SIZE: u64 = 30;
 /// Space available in each block to hold content (currently there are 2 bytes of metadata per block)
 pub const CONTENT_SIZE: u64 = BLOCK_SIZE - 2;
 
+/// Smallest block size allowed, a block needs its `Metadata`, its END_BYTE and at least one byte of content
+pub const MIN_BLOCK_SIZE: u64 = 3;
+
+/// Size of the length that starts the content of every object written with `Metadata::SizedStart`
+///
+/// It doesn't count itself, content ends after that many bytes, no matter the bytes that follow it
+pub const LENGTH_SIZE: u64 = 8;
+
+/// Size of the sequence number that prefixes the content of every object, if sequence numbers are enabled
+///
+/// Like every integer stored by this crate it's little endian, no matter the platform
+pub const SEQUENCE_SIZE: u64 = 8;
+
+/// Size of the stable id that follows the sequence number, if stable ids are enabled
+pub const ID_SIZE: u64 = 8;
+
+/// Size of the CRC32 that follows the codec flag, if checksums are enabled
+pub const CHECKSUM_SIZE: u64 = 4;
+
 /// Block's starting byte, determines how to interpret blcok
 #[derive(PartialEq, Copy, Clone)]
 pub enum Metadata {
     Empty = 0,
+    /// First block of an object written before its length was stored, its content ends at the last END_BYTE
+    /// of its last block
     Start,
     Continuation,
+    /// First block of an extent of a fragmented object (other than the first extent), only reached by a link
+    Fragment,
+    /// First block of an object, its content starts with its length (see `LENGTH_SIZE`)
+    SizedStart,
 }
 
 impl Metadata {
@@ -24,4 +65,41 @@ impl Metadata {
     pub fn as_char(self) -> char {
         (self as u8).into()
     }
+
+    /// Returns if the metadata byte starts an object, with or without its length
+    #[inline]
+    pub fn is_start(metadata: u8) -> bool {
+        metadata == Metadata::Start as u8 || metadata == Metadata::SizedStart as u8
+    }
+}
+
+/// Returns `Error::InvalidBlockSize` if blocks of specified size can't hold any content
+#[inline]
+pub fn check_block_size(block_size: u64) -> Result<(), Error> {
+    if block_size < MIN_BLOCK_SIZE {
+        return Err(Error::InvalidBlockSize { block_size });
+    }
+    Ok(())
+}
+
+/// Panics (in debug builds) if the block layout invariants don't hold for specified block size
+///
+/// Meant to be called from tests, to catch a custom block size that leaves no room for the content
+///
+/// ```rust
+/// cabide::protocol::debug_assert_layout(64);
+/// ```
+#[inline]
+pub fn debug_assert_layout(block_size: u64) {
+    // Files are extended with zeroes, so empty blocks must be all zeroes
+    // So we assert it at compile time
+    const _METADATA_EMPTY_MUST_BE_ZERO: u8 = 0 - (Metadata::Empty as u8);
+
+    debug_assert_eq!(CONTENT_SIZE, BLOCK_SIZE - 2);
+    debug_assert!(
+        check_block_size(block_size).is_ok(),
+        "Block size {} has no room for content, at least {} bytes are needed",
+        block_size,
+        MIN_BLOCK_SIZE
+    );
 }
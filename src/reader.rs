@@ -0,0 +1,296 @@
+use crate::protocol::{Metadata, END_BYTE, LENGTH_SIZE, LINK_BYTE, LINK_SIZE};
+use crate::{Cabide, Error, READ_BLOCKS_COUNT};
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::Ordering;
+
+/// Streams the content of an object from its blocks, only one block is kept in memory at a time
+///
+/// Marks every read block as empty if the object is being removed
+pub(crate) struct BlockReader<'a, T, const B: usize> {
+    /// Database the object is read from
+    cabide: &'a mut Cabide<T, B>,
+    /// Starting block of the object
+    start: u64,
+    /// Next block to be read, `None` after the last one of the object was read
+    next: Option<u64>,
+    /// Content of the current block
+    chunk: Vec<u8>,
+    /// Bytes of the current block already read
+    position: usize,
+    /// Number of blocks read so far
+    blocks: u64,
+    /// Bytes of the object read so far
+    read: u64,
+    /// If read blocks should be marked as empty
+    remove: bool,
+    /// Runs of blocks marked as empty, `(starting block, blocks)`, cached once the object is fully removed
+    freed: Vec<(u64, u64)>,
+    /// If a link to another extent was followed, so the object isn't continuous
+    pub(crate) fragmented: bool,
+    /// If the content is prefixed by its length, objects written before it was stored end at the last END_BYTE
+    sized: bool,
+    /// Length prefix and how many of its bytes were read so far, tiny blocks may split it
+    length_prefix: ([u8; LENGTH_SIZE as usize], usize),
+    /// Length of the content, once its prefix was fully read
+    length: Option<u64>,
+    /// Bytes of content loaded so far, not counting the length prefix
+    loaded: u64,
+    /// Error that interrupted the reading, the deserializer only gets an opaque io error
+    error: Option<Error>,
+}
+
+impl<'a, T, const B: usize> BlockReader<'a, T, B> {
+    /// Starts reading the object at specified block
+    pub(crate) fn new(
+        cabide: &'a mut Cabide<T, B>,
+        start: u64,
+        remove: bool,
+    ) -> Result<Self, Error> {
+        let sized = match cabide.read_metadata(start)? {
+            Some(metadata) if metadata == Metadata::SizedStart as u8 => true,
+            Some(metadata) if metadata == Metadata::Start as u8 => false,
+            Some(metadata) if metadata == Metadata::Empty as u8 => {
+                return Err(Error::EmptyBlock { block: start })
+            }
+            Some(metadata) => {
+                debug_assert!(
+                    metadata == Metadata::Continuation as u8
+                        || metadata == Metadata::Fragment as u8
+                );
+                return Err(Error::ContinuationBlock { block: start });
+            }
+            // Prefilled blocks are empty, but past them there are no blocks at all
+            None => return Err(cabide.out_of_bounds(start)),
+        };
+        if remove {
+            cabide.uncache(start);
+        }
+
+        let chunk = Vec::with_capacity(Cabide::<T, B>::BLOCK_SIZE as usize);
+        Ok(Self {
+            cabide,
+            start,
+            next: Some(start),
+            chunk,
+            position: 0,
+            blocks: 0,
+            read: 0,
+            remove,
+            freed: vec![],
+            fragmented: false,
+            sized,
+            length_prefix: ([0; LENGTH_SIZE as usize], 0),
+            length: None,
+            loaded: 0,
+            error: None,
+        })
+    }
+
+    /// Loads the content of the next block of the object, returns false if there are no more blocks
+    fn next_chunk(&mut self) -> Result<bool, Error> {
+        let block = match self.next {
+            Some(block) => block,
+            None => return Ok(false),
+        };
+        if self.blocks >= self.cabide.object_blocks_limit() {
+            return Err(Error::ObjectTooLong { block: self.start });
+        }
+        READ_BLOCKS_COUNT.fetch_add(1, Ordering::SeqCst);
+        self.blocks += 1;
+
+        let cabide = &mut *self.cabide;
+        if self.remove {
+            cabide.seek_block(block)?;
+            cabide.file.write_all(&[Metadata::Empty as u8])?;
+            match self.freed.last_mut() {
+                Some((start, blocks)) if *start + *blocks == block => *blocks += 1,
+                _ => self.freed.push((block, 1)),
+            }
+        } else {
+            // Metadata was already read, so the cursor is usually right after it
+            let start = cabide.block_start(block)?;
+            cabide.file.seek(SeekFrom::Start(start + 1))?;
+        }
+
+        // We read up to the last byte, since full blocks have their END_BYTE there
+        self.chunk.clear();
+        self.position = 0;
+        Read::by_ref(&mut cabide.file)
+            .take(Cabide::<T, B>::BLOCK_SIZE - 1)
+            .read_to_end(&mut self.chunk)?;
+
+        if self.chunk.last() == Some(&LINK_BYTE) {
+            // Last block of an extent of a fragmented object ends with the block where the next one starts
+            self.chunk.pop();
+            let link = self.chunk.len().checked_sub(LINK_SIZE as usize);
+            let link = link.ok_or(Error::CorruptedBlock)?;
+            let next = <[u8; LINK_SIZE as usize]>::try_from(&self.chunk[link..])
+                .map_err(|_| Error::CorruptedBlock)?;
+            let next = u64::from_le_bytes(next);
+            self.chunk.truncate(link);
+
+            if cabide.read_metadata(next)? != Some(Metadata::Fragment as u8) {
+                return Err(Error::CorruptedBlock);
+            }
+            self.next = Some(next);
+            self.fragmented = true;
+        } else if cabide.read_metadata(block + 1)? == Some(Metadata::Continuation as u8) {
+            // Block is not the last one, so its last byte is the END_BYTE
+            self.chunk.pop();
+            self.next = Some(block + 1);
+        } else {
+            self.next = None;
+            // Objects without a length have a END_BYTE right after the content of their last block, followed
+            // by the (optional) padding, which never contains END_BYTE (older files are padded with
+            // `Metadata::Empty` instead of PAD_BYTE)
+            if !self.sized {
+                while self.chunk.last().is_some_and(|byte| *byte != END_BYTE) {
+                    self.chunk.pop();
+                }
+                self.chunk.pop();
+            }
+        }
+
+        if self.sized {
+            self.split_length()?;
+        }
+        Ok(true)
+    }
+
+    /// Takes the length prefix out of the current block, and the END_BYTE and padding out of the last one
+    ///
+    /// Objects longer than the maximum object size fail as soon as their length is read
+    fn split_length(&mut self) -> Result<(), Error> {
+        if self.length.is_none() {
+            let (prefix, read) = &mut self.length_prefix;
+            self.position = (prefix.len() - *read).min(self.chunk.len());
+            prefix[*read..*read + self.position].copy_from_slice(&self.chunk[..self.position]);
+            *read += self.position;
+            if *read == prefix.len() {
+                let (length, max) = (u64::from_le_bytes(*prefix), self.cabide.max_object_size);
+                if length > max {
+                    return Err(Error::ObjectTooLarge {
+                        claimed: Some(length),
+                        max,
+                    });
+                }
+                self.length = Some(length);
+            }
+        }
+
+        let content = (self.chunk.len() - self.position) as u64;
+        if self.next.is_some() {
+            self.loaded += content;
+            return Ok(());
+        }
+
+        // Content of the last block is followed by its END_BYTE, whatever its bytes are
+        let end = self
+            .length
+            .and_then(|length| length.checked_sub(self.loaded))
+            .filter(|left| *left < content)
+            .map(|left| self.position + left as usize)
+            .filter(|end| self.chunk[*end] == END_BYTE)
+            .ok_or(Error::CorruptedBlock)?;
+        self.chunk.truncate(end);
+        self.loaded += (end - self.position) as u64;
+        Ok(())
+    }
+
+    /// Reads the rest of the object if it's being removed, caching its blocks as empty
+    ///
+    /// Returns the error that interrupted the reading, if any, instead of `result`
+    #[inline]
+    pub(crate) fn finish<U>(self, result: Result<U, Error>) -> Result<U, Error> {
+        self.finish_counted(result).map(|(value, _)| value)
+    }
+
+    /// Same as `finish`, but also returns the number of blocks read
+    pub(crate) fn finish_counted<U>(mut self, result: Result<U, Error>) -> Result<(U, u64), Error> {
+        if self.remove {
+            while self.next_chunk()? {}
+
+            for (start, blocks) in std::mem::take(&mut self.freed) {
+                self.cabide.free_run(start, blocks);
+            }
+        }
+
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => result.map(|value| (value, self.blocks)),
+        }
+    }
+}
+
+impl<T, const B: usize> Read for BlockReader<'_, T, B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            let max = self.cabide.max_object_size;
+            if self.read > max {
+                self.error = Some(Error::ObjectTooLarge {
+                    claimed: Some(self.read),
+                    max,
+                });
+                return Err(io::Error::other("object is too large"));
+            }
+
+            match self.next_chunk() {
+                Ok(true) => {}
+                Ok(false) => return Ok(0),
+                Err(err) => {
+                    self.error = Some(err);
+                    return Err(io::Error::other("unable to read block"));
+                }
+            }
+        }
+
+        let length = buf.len().min(self.chunk.len() - self.position);
+        buf[..length].copy_from_slice(&self.chunk[self.position..self.position + length]);
+        self.position += length;
+        self.read += length as u64;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::PAD_BYTE;
+    use bincode::serialize;
+
+    #[test]
+    fn streams_large_object() {
+        std::fs::File::create("reader_large.test").unwrap();
+        let mut cbd: Cabide<Vec<u8>> = Cabide::new("reader_large.test", None).unwrap();
+
+        // Contents full of framing bytes, spanning thousands of blocks
+        let obj: Vec<u8> = (0..100_000)
+            .map(|i| [END_BYTE, PAD_BYTE, Metadata::Empty as u8, i as u8][i % 4])
+            .collect();
+        cbd.write(&vec![PAD_BYTE; 40]).unwrap();
+        let block = cbd.write(&obj).unwrap();
+        cbd.write(&vec![END_BYTE]).unwrap();
+
+        let (mut content, mut buf) = (Vec::<u8>::new(), [0; 7]);
+        let mut reader = BlockReader::new(&mut cbd, block, false).unwrap();
+        loop {
+            let read = reader.read(&mut buf).unwrap();
+            if read == 0 {
+                break;
+            }
+            content.extend(&buf[..read]);
+            // Never holds more than a block in memory
+            assert!(reader.chunk.capacity() <= Cabide::<Vec<u8>>::BLOCK_SIZE as usize);
+        }
+        reader.finish(Ok(())).unwrap();
+        assert_eq!(content, serialize(&obj).unwrap());
+
+        assert_eq!(cbd.read(block).unwrap(), obj);
+        assert_eq!(cbd.remove(block).unwrap(), obj);
+        let blocks = cbd.needed_blocks(&content) as usize;
+        assert_eq!(cbd.empty_blocks.get(&blocks), Some(&vec![block]));
+        assert_eq!(cbd.read(block + blocks as u64).unwrap(), vec![END_BYTE]);
+        std::fs::remove_file("reader_large.test").unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{Cabide, CabideBuilder, Error};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Database of an enum, each object is prefixed by its variant index so variants can be told apart
+/// without deserializing them
+///
+/// The tag is the variant's index in the enum declaration (starting at 0), so enums must have at most
+/// 256 variants and their order must not change
+///
+/// ```rust
+/// use serde::{Serialize, Deserialize};
+/// use cabide::TaggedCabide;
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// enum Event {
+///     Login { user: String },
+///     Purchase { user: String, cents: u64 },
+/// }
+///
+/// # fn main() -> Result<(), cabide::Error> {
+/// # std::fs::File::create("test24.file")?;
+/// let mut cbd: TaggedCabide<Event> = TaggedCabide::new("test24.file", None)?;
+///
+/// cbd.write(&Event::Login { user: "alice".to_owned() })?;
+/// cbd.write(&Event::Purchase { user: "alice".to_owned(), cents: 100 })?;
+///
+/// assert_eq!(
+///     cbd.filter_variant(1),
+///     vec![Event::Purchase { user: "alice".to_owned(), cents: 100 }]
+/// );
+/// # std::fs::remove_file("test24.file")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TaggedCabide<E> {
+    cabide: Cabide<E>,
+}
+
+impl<E> TaggedCabide<E> {
+    /// Binds database to specified file, creating it if non existent, see [`Cabide::new`]
+    pub fn new<P>(filename: P, blocks: Option<u64>) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let cabide = CabideBuilder {
+            prefill: blocks,
+            tagged: true,
+            ..CabideBuilder::new()
+        }
+        .open(filename)?;
+        Ok(Self { cabide })
+    }
+
+    /// Returns the variant index of the object at specified block, without deserializing it
+    #[inline]
+    pub fn tag(&mut self, block: u64) -> Result<u8, Error> {
+        self.cabide.read_tag(block)
+    }
+
+    /// Returns the starting block and variant index of every object, in block order, only the first block
+    /// of each object is read and nothing is deserialized
+    ///
+    /// ```rust
+    /// use serde::{Serialize, Deserialize};
+    /// use cabide::TaggedCabide;
+    ///
+    /// #[derive(Serialize, Deserialize)]
+    /// enum Shape {
+    ///     Circle(u64),
+    ///     Polygon(Vec<(u64, u64)>),
+    /// }
+    ///
+    /// # fn main() -> Result<(), cabide::Error> {
+    /// # std::fs::File::create("test37.file")?;
+    /// let mut cbd: TaggedCabide<Shape> = TaggedCabide::new("test37.file", None)?;
+    ///
+    /// cbd.write(&Shape::Polygon(vec![(0, 0); 10]))?;
+    /// let circle = cbd.write(&Shape::Circle(3))?;
+    /// assert_eq!(cbd.variant_index()?, vec![(0, 1), (circle, 0)]);
+    /// # std::fs::remove_file("test37.file")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn variant_index(&mut self) -> Result<Vec<(u64, u8)>, Error> {
+        let mut vec = vec![];
+        for block in self.cabide.object_blocks()? {
+            vec.push((block, self.tag(block)?));
+        }
+        Ok(vec)
+    }
+
+    /// Returns number of blocks written to file (some may be empty)
+    #[inline]
+    pub fn blocks(&self) -> Result<u64, Error> {
+        self.cabide.blocks()
+    }
+}
+
+impl<E: Serialize> TaggedCabide<E> {
+    /// Writes object to database, tagging it with its variant, see [`Cabide::write`]
+    #[inline]
+    pub fn write(&mut self, obj: &E) -> Result<u64, Error> {
+        self.cabide.write(obj)
+    }
+}
+
+impl<E> TaggedCabide<E>
+where
+    for<'de> E: Deserialize<'de>,
+{
+    /// Returns object deserialized from specified starting block, see [`Cabide::read`]
+    #[inline]
+    pub fn read(&mut self, block: u64) -> Result<E, Error> {
+        self.cabide.read(block)
+    }
+
+    /// Mark object blocks as empty, returns removed object, see [`Cabide::remove`]
+    #[inline]
+    pub fn remove(&mut self, block: u64) -> Result<E, Error> {
+        self.cabide.remove(block)
+    }
+
+    /// Returns every object of the variant with specified index, other variants are never deserialized
+    pub fn filter_variant(&mut self, tag: u8) -> Vec<E> {
+        let mut vec = vec![];
+        for block in self.cabide.object_blocks().unwrap_or_default() {
+            match self.tag(block) {
+                Ok(found) if found == tag => {}
+                // We ignore errors, just like `Cabide::filter`
+                _ => continue,
+            }
+
+            if let Ok(obj) = self.read(block) {
+                vec.push(obj);
+            }
+        }
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Student {
+        name: String,
+        dre: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Record {
+        Student(Student),
+        Class { code: String, students: Vec<u64> },
+        Empty,
+    }
+
+    #[test]
+    fn filter_variant() {
+        std::fs::File::create("tagged_filter.test").unwrap();
+        let mut cbd: TaggedCabide<Record> = TaggedCabide::new("tagged_filter.test", None).unwrap();
+
+        let (mut students, mut classes) = (vec![], vec![]);
+        for i in 0..20 {
+            if i % 3 == 0 {
+                let class = Record::Class {
+                    code: format!("MAB{}", i),
+                    students: (0..i).collect(),
+                };
+                cbd.write(&class).unwrap();
+                classes.push(class);
+            } else {
+                let student = Record::Student(Student {
+                    name: format!("Student {}", i),
+                    dre: i,
+                });
+                cbd.write(&student).unwrap();
+                students.push(student);
+            }
+        }
+        let empty = cbd.write(&Record::Empty).unwrap();
+
+        // this drops the last cabide, therefore closes the file
+        cbd = TaggedCabide::new("tagged_filter.test", None).unwrap();
+        assert_eq!(cbd.filter_variant(0), students);
+        assert_eq!(cbd.filter_variant(1), classes);
+        assert_eq!(cbd.tag(empty).unwrap(), 2);
+        assert_eq!(cbd.remove(empty).unwrap(), Record::Empty);
+        assert!(cbd.filter_variant(2).is_empty());
+        std::fs::remove_file("tagged_filter.test").unwrap();
+    }
+
+    #[test]
+    fn variant_index() {
+        std::fs::File::create("tagged_index.test").unwrap();
+        let mut cbd: TaggedCabide<Record> = TaggedCabide::new("tagged_index.test", None).unwrap();
+
+        let mut written = vec![];
+        for i in 0..30 {
+            let record = match i % 3 {
+                0 => Record::Student(Student {
+                    name: format!("Student {}", i),
+                    dre: i,
+                }),
+                1 => Record::Class {
+                    code: format!("MAB{}", i),
+                    students: (0..i * 10).collect(),
+                },
+                _ => Record::Empty,
+            };
+            written.push((cbd.write(&record).unwrap(), (i % 3) as u8));
+        }
+        for (block, _) in written.drain(10..13) {
+            cbd.remove(block).unwrap();
+        }
+
+        let index = cbd.variant_index().unwrap();
+        assert_eq!(index, written);
+        for (block, tag) in index {
+            let variant = match cbd.read(block).unwrap() {
+                Record::Student(_) => 0,
+                Record::Class { .. } => 1,
+                Record::Empty => 2,
+            };
+            assert_eq!(variant, tag);
+        }
+        std::fs::remove_file("tagged_index.test").unwrap();
+    }
+}
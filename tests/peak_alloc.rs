@@ -0,0 +1,82 @@
+use cabide::Cabide;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Keeps track of the bytes currently allocated and the most that were allocated at once
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn allocated(bytes: usize) {
+    let current = CURRENT.fetch_add(bytes, Ordering::SeqCst) + bytes;
+    PEAK.fetch_max(current, Ordering::SeqCst);
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+            allocated(new_size);
+        }
+        new
+    }
+}
+
+/// Returns the most bytes allocated at once by `f`, on top of what was already allocated
+fn peak_allocation<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let result = f();
+    (result, PEAK.load(Ordering::SeqCst) - before)
+}
+
+// Only test of this binary, so nothing else allocates while it's measured
+#[test]
+fn streamed_read_keeps_one_copy() {
+    let filename = "cabide_peak_alloc.test";
+    std::fs::File::create(filename).unwrap();
+    let mut cbd: Cabide<String> = Cabide::new(filename, None).unwrap();
+
+    let size = 4 << 20;
+    let block = cbd.write(&"a".repeat(size)).unwrap();
+
+    // The string itself is the only big allocation, its content isn't collected before deserializing
+    let (obj, peak) = peak_allocation(|| cbd.read(block).unwrap());
+    assert_eq!(obj.len(), size);
+    assert!(obj.bytes().all(|byte| byte == b'a'));
+    assert!(
+        peak < size + size / 8,
+        "{} bytes for a {} bytes object",
+        peak,
+        size
+    );
+    drop(obj);
+
+    let (obj, peak) = peak_allocation(|| cbd.remove(block).unwrap());
+    assert_eq!(obj.len(), size);
+    assert!(
+        peak < size + size / 8,
+        "{} bytes for a {} bytes object",
+        peak,
+        size
+    );
+    std::fs::remove_file(filename).unwrap();
+}
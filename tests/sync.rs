@@ -0,0 +1,47 @@
+use cabide::{Cabide, SyncMode};
+use std::{env, process::Command};
+
+/// Set in the child process, to the file it writes to before being killed
+const CHILD_FILE: &str = "CABIDE_SYNC_CHILD_FILE";
+
+/// Writes, removes and updates objects, then dies without dropping the database
+///
+/// The OS keeps what was written even if it wasn't synced, so this only shows nothing waits for the drop,
+/// the syncs themselves are counted by the builder's tests
+fn child(filename: &str) -> ! {
+    let mut cbd: Cabide<String> = Cabide::builder()
+        .sync_mode(SyncMode::OnEveryWrite)
+        .open(filename)
+        .unwrap();
+    for i in 0..10 {
+        assert_eq!(cbd.write(&i.to_string()).unwrap(), i);
+    }
+    cbd.remove(3).unwrap();
+    assert_eq!(cbd.update(5, &"five".to_owned()).unwrap(), 5);
+    std::process::abort()
+}
+
+#[test]
+fn on_every_write_survives_kill() {
+    if let Ok(filename) = env::var(CHILD_FILE) {
+        child(&filename);
+    }
+
+    let filename = "cabide_sync_kill.test";
+    let _ = std::fs::remove_file(filename);
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["on_every_write_survives_kill", "--exact"])
+        .env(CHILD_FILE, filename)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let mut cbd: Cabide<String> = Cabide::new(filename, None).unwrap();
+    let mut expected: Vec<(u64, String)> = (0..10).map(|i| (i, i.to_string())).collect();
+    expected.remove(3);
+    expected[4].1 = "five".to_owned();
+    assert_eq!(cbd.filter_with_blocks(|_| true), expected);
+    assert_eq!(cbd.write(&"reused".to_owned()).unwrap(), 3);
+    drop(cbd);
+    std::fs::remove_file(filename).unwrap();
+}